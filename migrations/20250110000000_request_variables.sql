@@ -0,0 +1,2 @@
+-- Per-recipient template variables (JSON object, rendered at send time)
+ALTER TABLE email_requests ADD COLUMN variables TEXT DEFAULT NULL;
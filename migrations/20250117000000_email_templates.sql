@@ -0,0 +1,30 @@
+-- Stored email templates with immutable versions
+
+CREATE TABLE IF NOT EXISTS email_templates (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name VARCHAR(255) NOT NULL,
+    latest_version INTEGER NOT NULL DEFAULT 1,
+    created_at DATETIME NOT NULL DEFAULT (datetime('now')),
+    updated_at DATETIME NOT NULL DEFAULT (datetime('now')),
+    deleted_at DATETIME
+);
+
+-- Template names are unique among active (non-deleted) templates
+CREATE UNIQUE INDEX IF NOT EXISTS idx_templates_name_active ON email_templates(name) WHERE deleted_at IS NULL;
+
+CREATE TABLE IF NOT EXISTS email_template_versions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    template_id INTEGER NOT NULL,
+    version INTEGER NOT NULL,
+    subject VARCHAR(255) NOT NULL,
+    content TEXT NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT (datetime('now')),
+    FOREIGN KEY (template_id) REFERENCES email_templates(id),
+    UNIQUE (template_id, version)
+);
+
+-- Contents remember which template version they were rendered from
+ALTER TABLE email_contents ADD COLUMN template_id INTEGER DEFAULT NULL REFERENCES email_templates(id);
+ALTER TABLE email_contents ADD COLUMN template_version INTEGER DEFAULT NULL;
+
+CREATE INDEX IF NOT EXISTS idx_contents_template ON email_contents(template_id, template_version);
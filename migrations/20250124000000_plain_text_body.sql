@@ -0,0 +1,3 @@
+-- Optional plain-text alternative body (derived from HTML at send time when NULL)
+ALTER TABLE email_contents ADD COLUMN text TEXT DEFAULT NULL;
+ALTER TABLE email_template_versions ADD COLUMN text TEXT DEFAULT NULL;
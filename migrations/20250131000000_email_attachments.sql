@@ -0,0 +1,23 @@
+-- Attachment payloads, deduplicated by SHA-256 of their bytes
+CREATE TABLE IF NOT EXISTS email_attachments (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    sha256 CHAR(64) NOT NULL UNIQUE,
+    size INTEGER NOT NULL,
+    data BLOB NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT (datetime('now'))
+);
+
+-- Attachments of each content (file name, MIME type and inline Content-ID per usage)
+CREATE TABLE IF NOT EXISTS email_content_attachments (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    content_id INTEGER NOT NULL,
+    attachment_id INTEGER NOT NULL,
+    filename VARCHAR(255) NOT NULL,
+    content_type VARCHAR(255) NOT NULL,
+    cid VARCHAR(255) DEFAULT NULL,
+    position INTEGER NOT NULL DEFAULT 0,
+    FOREIGN KEY (content_id) REFERENCES email_contents(id),
+    FOREIGN KEY (attachment_id) REFERENCES email_attachments(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_content_attachments_content_id ON email_content_attachments(content_id);
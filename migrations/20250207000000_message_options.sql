@@ -0,0 +1,2 @@
+-- Message-level delivery options shared by all recipients of a content (JSON: cc, bcc, reply_to)
+ALTER TABLE email_contents ADD COLUMN options TEXT DEFAULT NULL;
@@ -0,0 +1,9 @@
+-- Recipients that must not receive emails (hard bounces, complaints, unsubscribes, manual entries)
+CREATE TABLE IF NOT EXISTS suppressions (
+    email VARCHAR(255) PRIMARY KEY,
+    reason VARCHAR(32) NOT NULL,
+    request_id INTEGER DEFAULT NULL,
+    created_at DATETIME NOT NULL DEFAULT (datetime('now'))
+);
+
+CREATE INDEX IF NOT EXISTS idx_suppressions_created_at ON suppressions(created_at);
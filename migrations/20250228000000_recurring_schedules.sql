@@ -0,0 +1,19 @@
+-- Recurring schedules materialized into email_requests at each cron occurrence
+CREATE TABLE IF NOT EXISTS recurring_schedules (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name VARCHAR(255) NOT NULL,
+    cron VARCHAR(255) NOT NULL,
+    timezone VARCHAR(64) NOT NULL,
+    topic_id VARCHAR(255) NOT NULL,
+    content_id INTEGER NOT NULL,
+    recipients TEXT NOT NULL,
+    paused BOOLEAN NOT NULL DEFAULT 0,
+    next_run_at DATETIME DEFAULT NULL,
+    last_run_at DATETIME DEFAULT NULL,
+    created_at DATETIME NOT NULL DEFAULT (datetime('now')),
+    updated_at DATETIME NOT NULL DEFAULT (datetime('now')),
+    FOREIGN KEY (content_id) REFERENCES email_contents(id)
+);
+
+-- Scheduler query optimization (due, active schedules)
+CREATE INDEX IF NOT EXISTS idx_recurring_schedules_due ON recurring_schedules(paused, next_run_at);
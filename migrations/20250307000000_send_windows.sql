@@ -0,0 +1,9 @@
+-- Recipient-local send windows: requests are only claimed inside their window
+ALTER TABLE email_requests ADD COLUMN send_window VARCHAR(11) DEFAULT NULL;
+ALTER TABLE email_requests ADD COLUMN timezone VARCHAR(64) DEFAULT NULL;
+ALTER TABLE email_requests ADD COLUMN send_window_ends_at DATETIME DEFAULT NULL;
+
+-- Scheduler query optimization: queued requests whose window has closed
+CREATE INDEX IF NOT EXISTS idx_requests_status_window_ends
+    ON email_requests(status, send_window_ends_at)
+    WHERE send_window_ends_at IS NOT NULL;
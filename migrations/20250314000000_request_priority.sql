@@ -0,0 +1,6 @@
+-- Priority lane of each request (0: transactional, 1: bulk)
+ALTER TABLE email_requests ADD COLUMN priority INTEGER NOT NULL DEFAULT 1;
+
+-- Scheduler claims transactional requests before bulk ones
+CREATE INDEX IF NOT EXISTS idx_requests_status_priority_scheduled
+    ON email_requests(status, priority, scheduled_at ASC);
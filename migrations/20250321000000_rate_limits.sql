@@ -0,0 +1,8 @@
+-- Per-topic and per-sender send rate caps (on top of MAX_SEND_PER_SECOND)
+CREATE TABLE IF NOT EXISTS rate_limits (
+    scope VARCHAR(16) NOT NULL,
+    key VARCHAR(255) NOT NULL,
+    max_per_second INTEGER NOT NULL,
+    updated_at DATETIME NOT NULL DEFAULT (datetime('now')),
+    PRIMARY KEY (scope, key)
+);
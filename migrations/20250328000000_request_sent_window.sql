@@ -0,0 +1,3 @@
+-- Rolling 24-hour count of sent requests for the daily quota guard
+CREATE INDEX IF NOT EXISTS idx_requests_status_updated
+    ON email_requests(status, updated_at);
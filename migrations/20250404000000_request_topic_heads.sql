@@ -0,0 +1,4 @@
+-- Scheduler claim: walks the topics of each priority lane and takes the
+-- earliest due requests of each topic without sorting the whole backlog
+CREATE INDEX IF NOT EXISTS idx_requests_status_priority_topic_scheduled
+    ON email_requests(status, priority, topic_id, scheduled_at ASC);
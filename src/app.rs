@@ -1,15 +1,17 @@
 //! HTTP routing configuration
 
 use axum::{
+    extract::DefaultBodyLimit,
     middleware::from_fn,
-    routing::{delete, get, post},
+    routing::{delete, get, post, put},
     Router,
 };
 use tower_http::trace::TraceLayer;
 
-use crate::{handlers, middlewares, state};
+use crate::{constants::MAX_REQUEST_BODY_BYTES, handlers, middlewares, state};
 
 /// Creates the Axum router with all routes configured.
+#[allow(clippy::too_many_lines)]
 pub fn app(state: state::AppState) -> Router {
     let auth = from_fn(middlewares::auth_middlewares::api_key_auth);
 
@@ -20,7 +22,9 @@ pub fn app(state: state::AppState) -> Router {
         // API endpoints
         .route(
             "/v1/messages",
-            post(handlers::message_handlers::create_message).layer(auth.clone()),
+            post(handlers::message_handlers::create_message)
+                .layer(DefaultBodyLimit::max(MAX_REQUEST_BODY_BYTES))
+                .layer(auth.clone()),
         )
         .route(
             "/v1/topics/{topic_id}",
@@ -30,7 +34,95 @@ pub fn app(state: state::AppState) -> Router {
             "/v1/topics/{topic_id}",
             delete(handlers::topic_handlers::stop_topic).layer(auth.clone()),
         )
+        .route(
+            "/v1/topics/{topic_id}/pause",
+            post(handlers::topic_handlers::pause_topic).layer(auth.clone()),
+        )
+        .route(
+            "/v1/topics/{topic_id}/resume",
+            post(handlers::topic_handlers::resume_topic).layer(auth.clone()),
+        )
+        .route(
+            "/v1/templates",
+            post(handlers::template_handlers::create_template).layer(auth.clone()),
+        )
+        .route(
+            "/v1/templates",
+            get(handlers::template_handlers::list_templates).layer(auth.clone()),
+        )
+        .route(
+            "/v1/templates/{template_id}",
+            get(handlers::template_handlers::get_template).layer(auth.clone()),
+        )
+        .route(
+            "/v1/templates/{template_id}",
+            put(handlers::template_handlers::update_template).layer(auth.clone()),
+        )
+        .route(
+            "/v1/templates/{template_id}",
+            delete(handlers::template_handlers::delete_template).layer(auth.clone()),
+        )
+        .route(
+            "/v1/templates/{template_id}/versions/{version}",
+            get(handlers::template_handlers::get_template_version).layer(auth.clone()),
+        )
+        .route(
+            "/v1/suppressions",
+            get(handlers::suppression_handlers::list_suppressions).layer(auth.clone()),
+        )
+        .route(
+            "/v1/suppressions",
+            post(handlers::suppression_handlers::add_suppressions).layer(auth.clone()),
+        )
+        .route(
+            "/v1/suppressions/{email}",
+            get(handlers::suppression_handlers::get_suppression).layer(auth.clone()),
+        )
+        .route(
+            "/v1/suppressions/{email}",
+            delete(handlers::suppression_handlers::delete_suppression).layer(auth.clone()),
+        )
+        .route(
+            "/v1/schedules",
+            get(handlers::schedule_handlers::list_schedules).layer(auth.clone()),
+        )
+        .route(
+            "/v1/schedules",
+            post(handlers::schedule_handlers::create_schedule).layer(auth.clone()),
+        )
+        .route(
+            "/v1/schedules/{schedule_id}",
+            get(handlers::schedule_handlers::get_schedule).layer(auth.clone()),
+        )
+        .route(
+            "/v1/schedules/{schedule_id}",
+            delete(handlers::schedule_handlers::delete_schedule).layer(auth.clone()),
+        )
+        .route(
+            "/v1/schedules/{schedule_id}/pause",
+            post(handlers::schedule_handlers::pause_schedule).layer(auth.clone()),
+        )
+        .route(
+            "/v1/schedules/{schedule_id}/resume",
+            post(handlers::schedule_handlers::resume_schedule).layer(auth.clone()),
+        )
+        .route(
+            "/v1/rate-limits",
+            get(handlers::rate_limit_handlers::list_rate_limits).layer(auth.clone()),
+        )
+        .route(
+            "/v1/rate-limits/{scope}/{key}",
+            put(handlers::rate_limit_handlers::set_rate_limit).layer(auth.clone()),
+        )
+        .route(
+            "/v1/rate-limits/{scope}/{key}",
+            delete(handlers::rate_limit_handlers::delete_rate_limit).layer(auth.clone()),
+        )
         .route("/v1/events/open", get(handlers::event_handlers::track_open))
+        .route(
+            "/v1/events/click",
+            get(handlers::event_handlers::track_click),
+        )
         .route(
             "/v1/events/counts/sent",
             get(handlers::event_handlers::get_sent_count).layer(auth),
@@ -39,6 +131,15 @@ pub fn app(state: state::AppState) -> Router {
             "/v1/events/results",
             post(handlers::event_handlers::handle_sns_event),
         )
+        // Public unsubscribe endpoints (authenticated by the signed token)
+        .route(
+            "/v1/unsubscribe",
+            get(handlers::unsubscribe_handlers::unsubscribe_page),
+        )
+        .route(
+            "/v1/unsubscribe",
+            post(handlers::unsubscribe_handlers::unsubscribe),
+        )
         .with_state(state)
         .layer(TraceLayer::new_for_http())
 }
@@ -91,6 +91,77 @@ async fn apply_sqlite_optimizations(pool: &SqlitePool) -> Result<(), sqlx::Error
     Ok(())
 }
 
+/// Creates an in-memory database with all migrations applied (tests only).
+#[cfg(test)]
+pub async fn init_test_db() -> SqlitePool {
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .expect("in-memory database");
+    run_migrations(&pool).await.expect("migrations");
+    pool
+}
+
+/// Inserts content 1 for the requests seeded by tests (tests only).
+#[cfg(test)]
+pub async fn seed_content(db: &SqlitePool) {
+    sqlx::query("INSERT OR IGNORE INTO email_contents (id, subject, content) VALUES (1, 's', 'c')")
+        .execute(db)
+        .await
+        .expect("seed content");
+}
+
+/// Inserts a `Created` request due now, optionally already sent as
+/// `message_id` (tests only).
+#[cfg(test)]
+pub async fn seed_request(
+    db: &SqlitePool,
+    id: i32,
+    topic_id: &str,
+    email: &str,
+    message_id: Option<&str>,
+) {
+    seed_content(db).await;
+    sqlx::query(
+        "INSERT INTO email_requests (id, topic_id, content_id, email, scheduled_at, message_id)
+         VALUES (?, ?, 1, ?, datetime('now'), ?)",
+    )
+    .bind(id)
+    .bind(topic_id)
+    .bind(email)
+    .bind(message_id)
+    .execute(db)
+    .await
+    .expect("seed request");
+}
+
+/// Inserts a request in `status`, scheduled now shifted by the `SQLite`
+/// modifier `scheduled_in` (e.g. `"-1 minute"`) (tests only).
+#[cfg(test)]
+pub async fn seed_scheduled_request(
+    db: &SqlitePool,
+    id: i32,
+    topic_id: &str,
+    email: &str,
+    status: crate::models::request::EmailMessageStatus,
+    scheduled_in: &str,
+) {
+    seed_content(db).await;
+    sqlx::query(
+        "INSERT INTO email_requests (id, topic_id, content_id, email, scheduled_at, status)
+         VALUES (?, ?, 1, ?, datetime('now', ?), ?)",
+    )
+    .bind(id)
+    .bind(topic_id)
+    .bind(email)
+    .bind(scheduled_in)
+    .bind(status as i32)
+    .execute(db)
+    .await
+    .expect("seed request");
+}
+
 /// Closes the database connection pool gracefully.
 pub async fn close_db() {
     if let Some(pool) = DB_POOL.get() {
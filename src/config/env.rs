@@ -3,6 +3,8 @@
 use std::env;
 use std::sync::{LazyLock, Once};
 
+use chrono_tz::Tz;
+
 static INIT: Once = Once::new();
 
 /// Initializes the environment by loading the .env file.
@@ -34,6 +36,19 @@ pub fn get_env_parsed<T: std::str::FromStr>(key: &str, default: T) -> T {
         .unwrap_or(default)
 }
 
+/// Retrieves a comma-separated environment variable as a list.
+///
+/// Entries are trimmed and empty entries are skipped.
+#[must_use]
+pub fn get_env_list(key: &str) -> Vec<String> {
+    get_env(key, None)
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
 /// Application configuration loaded from environment variables.
 #[derive(Debug, Clone)]
 pub struct AppConfig {
@@ -44,12 +59,30 @@ pub struct AppConfig {
     // API authentication
     pub api_key: String,
 
+    // Click tracking (HMAC key for redirect links, defaults to `api_key`)
+    pub tracking_secret: String,
+
     // AWS settings
     pub aws_region: String,
     pub aws_ses_from_email: String,
+    /// Verified identities a message may use as `from` (addresses or domains).
+    pub aws_ses_allowed_from: Vec<String>,
+    /// Default configuration set for event publishing and IP pools (`None` = not set).
+    pub aws_ses_configuration_set: Option<String>,
+    /// SES API endpoint override, e.g. a local SES stand-in (`None` = AWS).
+    pub aws_ses_endpoint_url: Option<String>,
 
-    // Rate limiting
+    /// Timezone of `scheduled_at` values without an offset (IANA name).
+    pub default_timezone: Tz,
+
+    // Rate limiting (`max_send_per_second` is used until the SES quota is known)
     pub max_send_per_second: i32,
+    /// How often the SES account send quota is refreshed.
+    pub ses_quota_refresh_secs: u64,
+    /// Rolling 24-hour send limit (0 = only the SES `Max24HourSend`).
+    pub max_24_hour_send: u64,
+    /// Share of the 24-hour limit kept unused, in percent.
+    pub quota_headroom_percent: u64,
 
     // Database settings
     pub db_max_connections: u32,
@@ -70,23 +103,39 @@ impl AppConfig {
     /// Creates a new `AppConfig` from environment variables.
     #[must_use]
     pub fn from_env() -> Self {
+        let api_key = get_env(
+            "API_KEY",
+            if cfg!(test) {
+                Some("test-api-key-12345")
+            } else {
+                None
+            },
+        );
+        let tracking_secret = Some(get_env("TRACKING_SECRET", None))
+            .filter(|s| !s.is_empty())
+            .unwrap_or_else(|| api_key.clone());
+
         Self {
             server_port: get_env("SERVER_PORT", Some("8080")),
             server_url: get_env("SERVER_URL", None),
 
-            api_key: get_env(
-                "API_KEY",
-                if cfg!(test) {
-                    Some("test-api-key-12345")
-                } else {
-                    None
-                },
-            ),
+            api_key,
+            tracking_secret,
 
             aws_region: get_env("AWS_REGION", Some("ap-northeast-2")),
             aws_ses_from_email: get_env("AWS_SES_FROM_EMAIL", None),
+            aws_ses_allowed_from: get_env_list("AWS_SES_ALLOWED_FROM"),
+            aws_ses_configuration_set: Some(get_env("AWS_SES_CONFIGURATION_SET", None))
+                .filter(|s| !s.is_empty()),
+            aws_ses_endpoint_url: Some(get_env("AWS_SES_ENDPOINT_URL", None))
+                .filter(|s| !s.is_empty()),
+
+            default_timezone: get_env_parsed("DEFAULT_TIMEZONE", chrono_tz::Asia::Seoul),
 
             max_send_per_second: get_env_parsed("MAX_SEND_PER_SECOND", 24),
+            ses_quota_refresh_secs: get_env_parsed("SES_QUOTA_REFRESH_SECS", 300),
+            max_24_hour_send: get_env_parsed("MAX_24_HOUR_SEND", 0),
+            quota_headroom_percent: get_env_parsed("QUOTA_HEADROOM_PERCENT", 1),
 
             db_max_connections: get_env_parsed("DB_MAX_CONNECTIONS", 20),
             db_min_connections: get_env_parsed("DB_MIN_CONNECTIONS", 5),
@@ -159,6 +208,17 @@ mod tests {
         assert!((result - 0.5).abs() < f32::EPSILON);
     }
 
+    #[test]
+    fn test_get_env_list() {
+        assert!(get_env_list("NON_EXISTENT_LIST_VAR").is_empty());
+
+        std::env::set_var("TEST_ENV_LIST_VAR", " a@example.com, ,example.org ");
+        assert_eq!(
+            get_env_list("TEST_ENV_LIST_VAR"),
+            vec!["a@example.com", "example.org"]
+        );
+    }
+
     #[test]
     fn test_app_config_from_env() {
         let config = AppConfig::from_env();
@@ -167,6 +227,7 @@ mod tests {
         assert!(config.db_max_connections > 0);
         assert!(config.db_min_connections > 0);
         assert!(config.max_send_per_second > 0);
+        assert!(!config.tracking_secret.is_empty());
     }
 
     #[test]
@@ -4,4 +4,6 @@ mod db;
 mod env;
 
 pub use db::{close_db, init_db};
+#[cfg(test)]
+pub use db::{init_test_db, seed_content, seed_request, seed_scheduled_request};
 pub use env::APP_CONFIG;
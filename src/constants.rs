@@ -1,5 +1,18 @@
 //! Common constants used across the application
 
 /// Max records per batch INSERT (`SQLite` variable limit: 999).
-/// With 5 columns per row, 150 rows = 750 placeholders (safe margin).
-pub const BATCH_INSERT_SIZE: usize = 150;
+/// With 10 columns per row, 90 rows = 900 placeholders (safe margin).
+pub const BATCH_INSERT_SIZE: usize = 90;
+
+/// Max size of a single email accepted by SES, including attachments (10 MB).
+pub const MAX_MESSAGE_SIZE_BYTES: usize = 10 * 1024 * 1024;
+
+/// Max request body of `POST /v1/messages` (base64 attachments are ~4/3 of their size).
+pub const MAX_REQUEST_BODY_BYTES: usize = 50 * 1024 * 1024;
+
+/// How long an `Idempotency-Key` of `POST /v1/messages` is remembered (24 hours).
+pub const IDEMPOTENCY_KEY_RETENTION_HOURS: i64 = 24;
+
+/// How long an unfinished `Idempotency-Key` reservation blocks retries (5 minutes).
+/// After that it is considered abandoned (crash, failed release) and can be taken over.
+pub const IDEMPOTENCY_KEY_IN_PROGRESS_TIMEOUT_SECS: i64 = 300;
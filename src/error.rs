@@ -31,6 +31,10 @@ pub enum AppError {
     #[error("Validation error: {0}")]
     Validation(String),
 
+    /// Conflict error (409)
+    #[error("Conflict: {0}")]
+    Conflict(String),
+
     /// Internal server error (500)
     #[error("Internal server error: {0}")]
     Internal(String),
@@ -54,6 +58,7 @@ impl IntoResponse for AppError {
             Self::BadRequest(msg) | Self::Validation(msg) => (StatusCode::BAD_REQUEST, msg.clone()),
             Self::Unauthorized(msg) => (StatusCode::UNAUTHORIZED, msg.clone()),
             Self::NotFound(msg) => (StatusCode::NOT_FOUND, msg.clone()),
+            Self::Conflict(msg) => (StatusCode::CONFLICT, msg.clone()),
             Self::Internal(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg.clone()),
             Self::Database(e) => {
                 tracing::error!("Database error: {e:?}");
@@ -122,6 +127,12 @@ mod tests {
         assert_eq!(error.to_string(), "Validation error: 유효성 검사 실패");
     }
 
+    #[test]
+    fn test_app_error_conflict_display() {
+        let error = AppError::Conflict("중복".to_string());
+        assert_eq!(error.to_string(), "Conflict: 중복");
+    }
+
     #[test]
     fn test_app_error_internal_display() {
         let error = AppError::Internal("내부 오류".to_string());
@@ -176,6 +187,13 @@ mod tests {
         assert_eq!(response.status(), StatusCode::BAD_REQUEST);
     }
 
+    #[tokio::test]
+    async fn test_conflict_into_response() {
+        let error = AppError::Conflict("이미 존재함".to_string());
+        let response = error.into_response();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+    }
+
     #[tokio::test]
     async fn test_internal_into_response() {
         let error = AppError::Internal("서버 오류".to_string());
@@ -243,6 +261,7 @@ mod tests {
             AppError::Unauthorized("unauth".to_string()),
             AppError::NotFound("not found".to_string()),
             AppError::Validation("invalid".to_string()),
+            AppError::Conflict("conflict".to_string()),
             AppError::Internal("internal".to_string()),
             AppError::Email("email error".to_string()),
             AppError::ChannelClosed,
@@ -2,16 +2,25 @@
 
 use axum::{
     extract::{Json, Query, Request, State},
-    http::{header::HeaderValue, HeaderMap, StatusCode},
+    http::{
+        header::{self, HeaderValue},
+        HeaderMap, StatusCode,
+    },
     response::IntoResponse,
 };
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 use crate::{
+    config::APP_CONFIG,
     error::{AppError, AppResult},
-    models::{request::EmailRequest, result::EmailResult},
+    models::{
+        request::EmailRequest,
+        result::EmailResult,
+        suppression::{Suppression, SuppressionReason},
+    },
+    services::{sender::REQUEST_ID_TAG, tracking},
     state::AppState,
 };
 
@@ -31,6 +40,13 @@ pub struct OpenQueryParams {
     pub request_id: Option<String>,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct ClickQueryParams {
+    pub request_id: Option<String>,
+    pub url: Option<String>,
+    pub sig: Option<String>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct SentCountQueryParams {
     pub hours: Option<i32>,
@@ -57,9 +73,10 @@ enum SnsMessage {
     Other(Value),
 }
 
+/// SES notification (`notificationType`) or event publishing record (`eventType`).
 #[derive(Debug, Deserialize)]
 struct SesNotification {
-    #[serde(rename = "notificationType")]
+    #[serde(rename = "notificationType", alias = "eventType")]
     event_type: String,
     #[serde(flatten)]
     other_fields: Value,
@@ -89,6 +106,43 @@ pub async fn track_open(
     (StatusCode::OK, headers, TRACKING_PIXEL)
 }
 
+/// Records a link click and redirects (302) to the original URL.
+///
+/// Only URLs signed for the request are followed, so the endpoint cannot be
+/// used as an open redirect.
+pub async fn track_click(
+    State(state): State<AppState>,
+    Query(query): Query<ClickQueryParams>,
+) -> AppResult<impl IntoResponse> {
+    let invalid = || AppError::BadRequest("Invalid tracking link".to_string());
+
+    let request_id = query
+        .request_id
+        .as_deref()
+        .and_then(|id| id.parse::<i32>().ok())
+        .ok_or_else(invalid)?;
+    let (Some(url), Some(sig)) = (query.url, query.sig) else {
+        return Err(invalid());
+    };
+    if !tracking::verify(&APP_CONFIG.tracking_secret, request_id, &url, &sig) {
+        return Err(invalid());
+    }
+    let target = tracking::parse_target(&url).ok_or_else(invalid)?;
+    let location = HeaderValue::from_str(target.as_str()).map_err(|_| invalid())?;
+
+    let result = EmailResult {
+        id: None,
+        status: "Click".to_owned(),
+        request_id,
+        raw: Some(url),
+    };
+    if let Err(e) = result.save(&state.db_pool).await {
+        error!("Failed to save click event: {e:?}");
+    }
+
+    Ok((StatusCode::FOUND, [(header::LOCATION, location)]))
+}
+
 /// Returns the count of emails sent within the specified hours.
 pub async fn get_sent_count(
     State(state): State<AppState>,
@@ -141,7 +195,6 @@ pub async fn handle_sns_event(
     }
 }
 
-#[allow(clippy::similar_names)]
 async fn process_ses_notification(
     state: &AppState,
     message: &str,
@@ -150,38 +203,130 @@ async fn process_ses_notification(
     let notification: SesNotification = serde_json::from_str(message)
         .map_err(|_| AppError::BadRequest("Non-SES notification".to_string()))?;
 
-    let ses_msg_id = notification
+    let request_id = verified_request_id(state, &notification, sns_message_id).await?;
+
+    if let Some((reason, recipients)) = suppression_targets(&notification) {
+        for email in recipients {
+            if Suppression::add(&state.db_pool, email, reason, Some(request_id)).await? {
+                info!("Suppressed {email}: {}", reason.as_str());
+            }
+        }
+    }
+
+    let result = EmailResult {
+        id: None,
+        request_id,
+        status: notification.event_type,
+        raw: Some(message.to_owned()),
+    };
+
+    result.save(&state.db_pool).await?;
+    Ok(())
+}
+
+/// Request ID from the `request_id` message tag (set on every send).
+fn tagged_request_id(notification: &SesNotification) -> Option<i32> {
+    notification
+        .other_fields
+        .get("mail")?
+        .get("tags")?
+        .get(REQUEST_ID_TAG)?
+        .get(0)?
+        .as_str()?
+        .parse()
+        .ok()
+}
+
+/// SES message ID of the email the notification is about (`mail.messageId`).
+fn ses_message_id(notification: &SesNotification) -> Option<&str> {
+    notification
         .other_fields
-        .get("mail")
-        .and_then(|m| m.get("messageId"))
-        .and_then(Value::as_str);
+        .get("mail")?
+        .get("messageId")?
+        .as_str()
+}
 
-    let ses_msg_id = ses_msg_id.ok_or_else(|| {
+/// Resolves the request a notification is about.
+///
+/// The endpoint is unauthenticated, so the `request_id` tag is only trusted when
+/// the request has the notification's SES message ID stored; otherwise the
+/// request is looked up by that message ID.
+async fn verified_request_id(
+    state: &AppState,
+    notification: &SesNotification,
+    sns_message_id: &str,
+) -> AppResult<i32> {
+    if let (Some(request_id), Some(ses_msg_id)) = (
+        tagged_request_id(notification),
+        ses_message_id(notification),
+    ) {
+        if EmailRequest::has_message_id(&state.db_pool, request_id, ses_msg_id).await? {
+            return Ok(request_id);
+        }
+        warn!("request_id tag {request_id} does not match SES message {ses_msg_id}");
+    }
+    request_id_by_message_id(state, notification, sns_message_id).await
+}
+
+/// Finds the request that was sent with the notification's SES message ID.
+#[allow(clippy::similar_names)]
+async fn request_id_by_message_id(
+    state: &AppState,
+    notification: &SesNotification,
+    sns_message_id: &str,
+) -> AppResult<i32> {
+    let ses_msg_id = ses_message_id(notification).ok_or_else(|| {
         error!("SES message_id not found. SNS: {sns_message_id}");
         AppError::BadRequest("SES message_id not found".to_string())
     })?;
 
-    let request_id = EmailRequest::get_request_id_by_message_id(&state.db_pool, ses_msg_id)
+    EmailRequest::get_request_id_by_message_id(&state.db_pool, ses_msg_id)
         .await
         .map_err(|e| {
             error!("Request lookup failed. SES: {ses_msg_id}, Error: {e:?}");
             AppError::NotFound("Request not found".to_string())
-        })?;
+        })
+}
 
-    let result = EmailResult {
-        id: None,
-        request_id,
-        status: notification.event_type,
-        raw: Some(message.to_owned()),
+/// Recipients to suppress for a notification: permanent bounces and complaints.
+///
+/// Transient (soft) bounces are retried by SES and do not suppress.
+fn suppression_targets(notification: &SesNotification) -> Option<(SuppressionReason, Vec<&str>)> {
+    let (reason, recipients) = match notification.event_type.as_str() {
+        "Bounce" => {
+            let bounce = notification.other_fields.get("bounce")?;
+            if bounce.get("bounceType").and_then(Value::as_str) != Some("Permanent") {
+                return None;
+            }
+            (SuppressionReason::Bounce, bounce.get("bouncedRecipients")?)
+        }
+        "Complaint" => (
+            SuppressionReason::Complaint,
+            notification
+                .other_fields
+                .get("complaint")?
+                .get("complainedRecipients")?,
+        ),
+        _ => return None,
     };
 
-    result.save(&state.db_pool).await?;
-    Ok(())
+    let emails = recipients
+        .as_array()?
+        .iter()
+        .filter_map(|r| r.get("emailAddress").and_then(Value::as_str))
+        .collect();
+    Some((reason, emails))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::seed_request;
+
+    async fn test_state() -> AppState {
+        let (tx, _rx) = tokio::sync::mpsc::channel(1);
+        AppState::new(crate::config::init_test_db().await, tx)
+    }
 
     #[test]
     fn test_tracking_pixel_is_valid_png() {
@@ -206,6 +351,165 @@ mod tests {
         assert_eq!(params.request_id, Some("123".to_string()));
     }
 
+    #[test]
+    fn test_click_query_params_deserialization() {
+        let json = r#"{"request_id": "7", "url": "https://example.com", "sig": "abc"}"#;
+        let params: ClickQueryParams = serde_json::from_str(json).unwrap();
+        assert_eq!(params.request_id.as_deref(), Some("7"));
+        assert_eq!(params.url.as_deref(), Some("https://example.com"));
+    }
+
+    #[tokio::test]
+    async fn test_track_click_redirects_signed_url() {
+        let state = test_state().await;
+        seed_request(&state.db_pool, 7, "t", "user@example.com", None).await;
+        let url = "https://shop.example.com/item?id=1";
+        let query = ClickQueryParams {
+            request_id: Some("7".to_string()),
+            url: Some(url.to_string()),
+            sig: Some(tracking::sign(&APP_CONFIG.tracking_secret, 7, url)),
+        };
+
+        let response = track_click(State(state.clone()), Query(query))
+            .await
+            .unwrap()
+            .into_response();
+        assert_eq!(response.status(), StatusCode::FOUND);
+        assert_eq!(response.headers()[header::LOCATION], url);
+
+        let (status, raw): (String, String) =
+            sqlx::query_as("SELECT status, raw FROM email_results WHERE request_id = 7")
+                .fetch_one(&state.db_pool)
+                .await
+                .unwrap();
+        assert_eq!(status, "Click");
+        assert_eq!(raw, url);
+    }
+
+    #[tokio::test]
+    async fn test_track_click_rejects_unsigned_url() {
+        let state = test_state().await;
+        let signed = tracking::sign(&APP_CONFIG.tracking_secret, 7, "https://shop.example.com");
+        let query = ClickQueryParams {
+            request_id: Some("7".to_string()),
+            url: Some("https://evil.example.com".to_string()),
+            sig: Some(signed),
+        };
+
+        let result = track_click(State(state), Query(query)).await;
+        assert!(matches!(result, Err(AppError::BadRequest(_))));
+    }
+
+    fn notification(json: &str) -> SesNotification {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_suppression_targets() {
+        let hard = notification(
+            r#"{"notificationType": "Bounce", "bounce": {"bounceType": "Permanent",
+                "bouncedRecipients": [{"emailAddress": "gone@example.com"}]}}"#,
+        );
+        assert_eq!(
+            suppression_targets(&hard),
+            Some((SuppressionReason::Bounce, vec!["gone@example.com"]))
+        );
+
+        let complaint = notification(
+            r#"{"notificationType": "Complaint", "complaint":
+                {"complainedRecipients": [{"emailAddress": "angry@example.com"}]}}"#,
+        );
+        assert_eq!(
+            suppression_targets(&complaint),
+            Some((SuppressionReason::Complaint, vec!["angry@example.com"]))
+        );
+
+        let soft = notification(
+            r#"{"notificationType": "Bounce", "bounce": {"bounceType": "Transient",
+                "bouncedRecipients": [{"emailAddress": "full@example.com"}]}}"#,
+        );
+        assert_eq!(suppression_targets(&soft), None);
+        assert_eq!(
+            suppression_targets(&notification(r#"{"notificationType": "Delivery"}"#)),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_permanent_bounce_suppresses_recipient() {
+        let state = test_state().await;
+        seed_request(&state.db_pool, 7, "t", "gone@example.com", Some("ses-1")).await;
+
+        let message = r#"{"notificationType": "Bounce", "mail": {"messageId": "ses-1"},
+            "bounce": {"bounceType": "Permanent", "bouncedRecipients": [{"emailAddress": "Gone@example.com"}]}}"#;
+        process_ses_notification(&state, message, "sns-1")
+            .await
+            .unwrap();
+
+        let suppression = Suppression::get(&state.db_pool, "gone@example.com")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(suppression.reason, "Bounce");
+        assert_eq!(suppression.request_id, Some(7));
+    }
+
+    #[tokio::test]
+    async fn test_forged_complaint_does_not_suppress() {
+        let state = test_state().await;
+        seed_request(&state.db_pool, 7, "t", "user@example.com", Some("ses-1")).await;
+
+        // Forged complaint naming a real request but a message ID that was never sent
+        let message = r#"{"notificationType": "Complaint",
+            "mail": {"messageId": "forged", "tags": {"request_id": ["7"]}},
+            "complaint": {"complainedRecipients": [{"emailAddress": "victim@example.com"}]}}"#;
+        let result = process_ses_notification(&state, message, "sns-1").await;
+        assert!(matches!(result, Err(AppError::NotFound(_))));
+
+        assert!(Suppression::get(&state.db_pool, "victim@example.com")
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_event_correlated_by_request_id_tag() {
+        let state = test_state().await;
+        seed_request(&state.db_pool, 7, "t", "user@example.com", Some("ses-1")).await;
+
+        // Event publishing record (`eventType`)
+        let message = r#"{"eventType": "Delivery", "mail": {"messageId": "ses-1",
+            "tags": {"topic_id": ["t"], "request_id": ["7"]}}}"#;
+        process_ses_notification(&state, message, "sns-1")
+            .await
+            .unwrap();
+
+        assert!(EmailResult::exists(&state.db_pool, 7, "Delivery")
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_mismatched_request_id_tag_uses_message_id() {
+        let state = test_state().await;
+        seed_request(&state.db_pool, 7, "t", "a@example.com", Some("ses-7")).await;
+        seed_request(&state.db_pool, 8, "t", "b@example.com", Some("ses-8")).await;
+
+        // The tag claims request 8, but the SES message belongs to request 7
+        let message = r#"{"eventType": "Delivery", "mail": {"messageId": "ses-7",
+            "tags": {"request_id": ["8"]}}}"#;
+        process_ses_notification(&state, message, "sns-1")
+            .await
+            .unwrap();
+
+        assert!(EmailResult::exists(&state.db_pool, 7, "Delivery")
+            .await
+            .unwrap());
+        assert!(!EmailResult::exists(&state.db_pool, 8, "Delivery")
+            .await
+            .unwrap());
+    }
+
     #[test]
     fn test_sns_message_notification_deserialization() {
         let json = r#"{"Message": "test", "MessageId": "msg-123"}"#;
@@ -1,142 +1,473 @@
 //! Email message sending handler
 
-use std::sync::Arc;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::Arc,
+};
 
-use axum::{extract::State, response::IntoResponse, Json};
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::HeaderMap,
+    response::{IntoResponse, Response},
+    Json,
+};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
 use sqlx::SqlitePool;
 use tracing::{error, info, warn};
 
 use crate::{
+    config::APP_CONFIG,
+    constants::MAX_MESSAGE_SIZE_BYTES,
     error::{AppError, AppResult},
+    handlers::rate_limit_handlers::{apply_rate_limit, validate_rate_limit},
     models::{
-        content::EmailContent,
-        request::{EmailMessageStatus, EmailRequest},
+        attachment::Attachment,
+        content::{EmailContent, FromAddress, MessageOptions},
+        idempotency::{IdempotencyKey, Reservation},
+        rate_limit::RateLimitScope,
+        request::{EmailMessageStatus, EmailRequest, Priority, RequestWindow},
+        suppression::Suppression,
+        template::EmailTemplateVersion,
+    },
+    services::{
+        address, mime,
+        schedule::{self, SendWindow},
     },
     state::AppState,
 };
 
-const MAX_EMAILS_PER_REQUEST: usize = 10_000;
+pub const MAX_EMAILS_PER_REQUEST: usize = 10_000;
+const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+/// Set on responses replayed from a stored idempotency key.
+const IDEMPOTENT_REPLAYED_HEADER: &str = "idempotent-replayed";
+const MAX_IDEMPOTENCY_KEY_LENGTH: usize = 255;
+const DEFAULT_ATTACHMENT_CONTENT_TYPE: &str = "application/octet-stream";
+/// SES limit of To + CC + BCC addresses per email.
+const MAX_DESTINATIONS_PER_EMAIL: usize = 50;
+/// SES limits for custom header names and values.
+const MAX_HEADER_NAME_LENGTH: usize = 126;
+const MAX_HEADER_VALUE_LENGTH: usize = 870;
+/// SES limit for configuration set names.
+const MAX_CONFIGURATION_SET_LENGTH: usize = 64;
+
+/// Headers set by this service or SES that messages must not override.
+const RESERVED_HEADERS: &[&str] = &[
+    "from",
+    "sender",
+    "to",
+    "cc",
+    "bcc",
+    "reply-to",
+    "subject",
+    "date",
+    "message-id",
+    "return-path",
+    "mime-version",
+    "content-type",
+    "content-transfer-encoding",
+    "content-disposition",
+    "dkim-signature",
+    "received",
+    "list-unsubscribe",
+    "list-unsubscribe-post",
+];
+
+/// A recipient address with optional per-recipient template variables and send time.
+///
+/// Accepts either a plain address (`"user@example.com"`) or an object
+/// (`{"email": "user@example.com", "variables": {"name": "..."}, "send_after_seconds": 3600}`).
+/// `scheduled_at` / `send_after_seconds` override the message and request send time.
+/// `timezone` applies to the recipient's local `scheduled_at` and the message's `send_window`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(from = "RecipientInput")]
+pub struct Recipient {
+    pub email: String,
+    pub variables: HashMap<String, String>,
+    pub scheduled_at: Option<String>,
+    pub send_after_seconds: Option<u64>,
+    pub timezone: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RecipientInput {
+    Address(String),
+    Detailed {
+        email: String,
+        #[serde(default)]
+        variables: HashMap<String, String>,
+        scheduled_at: Option<String>,
+        send_after_seconds: Option<u64>,
+        timezone: Option<String>,
+    },
+}
+
+impl From<RecipientInput> for Recipient {
+    fn from(input: RecipientInput) -> Self {
+        match input {
+            RecipientInput::Address(email) => Self {
+                email,
+                ..Self::default()
+            },
+            RecipientInput::Detailed {
+                email,
+                variables,
+                scheduled_at,
+                send_after_seconds,
+                timezone,
+            } => Self {
+                email,
+                variables,
+                scheduled_at,
+                send_after_seconds,
+                timezone,
+            },
+        }
+    }
+}
+
+/// A base64-encoded file attached to a message.
+///
+/// With `content_id`, the file is sent inline and can be referenced from
+/// the HTML body as `<img src="cid:{content_id}">`.
+#[derive(Debug, Deserialize)]
+pub struct AttachmentInput {
+    pub filename: String,
+    pub content_type: Option<String>,
+    pub content_id: Option<String>,
+    pub data: String,
+}
+
+/// Daily local time range (`HH:MM`) in which a message may be sent.
+#[derive(Debug, Deserialize)]
+pub struct SendWindowInput {
+    pub start: String,
+    pub end: String,
+}
 
+/// Sender identity: a plain address or `{"email": "...", "name": "..."}`.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum FromInput {
+    Address(String),
+    Detailed { email: String, name: Option<String> },
+}
+
+/// A message sent to a group of recipients.
+///
+/// Content is either inline (`subject` + `content`, optional `text`) or a stored
+/// template (`template_id`, optionally pinned to `version`; latest otherwise).
+/// Without `text`, a plain-text body is derived from the HTML at send time.
+/// `cc`, `bcc` and `reply_to` apply to the email sent to every recipient.
+/// `from` must be covered by `AWS_SES_ALLOWED_FROM` (default: `AWS_SES_FROM_EMAIL`).
+/// `configuration_set` overrides `AWS_SES_CONFIGURATION_SET`.
+/// `scheduled_at` / `send_after_seconds` override the request send time,
+/// `spread_over_seconds` staggers the recipients evenly over a duration, and
+/// `send_window` defers recipients to the next window opening in their timezone.
 #[derive(Debug, Deserialize)]
 pub struct Message {
     pub topic_id: Option<String>,
-    pub emails: Vec<String>,
-    pub subject: String,
-    pub content: String,
+    pub from: Option<FromInput>,
+    pub emails: Vec<Recipient>,
+    pub subject: Option<String>,
+    pub content: Option<String>,
+    pub text: Option<String>,
+    pub template_id: Option<i32>,
+    pub version: Option<i32>,
+    #[serde(default)]
+    pub attachments: Vec<AttachmentInput>,
+    #[serde(default)]
+    pub cc: Vec<String>,
+    #[serde(default)]
+    pub bcc: Vec<String>,
+    #[serde(default)]
+    pub reply_to: Vec<String>,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    pub configuration_set: Option<String>,
+    pub scheduled_at: Option<String>,
+    pub send_after_seconds: Option<u64>,
+    pub send_window: Option<SendWindowInput>,
+    pub spread_over_seconds: Option<u64>,
+    /// Sending lane (default: bulk)
+    pub priority: Option<Priority>,
+    /// Emails-per-second cap of the topic (kept until changed or removed)
+    pub rate_limit_per_second: Option<u32>,
 }
 
+/// `scheduled_at` is RFC 3339 (`2025-01-01T09:00:00+09:00`) or a local time
+/// (`2025-01-01 09:00:00`) in `timezone` (IANA name, default: `DEFAULT_TIMEZONE`);
+/// `send_after_seconds` delays sending relative to the request instead.
+/// Both can also be set per message and per recipient (the most specific wins).
 #[derive(Debug, Deserialize)]
 pub struct CreateMessageRequest {
     pub messages: Vec<Message>,
     pub scheduled_at: Option<String>,
+    pub send_after_seconds: Option<u64>,
+    pub timezone: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
+/// A recipient dropped before saving, with the reason.
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct RejectedRecipient {
+    pub email: String,
+    pub reason: String,
+}
+
+/// `total` = `success` + `errors` + `skipped`; `errors` includes `rejected`.
+#[derive(Debug, Deserialize, Serialize)]
 pub struct CreateMessageResponse {
     pub total: usize,
     pub success: usize,
     pub errors: usize,
+    /// Suppressed recipients (stored with `Skipped` status, never sent)
+    pub skipped: usize,
+    /// Immediate sends held for the scheduler because the 24-hour quota is reached
+    #[serde(default)]
+    pub held: usize,
+    /// Invalid or duplicate recipients (not stored)
+    #[serde(default)]
+    pub rejected: Vec<RejectedRecipient>,
     pub duration_ms: u128,
+    /// Whether any recipient was scheduled for later
     pub scheduled: bool,
+    /// Earliest scheduled send time (RFC 3339, UTC)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scheduled_at: Option<String>,
 }
 
 /// Creates email sending requests.
 ///
+/// With an `Idempotency-Key` header, a retry of the same body returns the
+/// stored response instead of sending again; reusing the key with a different
+/// body is rejected with `409 Conflict`.
+pub async fn create_message(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> AppResult<Response> {
+    let payload: CreateMessageRequest = serde_json::from_slice(&body)
+        .map_err(|e| AppError::BadRequest(format!("Invalid request body: {e}")))?;
+
+    let Some(key) = idempotency_key(&headers)? else {
+        return Ok(Json(process_messages(&state, payload).await?).into_response());
+    };
+
+    let request_hash = IdempotencyKey::request_hash(&body);
+    match IdempotencyKey::reserve(&state.db_pool, &key, &request_hash).await? {
+        Reservation::Reserved => {}
+        Reservation::Completed {
+            request_hash: stored,
+            response,
+        } if stored == request_hash => {
+            info!("Replaying response for Idempotency-Key '{key}'");
+            let response: CreateMessageResponse = serde_json::from_str(&response)
+                .map_err(|e| AppError::Internal(format!("Invalid stored response: {e}")))?;
+            return Ok(([(IDEMPOTENT_REPLAYED_HEADER, "true")], Json(response)).into_response());
+        }
+        Reservation::InProgress {
+            request_hash: stored,
+        } if stored == request_hash => {
+            return Err(AppError::Conflict(
+                "A request with this Idempotency-Key is still in progress".to_string(),
+            ));
+        }
+        _ => {
+            return Err(AppError::Conflict(
+                "Idempotency-Key was already used with a different request body".to_string(),
+            ));
+        }
+    }
+
+    match process_messages(&state, payload).await {
+        Ok(response) => {
+            let stored = serde_json::to_string(&response).unwrap_or_default();
+            if let Err(e) = IdempotencyKey::complete(&state.db_pool, &key, &stored).await {
+                error!("Failed to store response for Idempotency-Key '{key}': {e}");
+            }
+            Ok(Json(response).into_response())
+        }
+        Err(e) => {
+            if let Err(release_err) = IdempotencyKey::release(&state.db_pool, &key).await {
+                error!("Failed to release Idempotency-Key '{key}': {release_err}");
+            }
+            Err(e)
+        }
+    }
+}
+
+/// Reads the optional `Idempotency-Key` header (visible ASCII, max 255 chars).
+fn idempotency_key(headers: &HeaderMap) -> AppResult<Option<String>> {
+    let Some(value) = headers.get(IDEMPOTENCY_KEY_HEADER) else {
+        return Ok(None);
+    };
+
+    value
+        .to_str()
+        .ok()
+        .map(str::trim)
+        .filter(|key| {
+            !key.is_empty()
+                && key.len() <= MAX_IDEMPOTENCY_KEY_LENGTH
+                && key.bytes().all(|b| b.is_ascii_graphic())
+        })
+        .map(|key| Some(key.to_string()))
+        .ok_or_else(|| AppError::BadRequest("Invalid Idempotency-Key header".to_string()))
+}
+
+/// Saves and dispatches the messages of a request.
+///
 /// 1. Saves content (subject, body) to `email_contents` table
 /// 2. Creates requests with `content_id` reference
 /// - Immediate: Sent directly to the sending queue
 /// - Scheduled: Stored with `scheduled_at` for later processing
 #[allow(clippy::too_many_lines)]
-pub async fn create_message(
-    State(state): State<AppState>,
-    Json(payload): Json<CreateMessageRequest>,
-) -> AppResult<impl IntoResponse> {
+async fn process_messages(
+    state: &AppState,
+    mut payload: CreateMessageRequest,
+) -> AppResult<CreateMessageResponse> {
     let start = std::time::Instant::now();
 
     if payload.messages.is_empty() {
         return Err(AppError::BadRequest("No messages provided".to_string()));
     }
 
-    let scheduled_at = payload.scheduled_at;
-    let is_scheduled = matches!(&scheduled_at, Some(s) if !s.is_empty());
-    let status = if is_scheduled {
-        EmailMessageStatus::Created as i32
-    } else {
-        EmailMessageStatus::Processed as i32
-    };
+    let total: usize = payload.messages.iter().map(|m| m.emails.len()).sum();
+    if total > MAX_EMAILS_PER_REQUEST {
+        warn!("Too many emails: {total} > {MAX_EMAILS_PER_REQUEST}");
+        return Err(AppError::BadRequest(format!(
+            "Max {MAX_EMAILS_PER_REQUEST} emails per request"
+        )));
+    }
+    let rejected = accept_recipients(&mut payload.messages);
+    let deliveries = resolve_deliveries(&payload, Utc::now())?;
+    let scheduled_at = deliveries.iter().flatten().filter_map(|d| d.send_at).min();
+    let is_scheduled = scheduled_at.is_some();
 
-    // 1. Save contents first (one per message)
-    let contents: Vec<EmailContent> = payload
-        .messages
-        .iter()
-        .map(|msg| EmailContent {
-            id: None,
-            subject: msg.subject.clone(),
-            content: msg.content.clone(),
-        })
-        .collect();
+    let rate_limits = topic_rate_limits(&payload.messages)?;
 
-    let saved_contents = EmailContent::save_batch(contents, &state.db_pool).await?;
+    // 1. Save contents first (one per message), then their attachments
+    let (saved_contents, attachments) = save_contents(&state.db_pool, &payload.messages).await?;
+
+    // Topic limits only change once the whole request has been validated
+    for (topic_id, max_per_second) in rate_limits {
+        apply_rate_limit(state, RateLimitScope::Topic, &topic_id, max_per_second).await?;
+    }
 
     // 2. Create requests with content_id
     // Use Arc to share subject/content across all emails in the same message,
     // avoiding expensive string cloning (e.g., 10,000 emails = 1 Arc::clone vs 10,000 String::clone)
-    let requests: Vec<EmailRequest> = payload
+    let mut requests: Vec<EmailRequest> = payload
         .messages
         .into_iter()
         .zip(saved_contents.iter())
-        .flat_map(|(msg, saved_content)| {
+        .zip(attachments)
+        .zip(deliveries)
+        .flat_map(|(((msg, saved_content), files), times)| {
             let topic_id = msg.topic_id.unwrap_or_default();
             let content_id = saved_content.id;
             // Create Arc once per message, share across all emails
             let subject = Arc::new(saved_content.subject.clone());
             let content = Arc::new(saved_content.content.clone());
-            let sched = scheduled_at.clone();
+            let text = saved_content.text.clone().map(Arc::new);
+            let files = Arc::new(files);
+            let options = Arc::new(saved_content.options.clone());
+            let priority = msg.priority.unwrap_or_default();
 
-            msg.emails.into_iter().map(move |email| EmailRequest {
-                id: None,
-                topic_id: Some(topic_id.clone()),
-                content_id,
-                email,
-                subject: Arc::clone(&subject),
-                content: Arc::clone(&content),
-                scheduled_at: sched.clone(),
-                status,
-                error: None,
-                message_id: None,
-            })
+            msg.emails.into_iter().zip(times).map(
+                move |(recipient, Delivery { send_at, window })| EmailRequest {
+                    id: None,
+                    topic_id: Some(topic_id.clone()),
+                    content_id,
+                    email: recipient.email,
+                    subject: Arc::clone(&subject),
+                    content: Arc::clone(&content),
+                    text: text.clone(),
+                    attachments: Arc::clone(&files),
+                    options: Arc::clone(&options),
+                    scheduled_at: send_at.map(schedule::to_storage),
+                    status: if send_at.is_some() {
+                        EmailMessageStatus::Created as i32
+                    } else {
+                        EmailMessageStatus::Processed as i32
+                    },
+                    error: None,
+                    message_id: None,
+                    variables: Some(recipient.variables).filter(|v| !v.is_empty()),
+                    send_window: window,
+                    priority,
+                },
+            )
         })
         .collect();
 
-    let total = requests.len();
+    let accepted = requests.len();
 
-    if total > MAX_EMAILS_PER_REQUEST {
-        warn!("Too many emails: {total} > {MAX_EMAILS_PER_REQUEST}");
-        return Err(AppError::BadRequest(format!(
-            "Max {MAX_EMAILS_PER_REQUEST} emails per request"
-        )));
+    // 3. Skip suppressed recipients (kept for statistics, never sent)
+    let emails: Vec<&str> = requests.iter().map(|r| r.email.as_str()).collect();
+    let suppressed = Suppression::find_suppressed(&state.db_pool, &emails).await?;
+    let mut skipped = 0;
+    for req in &mut requests {
+        if suppressed.contains(&Suppression::normalize(&req.email)) {
+            req.status = EmailMessageStatus::Skipped as i32;
+            skipped += 1;
+        }
+    }
+
+    // 4. Reserve 24-hour quota slots for immediate sends; the rest are held for the scheduler
+    let immediate_count = requests
+        .iter()
+        .filter(|r| r.status == EmailMessageStatus::Processed as i32)
+        .count();
+    let reserved = state.daily_quota.try_reserve(immediate_count as u64);
+    let mut held = 0;
+    for req in requests
+        .iter_mut()
+        .filter(|r| r.status == EmailMessageStatus::Processed as i32)
+        .skip(usize::try_from(reserved).unwrap_or(usize::MAX))
+    {
+        req.status = EmailMessageStatus::Created as i32;
+        held += 1;
+    }
+    if held > 0 {
+        warn!("24-hour send quota reached, holding {held} emails");
     }
 
-    info!("Processing {total} emails (scheduled={is_scheduled})");
+    info!(
+        "Processing {accepted} emails (scheduled={is_scheduled}, skipped={skipped}, rejected={})",
+        rejected.len()
+    );
 
     let (success, errors, saved_requests) =
         match EmailRequest::save_batch(requests, &state.db_pool).await {
             Ok(saved) => {
-                let count = saved.len();
+                state.daily_quota.commit(reserved);
+                let count = saved.len() - skipped;
                 (count, 0, saved)
             }
             Err(e) => {
                 error!("Batch save failed: {e:?}");
-                (0, total, Vec::new())
+                state.daily_quota.release(reserved);
+                skipped = 0;
+                held = 0;
+                (0, accepted, Vec::new())
             }
         };
 
-    if !is_scheduled {
+    // Scheduled requests are left to the scheduler
+    let immediate: Vec<EmailRequest> = saved_requests
+        .into_iter()
+        .filter(|r| r.status == EmailMessageStatus::Processed as i32)
+        .collect();
+    if !immediate.is_empty() {
         let mut failed_requests = Vec::new();
         let mut channel_closed = false;
 
-        for req in saved_requests {
+        for req in immediate {
             if channel_closed {
                 // Channel already closed, add remaining to failed
                 let mut req = req;
@@ -167,6 +498,8 @@ pub async fn create_message(
         // Batch rollback for failed requests
         if !failed_requests.is_empty() {
             warn!("Rolling back {} failed requests", failed_requests.len());
+            // Their quota slots were reserved but will be claimed again later
+            state.daily_quota.release(failed_requests.len() as u64);
             let ids: Vec<i32> = failed_requests.iter().filter_map(|r| r.id).collect();
             if let Err(e) = rollback_to_created(&state.db_pool, &ids).await {
                 error!("Failed to rollback {} requests: {e}", ids.len());
@@ -175,15 +508,500 @@ pub async fn create_message(
     }
 
     let duration = start.elapsed();
-    info!("Done: {success} ok, {errors} err in {duration:?}");
+    let errors = errors + rejected.len();
+    info!("Done: {success} ok, {errors} err, {skipped} skipped, {held} held in {duration:?}");
 
-    Ok(Json(CreateMessageResponse {
+    Ok(CreateMessageResponse {
         total,
         success,
         errors,
+        skipped,
+        held,
+        rejected,
         duration_ms: duration.as_millis(),
         scheduled: is_scheduled,
-    }))
+        scheduled_at: scheduled_at.map(schedule::to_rfc3339),
+    })
+}
+
+/// When, and within which daily window, a recipient is sent.
+#[derive(Debug)]
+struct Delivery {
+    /// UTC send time (`None` = send immediately)
+    send_at: Option<DateTime<Utc>>,
+    window: Option<RequestWindow>,
+}
+
+/// Resolves the send time and window of every recipient.
+///
+/// A recipient's `scheduled_at` / `send_after_seconds` wins over its message's,
+/// which wins over the request's; delays are relative to `now`. With
+/// `spread_over_seconds`, each recipient is further delayed by its share of
+/// the duration. Recipients
+/// whose send time falls outside the message's `send_window` (in their own
+/// `timezone`, default: the request's) are moved to the next window opening.
+fn resolve_deliveries(
+    payload: &CreateMessageRequest,
+    now: DateTime<Utc>,
+) -> AppResult<Vec<Vec<Delivery>>> {
+    let invalid =
+        |context: &str, e: schedule::ScheduleError| AppError::Validation(format!("{context}: {e}"));
+    let timezone = schedule::parse_timezone(payload.timezone.as_deref())
+        .map_err(|e| invalid("timezone", e))?;
+    let resolve = |scheduled_at: Option<&String>, send_after_seconds, timezone, context: &str| {
+        schedule::resolve(
+            scheduled_at.map(String::as_str),
+            send_after_seconds,
+            timezone,
+            now,
+        )
+        .map_err(|e| invalid(context, e))
+    };
+
+    let request_time = resolve(
+        payload.scheduled_at.as_ref(),
+        payload.send_after_seconds,
+        timezone,
+        "request",
+    )?;
+    payload
+        .messages
+        .iter()
+        .enumerate()
+        .map(|(i, msg)| {
+            let message_time = resolve(
+                msg.scheduled_at.as_ref(),
+                msg.send_after_seconds,
+                timezone,
+                &format!("messages[{i}]"),
+            )?
+            .or(request_time);
+            let window = msg
+                .send_window
+                .as_ref()
+                .map(|w| SendWindow::new(&w.start, &w.end))
+                .transpose()
+                .map_err(|e| invalid(&format!("messages[{i}].send_window"), e))?;
+            let spread_over_seconds = match msg.spread_over_seconds {
+                Some(seconds) if seconds > schedule::MAX_SEND_AFTER_SECONDS => {
+                    return Err(invalid(
+                        &format!("messages[{i}]"),
+                        schedule::ScheduleError::SpreadTooLong(seconds),
+                    ));
+                }
+                spread => spread.unwrap_or(0),
+            };
+            let count = msg.emails.len();
+
+            msg.emails
+                .iter()
+                .enumerate()
+                .map(|(index, recipient)| {
+                    let context = format!("messages[{i}] recipient '{}'", recipient.email);
+                    let recipient_timezone =
+                        recipient.timezone.as_deref().map_or(Ok(timezone), |name| {
+                            schedule::parse_timezone(Some(name)).map_err(|e| invalid(&context, e))
+                        })?;
+                    let send_at = resolve(
+                        recipient.scheduled_at.as_ref(),
+                        recipient.send_after_seconds,
+                        recipient_timezone,
+                        &context,
+                    )?
+                    .or(message_time);
+                    let send_at = match schedule::spread_offset(spread_over_seconds, index, count) {
+                        offset if offset.is_zero() => send_at,
+                        offset => Some(send_at.unwrap_or(now) + offset),
+                    };
+
+                    Ok(window.map_or(
+                        Delivery {
+                            send_at,
+                            window: None,
+                        },
+                        |window| in_window(window, recipient_timezone, send_at, now),
+                    ))
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Defers a send time outside `window` to the window's next opening.
+fn in_window(
+    window: SendWindow,
+    timezone: Tz,
+    send_at: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> Delivery {
+    let at = send_at.unwrap_or(now);
+    let (open, close) = window.next(timezone, at);
+    Delivery {
+        send_at: if open > at { Some(open) } else { send_at },
+        window: Some(RequestWindow {
+            window: window.to_string(),
+            timezone: timezone.name().to_string(),
+            ends_at: schedule::to_storage(close),
+        }),
+    }
+}
+
+/// Validates the `rate_limit_per_second` of each message, returned per topic.
+///
+/// A limit needs a `topic_id`: untagged messages all share the empty topic.
+fn topic_rate_limits(messages: &[Message]) -> AppResult<Vec<(String, u32)>> {
+    messages
+        .iter()
+        .filter_map(|msg| Some((msg, msg.rate_limit_per_second?)))
+        .map(|(msg, max_per_second)| {
+            let topic_id = msg
+                .topic_id
+                .clone()
+                .filter(|t| !t.trim().is_empty())
+                .ok_or_else(|| {
+                    AppError::Validation("rate_limit_per_second requires topic_id".to_string())
+                })?;
+            validate_rate_limit(RateLimitScope::Topic, &topic_id, max_per_second)?;
+            Ok((topic_id, max_per_second))
+        })
+        .collect()
+}
+
+/// Validates and saves the content, options and attachments of each message.
+///
+/// Returns the saved contents and the decoded attachments, in message order.
+pub async fn save_contents(
+    db_pool: &SqlitePool,
+    messages: &[Message],
+) -> AppResult<(Vec<EmailContent>, Vec<Vec<Attachment>>)> {
+    let mut contents = Vec::with_capacity(messages.len());
+    let mut attachments = Vec::with_capacity(messages.len());
+    for msg in messages {
+        let mut content = resolve_content(db_pool, msg).await?;
+        content.options = message_options(msg)?;
+        let files = decode_attachments(&msg.attachments)?;
+
+        let size = mime::estimate_size(
+            &content.subject,
+            &content.content,
+            content.text.as_deref(),
+            &files,
+        );
+        if size > MAX_MESSAGE_SIZE_BYTES {
+            return Err(AppError::Validation(format!(
+                "Message size exceeds the SES limit of {} MB (estimated {size} bytes)",
+                MAX_MESSAGE_SIZE_BYTES / 1024 / 1024
+            )));
+        }
+
+        contents.push(content);
+        attachments.push(files);
+    }
+
+    let saved_contents = EmailContent::save_batch(contents, db_pool).await?;
+    let content_attachments: Vec<(i32, &[Attachment])> = saved_contents
+        .iter()
+        .zip(&attachments)
+        .filter_map(|(c, files)| c.id.map(|id| (id, files.as_slice())))
+        .collect();
+    Attachment::save_for_contents(db_pool, &content_attachments).await?;
+
+    Ok((saved_contents, attachments))
+}
+
+/// Normalizes the recipients of every message in place and drops invalid
+/// addresses and duplicates within a topic (compared case-insensitively).
+pub fn accept_recipients(messages: &mut [Message]) -> Vec<RejectedRecipient> {
+    let mut rejected = Vec::new();
+    let mut seen = HashSet::new();
+
+    for msg in messages {
+        let topic_id = msg.topic_id.clone().unwrap_or_default();
+        msg.emails.retain_mut(|recipient| {
+            let reason = match address::normalize(&recipient.email) {
+                Ok(normalized) => {
+                    if seen.insert((topic_id.clone(), normalized.to_lowercase())) {
+                        recipient.email = normalized;
+                        return true;
+                    }
+                    "duplicate recipient in topic".to_string()
+                }
+                Err(e) => e.to_string(),
+            };
+            rejected.push(RejectedRecipient {
+                email: std::mem::take(&mut recipient.email),
+                reason,
+            });
+            false
+        });
+    }
+    rejected
+}
+
+/// Builds the content for a message from inline fields or a stored template version.
+async fn resolve_content(db_pool: &SqlitePool, msg: &Message) -> AppResult<EmailContent> {
+    let Some(template_id) = msg.template_id else {
+        if msg.version.is_some() {
+            return Err(AppError::Validation(
+                "version requires template_id".to_string(),
+            ));
+        }
+        let (Some(subject), Some(content)) = (&msg.subject, &msg.content) else {
+            return Err(AppError::Validation(
+                "subject and content are required when template_id is not set".to_string(),
+            ));
+        };
+        return Ok(EmailContent {
+            id: None,
+            subject: subject.clone(),
+            content: content.clone(),
+            text: msg.text.clone(),
+            ..Default::default()
+        });
+    };
+
+    if msg.subject.is_some() || msg.content.is_some() || msg.text.is_some() {
+        return Err(AppError::Validation(
+            "Use either template_id or subject/content/text, not both".to_string(),
+        ));
+    }
+
+    let template = EmailTemplateVersion::get(db_pool, template_id, msg.version)
+        .await?
+        .ok_or_else(|| {
+            AppError::NotFound(msg.version.map_or_else(
+                || format!("Template {template_id} not found"),
+                |version| format!("Template {template_id} version {version} not found"),
+            ))
+        })?;
+
+    Ok(EmailContent {
+        id: None,
+        subject: template.subject,
+        content: template.content,
+        text: template.text,
+        template_id: Some(template.template_id),
+        template_version: Some(template.version),
+        ..Default::default()
+    })
+}
+
+/// Validates the sender, CC/BCC/Reply-To addresses and custom headers of a message.
+fn message_options(msg: &Message) -> AppResult<MessageOptions> {
+    let normalize = |field: &str, addresses: &[String]| -> AppResult<Vec<String>> {
+        addresses
+            .iter()
+            .map(|address| {
+                address::normalize(address).map_err(|e| {
+                    AppError::Validation(format!(
+                        "{field}: invalid email address '{}' ({e})",
+                        address.trim()
+                    ))
+                })
+            })
+            .collect()
+    };
+
+    let options = MessageOptions {
+        from: msg.from.as_ref().map(from_address).transpose()?,
+        cc: normalize("cc", &msg.cc)?,
+        bcc: normalize("bcc", &msg.bcc)?,
+        reply_to: normalize("reply_to", &msg.reply_to)?,
+        headers: validate_headers(&msg.headers)?,
+        configuration_set: msg
+            .configuration_set
+            .as_deref()
+            .map(validate_configuration_set)
+            .transpose()?,
+    };
+
+    if 1 + options.cc.len() + options.bcc.len() > MAX_DESTINATIONS_PER_EMAIL {
+        return Err(AppError::Validation(format!(
+            "Max {} cc/bcc addresses per message",
+            MAX_DESTINATIONS_PER_EMAIL - 1
+        )));
+    }
+    Ok(options)
+}
+
+fn from_address(input: &FromInput) -> AppResult<FromAddress> {
+    let (email, name) = match input {
+        FromInput::Address(email) => (email.trim(), None),
+        FromInput::Detailed { email, name } => (
+            email.trim(),
+            name.as_deref().map(str::trim).filter(|n| !n.is_empty()),
+        ),
+    };
+
+    let email = address::normalize(email).map_err(|e| {
+        AppError::Validation(format!("from: invalid email address '{email}' ({e})"))
+    })?;
+    if name.is_some_and(|n| n.chars().any(char::is_control)) {
+        return Err(AppError::Validation(
+            "from: name must not contain control characters".to_string(),
+        ));
+    }
+    if !is_allowed_sender(
+        &email,
+        &APP_CONFIG.aws_ses_allowed_from,
+        &APP_CONFIG.aws_ses_from_email,
+    ) {
+        return Err(AppError::Validation(format!(
+            "from: '{email}' is not an allowed sender identity"
+        )));
+    }
+
+    Ok(FromAddress {
+        email,
+        name: name.map(str::to_string),
+    })
+}
+
+/// Rejects reserved, malformed or duplicate (case-insensitive) header names and
+/// values that SES would refuse (printable ASCII only).
+fn validate_headers(headers: &BTreeMap<String, String>) -> AppResult<BTreeMap<String, String>> {
+    let mut validated = BTreeMap::new();
+    let mut seen = Vec::with_capacity(headers.len());
+
+    for (name, value) in headers {
+        let name = name.trim();
+        let lower = name.to_ascii_lowercase();
+
+        if name.is_empty()
+            || name.len() > MAX_HEADER_NAME_LENGTH
+            || !name.bytes().all(|b| (33..=126).contains(&b) && b != b':')
+        {
+            return Err(AppError::Validation(format!(
+                "headers: invalid header name '{name}'"
+            )));
+        }
+        if RESERVED_HEADERS.contains(&lower.as_str()) || lower.starts_with("x-ses-") {
+            return Err(AppError::Validation(format!(
+                "headers: '{name}' is controlled by the sender and cannot be set"
+            )));
+        }
+        if seen.contains(&lower) {
+            return Err(AppError::Validation(format!(
+                "headers: duplicate header '{name}'"
+            )));
+        }
+
+        let value = value.trim();
+        if value.is_empty()
+            || value.len() > MAX_HEADER_VALUE_LENGTH
+            || !value.bytes().all(|b| (0x20..0x7f).contains(&b))
+        {
+            return Err(AppError::Validation(format!(
+                "headers: invalid value for '{name}' (1-{MAX_HEADER_VALUE_LENGTH} printable ASCII characters)"
+            )));
+        }
+
+        seen.push(lower);
+        validated.insert(name.to_string(), value.to_string());
+    }
+    Ok(validated)
+}
+
+/// Checks a sender against the allow-list of addresses and domains.
+///
+/// Entries without `@` (or starting with `@`) allow any address of that domain.
+/// The default sender is always allowed.
+fn is_allowed_sender(email: &str, allowed: &[String], default_from: &str) -> bool {
+    let domain = email.rsplit_once('@').map_or("", |(_, d)| d);
+    email.eq_ignore_ascii_case(default_from)
+        || allowed.iter().any(|entry| {
+            let entry = entry.trim();
+            if entry.len() > 1 && entry.starts_with('@') {
+                domain.eq_ignore_ascii_case(&entry[1..])
+            } else if entry.contains('@') {
+                email.eq_ignore_ascii_case(entry)
+            } else {
+                domain.eq_ignore_ascii_case(entry)
+            }
+        })
+}
+
+/// Validates an SES configuration set name (ASCII letters, digits, `_` and `-`).
+fn validate_configuration_set(name: &str) -> AppResult<String> {
+    let name = name.trim();
+    let valid = !name.is_empty()
+        && name.len() <= MAX_CONFIGURATION_SET_LENGTH
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-'));
+    if valid {
+        Ok(name.to_string())
+    } else {
+        Err(AppError::Validation(format!(
+            "configuration_set: invalid name '{name}'"
+        )))
+    }
+}
+
+/// Decodes and validates the base64 attachments of a message.
+fn decode_attachments(inputs: &[AttachmentInput]) -> AppResult<Vec<Attachment>> {
+    inputs
+        .iter()
+        .enumerate()
+        .map(|(i, input)| {
+            let filename = input.filename.trim();
+            if filename.is_empty() || filename.chars().any(char::is_control) {
+                return Err(AppError::Validation(format!(
+                    "attachments[{i}]: invalid filename"
+                )));
+            }
+
+            let content_type = input
+                .content_type
+                .as_deref()
+                .map_or(DEFAULT_ATTACHMENT_CONTENT_TYPE, str::trim);
+            if !is_valid_content_type(content_type) {
+                return Err(AppError::Validation(format!(
+                    "attachments[{i}]: invalid content_type '{content_type}'"
+                )));
+            }
+
+            let cid = input
+                .content_id
+                .as_deref()
+                .map(|cid| cid.trim().trim_start_matches('<').trim_end_matches('>'));
+            if cid.is_some_and(|cid| {
+                cid.is_empty()
+                    || !cid
+                        .chars()
+                        .all(|c| c.is_ascii_graphic() && !matches!(c, '<' | '>' | '"'))
+            }) {
+                return Err(AppError::Validation(format!(
+                    "attachments[{i}]: invalid content_id"
+                )));
+            }
+
+            // Accept line-wrapped base64 (e.g. output of `base64` CLI)
+            let encoded: String = input.data.split_ascii_whitespace().collect();
+            let data = STANDARD.decode(encoded).map_err(|_| {
+                AppError::Validation(format!("attachments[{i}]: data is not valid base64"))
+            })?;
+
+            Ok(Attachment {
+                filename: filename.to_string(),
+                content_type: content_type.to_ascii_lowercase(),
+                cid: cid.map(str::to_string),
+                data,
+            })
+        })
+        .collect()
+}
+
+/// Checks for a `type/subtype` MIME type made of token characters.
+fn is_valid_content_type(value: &str) -> bool {
+    let is_token = |s: &str| {
+        !s.is_empty()
+            && s.chars()
+                .all(|c| c.is_ascii_alphanumeric() || "!#$&^_.+-".contains(c))
+    };
+    value
+        .split_once('/')
+        .is_some_and(|(kind, subtype)| is_token(kind) && is_token(subtype))
 }
 
 /// Batch rollback requests to Created status when channel send fails.
@@ -215,14 +1033,19 @@ mod tests {
             total: 100,
             success: 98,
             errors: 2,
+            skipped: 0,
+            held: 0,
+            rejected: Vec::new(),
             duration_ms: 150,
             scheduled: false,
+            scheduled_at: None,
         };
 
         let json = serde_json::to_string(&response).unwrap();
         assert!(json.contains("\"total\":100"));
         assert!(json.contains("\"success\":98"));
         assert!(json.contains("\"errors\":2"));
+        assert!(!json.contains("scheduled_at"));
     }
 
     #[test]
@@ -239,6 +1062,30 @@ mod tests {
         assert_eq!(msg.emails.len(), 1);
     }
 
+    #[test]
+    fn test_message_recipient_with_variables_deserialization() {
+        let json = r#"{
+            "emails": [
+                "plain@example.com",
+                {"email": "vip@example.com", "variables": {"name": "홍길동", "coupon_code": "VIP20"}},
+                {"email": "novars@example.com"}
+            ],
+            "subject": "Hello {{name}}",
+            "content": "<p>{{coupon_code}}</p>"
+        }"#;
+
+        let msg: Message = serde_json::from_str(json).unwrap();
+        assert_eq!(msg.emails.len(), 3);
+        assert_eq!(msg.emails[0].email, "plain@example.com");
+        assert!(msg.emails[0].variables.is_empty());
+        assert_eq!(msg.emails[1].email, "vip@example.com");
+        assert_eq!(
+            msg.emails[1].variables.get("coupon_code"),
+            Some(&"VIP20".to_string())
+        );
+        assert!(msg.emails[2].variables.is_empty());
+    }
+
     #[test]
     fn test_create_message_request_deserialization() {
         let json = r#"{
@@ -254,4 +1101,726 @@ mod tests {
         assert_eq!(req.messages.len(), 1);
         assert!(req.scheduled_at.is_some());
     }
+
+    fn send_times(json: serde_json::Value) -> AppResult<Vec<Vec<Option<String>>>> {
+        let payload: CreateMessageRequest = serde_json::from_value(json).unwrap();
+        resolve_deliveries(&payload, Utc::now()).map(|messages| {
+            messages
+                .into_iter()
+                .map(|deliveries| {
+                    deliveries
+                        .into_iter()
+                        .map(|d| d.send_at.map(schedule::to_rfc3339))
+                        .collect()
+                })
+                .collect()
+        })
+    }
+
+    #[test]
+    fn test_resolve_send_times_request_level() {
+        let json = |scheduled_at: &str, timezone: Option<&str>| {
+            serde_json::json!({
+                "messages": [{"emails": ["a@example.com"]}],
+                "scheduled_at": scheduled_at,
+                "timezone": timezone,
+            })
+        };
+        let at = |time: &str| vec![vec![Some(time.to_string())]];
+
+        assert_eq!(send_times(json(" ", None)).unwrap(), vec![vec![None]]);
+        assert_eq!(
+            send_times(json("2099-01-01T09:00:00+09:00", None)).unwrap(),
+            at("2099-01-01T00:00:00Z")
+        );
+        assert_eq!(
+            send_times(json("2099-07-01 09:00:00", Some("America/New_York"))).unwrap(),
+            at("2099-07-01T13:00:00Z")
+        );
+
+        for (scheduled_at, timezone) in [
+            ("2099-01-01 09:00", None),
+            ("2099-01-01 09:00:00", Some("Nowhere/City")),
+            ("2020-01-01T00:00:00Z", None),
+        ] {
+            assert!(
+                matches!(
+                    send_times(json(scheduled_at, timezone)),
+                    Err(AppError::Validation(_))
+                ),
+                "{scheduled_at}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_resolve_send_times_most_specific_wins() {
+        let times = send_times(serde_json::json!({
+            "messages": [
+                {"emails": ["a@example.com"]},
+                {
+                    "emails": [
+                        "b@example.com",
+                        {"email": "c@example.com", "scheduled_at": "2099-03-01T00:00:00Z"}
+                    ],
+                    "scheduled_at": "2099-02-01T00:00:00Z"
+                },
+                {"emails": [{"email": "d@example.com", "send_after_seconds": 0}]}
+            ],
+            "scheduled_at": "2099-01-01T00:00:00Z"
+        }))
+        .unwrap();
+
+        assert_eq!(times[0], vec![Some("2099-01-01T00:00:00Z".to_string())]);
+        assert_eq!(
+            times[1],
+            vec![
+                Some("2099-02-01T00:00:00Z".to_string()),
+                Some("2099-03-01T00:00:00Z".to_string())
+            ]
+        );
+        // send_after_seconds is relative to the request, not to scheduled_at
+        assert!(times[2][0].as_deref() < Some("2099"));
+
+        let conflicting = send_times(serde_json::json!({
+            "messages": [{
+                "emails": [{"email": "a@example.com", "scheduled_at": "2099-01-01T00:00:00Z", "send_after_seconds": 60}]
+            }]
+        }));
+        assert!(
+            matches!(conflicting, Err(AppError::Validation(msg)) if msg.starts_with("messages[0] recipient 'a@example.com'"))
+        );
+    }
+
+    #[test]
+    fn test_resolve_deliveries_send_window() {
+        let payload: CreateMessageRequest = serde_json::from_value(serde_json::json!({
+            "messages": [{
+                "emails": [
+                    "seoul@example.com",
+                    {"email": "ny@example.com", "timezone": "America/New_York"}
+                ],
+                "send_window": {"start": "09:00", "end": "18:00"}
+            }],
+            "scheduled_at": "2099-01-01T12:00:00",
+            "timezone": "Asia/Seoul"
+        }))
+        .unwrap();
+        let deliveries = resolve_deliveries(&payload, Utc::now()).unwrap();
+
+        // Inside the Seoul window: kept as is
+        let seoul = &deliveries[0][0];
+        assert_eq!(
+            seoul.send_at.map(schedule::to_rfc3339).as_deref(),
+            Some("2099-01-01T03:00:00Z")
+        );
+        let window = seoul.window.as_ref().unwrap();
+        assert_eq!(window.window, "09:00-18:00");
+        assert_eq!(window.timezone, "Asia/Seoul");
+        assert_eq!(window.ends_at, "2099-01-01 09:00:00");
+
+        // The same instant is 22:00 in New York: deferred to its 09:00 opening
+        let ny = &deliveries[0][1];
+        assert_eq!(
+            ny.send_at.map(schedule::to_rfc3339).as_deref(),
+            Some("2099-01-01T14:00:00Z")
+        );
+        assert_eq!(ny.window.as_ref().unwrap().timezone, "America/New_York");
+
+        let invalid: CreateMessageRequest = serde_json::from_value(serde_json::json!({
+            "messages": [{"emails": ["a@example.com"], "send_window": {"start": "9am", "end": "18:00"}}]
+        }))
+        .unwrap();
+        assert!(matches!(
+            resolve_deliveries(&invalid, Utc::now()),
+            Err(AppError::Validation(msg)) if msg.starts_with("messages[0].send_window")
+        ));
+    }
+
+    #[test]
+    fn test_resolve_deliveries_defers_outside_window() {
+        let payload: CreateMessageRequest = serde_json::from_value(serde_json::json!({
+            "messages": [{
+                "emails": [{"email": "a@example.com", "timezone": "Asia/Seoul"}],
+                "send_window": {"start": "22:00", "end": "06:00"}
+            }],
+            "scheduled_at": "2099-01-01T12:00:00Z"
+        }))
+        .unwrap();
+        let deliveries = resolve_deliveries(&payload, Utc::now()).unwrap();
+
+        // 21:00 KST is before the overnight window opens at 22:00 KST
+        let delivery = &deliveries[0][0];
+        assert_eq!(
+            delivery.send_at.map(schedule::to_rfc3339).as_deref(),
+            Some("2099-01-01T13:00:00Z")
+        );
+        assert_eq!(
+            delivery.window.as_ref().unwrap().ends_at,
+            "2099-01-01 21:00:00"
+        );
+    }
+
+    #[test]
+    fn test_resolve_deliveries_spread_over() {
+        let times = send_times(serde_json::json!({
+            "messages": [{
+                "emails": ["a@example.com", "b@example.com", "c@example.com", "d@example.com"],
+                "scheduled_at": "2099-01-01T00:00:00Z",
+                "spread_over_seconds": 3600
+            }]
+        }))
+        .unwrap();
+        assert_eq!(
+            times[0],
+            vec![
+                Some("2099-01-01T00:00:00Z".to_string()),
+                Some("2099-01-01T00:15:00Z".to_string()),
+                Some("2099-01-01T00:30:00Z".to_string()),
+                Some("2099-01-01T00:45:00Z".to_string()),
+            ]
+        );
+
+        // Without a send time, the first recipient is sent immediately
+        let times = send_times(serde_json::json!({
+            "messages": [{"emails": ["a@example.com", "b@example.com"], "spread_over_seconds": 600}]
+        }))
+        .unwrap();
+        assert_eq!(times[0][0], None);
+        assert!(times[0][1].is_some());
+
+        let too_long = send_times(serde_json::json!({
+            "messages": [{"emails": ["a@example.com"], "spread_over_seconds": u64::MAX}]
+        }));
+        assert!(matches!(too_long, Err(AppError::Validation(_))));
+    }
+
+    #[tokio::test]
+    async fn test_create_message_mixed_immediate_and_scheduled() {
+        let db = crate::config::init_test_db().await;
+        let (tx, mut rx) = tokio::sync::mpsc::channel(10);
+        let state = AppState::new(db.clone(), tx);
+
+        let payload: CreateMessageRequest = serde_json::from_value(serde_json::json!({
+            "messages": [{
+                "topic_id": "waves",
+                "subject": "s",
+                "content": "c",
+                "emails": [
+                    "now@example.com",
+                    {"email": "later@example.com", "send_after_seconds": 3600}
+                ]
+            }]
+        }))
+        .unwrap();
+        let response = process_messages(&state, payload).await.unwrap();
+        assert_eq!(response.success, 2);
+        assert!(response.scheduled);
+        assert!(response.scheduled_at.is_some());
+
+        // Only the immediate recipient is dispatched; the other waits for the scheduler
+        assert_eq!(rx.try_recv().unwrap().email, "now@example.com");
+        assert!(rx.try_recv().is_err());
+
+        let (status, pending): (i32, bool) = sqlx::query_as(
+            "SELECT status, scheduled_at > datetime('now', '+59 minutes') FROM email_requests WHERE email = ?",
+        )
+        .bind("later@example.com")
+        .fetch_one(&db)
+        .await
+        .unwrap();
+        assert_eq!(status, EmailMessageStatus::Created as i32);
+        assert!(pending);
+    }
+
+    fn template_message(template_id: Option<i32>, version: Option<i32>) -> Message {
+        Message {
+            topic_id: None,
+            from: None,
+            emails: Vec::new(),
+            subject: None,
+            content: None,
+            text: None,
+            template_id,
+            version,
+            attachments: Vec::new(),
+            cc: Vec::new(),
+            bcc: Vec::new(),
+            reply_to: Vec::new(),
+            headers: BTreeMap::new(),
+            configuration_set: None,
+            scheduled_at: None,
+            send_after_seconds: None,
+            send_window: None,
+            spread_over_seconds: None,
+            priority: None,
+            rate_limit_per_second: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_content_from_template_version() {
+        use crate::models::template::EmailTemplate;
+
+        let db = crate::config::init_test_db().await;
+        let (template, _) = EmailTemplate::create(&db, "t", "v1 {{name}}", "<p>v1</p>", None)
+            .await
+            .unwrap();
+        EmailTemplate::add_version(&db, template.id, None, "v2", "<p>v2</p>", Some("v2 text"))
+            .await
+            .unwrap();
+
+        let latest = resolve_content(&db, &template_message(Some(template.id), None))
+            .await
+            .unwrap();
+        assert_eq!(latest.subject, "v2");
+        assert_eq!(latest.text.as_deref(), Some("v2 text"));
+        assert_eq!(latest.template_version, Some(2));
+
+        let pinned = resolve_content(&db, &template_message(Some(template.id), Some(1)))
+            .await
+            .unwrap();
+        assert_eq!(pinned.subject, "v1 {{name}}");
+        assert_eq!(pinned.template_id, Some(template.id));
+        assert_eq!(pinned.template_version, Some(1));
+
+        let missing = resolve_content(&db, &template_message(Some(template.id), Some(9))).await;
+        assert!(matches!(missing, Err(AppError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_content_requires_inline_or_template() {
+        let db = crate::config::init_test_db().await;
+
+        let empty = resolve_content(&db, &template_message(None, None)).await;
+        assert!(matches!(empty, Err(AppError::Validation(_))));
+
+        let version_only = resolve_content(&db, &template_message(None, Some(1))).await;
+        assert!(matches!(version_only, Err(AppError::Validation(_))));
+
+        let mut both = template_message(Some(1), None);
+        both.subject = Some("inline".to_string());
+        let both = resolve_content(&db, &both).await;
+        assert!(matches!(both, Err(AppError::Validation(_))));
+    }
+
+    fn attachment_input(data: &str) -> AttachmentInput {
+        AttachmentInput {
+            filename: "report.csv".to_string(),
+            content_type: None,
+            content_id: None,
+            data: data.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_message_attachments_deserialization() {
+        let json = r#"{
+            "emails": ["test@example.com"],
+            "subject": "Invoice",
+            "content": "<img src=\"cid:logo\">",
+            "attachments": [
+                {"filename": "invoice.pdf", "content_type": "application/pdf", "data": "JVBERi0xLjQ="},
+                {"filename": "logo.png", "content_type": "image/png", "content_id": "logo", "data": "iVBORw=="}
+            ]
+        }"#;
+
+        let msg: Message = serde_json::from_str(json).unwrap();
+        assert_eq!(msg.attachments.len(), 2);
+        assert_eq!(msg.attachments[1].content_id.as_deref(), Some("logo"));
+    }
+
+    #[test]
+    fn test_decode_attachments() {
+        let mut inline = attachment_input("aGVs\nbG8=");
+        inline.content_type = Some("Image/PNG".to_string());
+        inline.content_id = Some("<logo>".to_string());
+
+        let decoded = decode_attachments(&[attachment_input("YSxiCjEsMg=="), inline]).unwrap();
+        assert_eq!(decoded[0].data, b"a,b\n1,2");
+        assert_eq!(decoded[0].content_type, DEFAULT_ATTACHMENT_CONTENT_TYPE);
+        assert_eq!(decoded[0].cid, None);
+        assert_eq!(decoded[1].data, b"hello");
+        assert_eq!(decoded[1].content_type, "image/png");
+        assert_eq!(decoded[1].cid.as_deref(), Some("logo"));
+    }
+
+    #[test]
+    fn test_decode_attachments_rejects_invalid_input() {
+        let invalid_data = decode_attachments(&[attachment_input("not base64!")]);
+        assert!(matches!(invalid_data, Err(AppError::Validation(_))));
+
+        let mut bad_name = attachment_input("YQ==");
+        bad_name.filename = "a\r\nBcc: x@example.com".to_string();
+        assert!(decode_attachments(&[bad_name]).is_err());
+
+        let mut bad_type = attachment_input("YQ==");
+        bad_type.content_type = Some("text/plain\r\nX: y".to_string());
+        assert!(decode_attachments(&[bad_type]).is_err());
+
+        let mut bad_cid = attachment_input("YQ==");
+        bad_cid.content_id = Some("a b".to_string());
+        assert!(decode_attachments(&[bad_cid]).is_err());
+    }
+
+    #[test]
+    fn test_message_size_limit() {
+        let files = vec![Attachment {
+            filename: "big.bin".to_string(),
+            content_type: DEFAULT_ATTACHMENT_CONTENT_TYPE.to_string(),
+            cid: None,
+            data: vec![0; MAX_MESSAGE_SIZE_BYTES],
+        }];
+        assert!(mime::estimate_size("s", "<p>c</p>", None, &files) > MAX_MESSAGE_SIZE_BYTES);
+        assert!(mime::estimate_size("s", "<p>c</p>", None, &[]) < MAX_MESSAGE_SIZE_BYTES);
+    }
+
+    #[test]
+    fn test_message_options() {
+        let json = r#"{
+            "emails": ["user@example.com"],
+            "subject": "Ticket #1",
+            "content": "<p>Hi</p>",
+            "cc": [" manager@example.com "],
+            "reply_to": ["support@example.com"]
+        }"#;
+        let msg: Message = serde_json::from_str(json).unwrap();
+
+        let options = message_options(&msg).unwrap();
+        assert_eq!(options.cc, vec!["manager@example.com"]);
+        assert!(options.bcc.is_empty());
+        assert_eq!(options.reply_to, vec!["support@example.com"]);
+    }
+
+    #[test]
+    fn test_message_options_rejects_invalid_addresses() {
+        for invalid in [
+            "",
+            "no-at-sign",
+            "a@b@c",
+            "a@example.com, b@example.com",
+            "a b@c.d",
+        ] {
+            let mut msg = template_message(None, None);
+            msg.cc = vec![invalid.to_string()];
+            assert!(
+                matches!(message_options(&msg), Err(AppError::Validation(_))),
+                "{invalid}"
+            );
+        }
+
+        let mut msg = template_message(None, None);
+        msg.bcc = (0..MAX_DESTINATIONS_PER_EMAIL)
+            .map(|i| format!("user{i}@example.com"))
+            .collect();
+        assert!(message_options(&msg).is_err());
+    }
+
+    #[test]
+    fn test_message_from_deserialization() {
+        let plain: Message =
+            serde_json::from_str(r#"{"emails": [], "from": "news@brand.example.com"}"#).unwrap();
+        assert!(
+            matches!(plain.from, Some(FromInput::Address(ref e)) if e == "news@brand.example.com")
+        );
+
+        let named: Message = serde_json::from_str(
+            r#"{"emails": [], "from": {"email": "news@brand.example.com", "name": "브랜드"}}"#,
+        )
+        .unwrap();
+        let from = from_address(named.from.as_ref().unwrap());
+        // Not in the allow-list of the test environment
+        assert!(matches!(from, Err(AppError::Validation(_))));
+    }
+
+    #[test]
+    fn test_is_allowed_sender() {
+        let allowed = vec![
+            "billing@shop.example.com".to_string(),
+            "brand.example.com".to_string(),
+            "@other.example.com".to_string(),
+        ];
+        let default_from = "noreply@example.com";
+
+        assert!(is_allowed_sender(
+            "noreply@example.com",
+            &allowed,
+            default_from
+        ));
+        assert!(is_allowed_sender(
+            "Billing@Shop.Example.com",
+            &allowed,
+            default_from
+        ));
+        assert!(is_allowed_sender(
+            "news@brand.example.com",
+            &allowed,
+            default_from
+        ));
+        assert!(is_allowed_sender(
+            "a@other.example.com",
+            &allowed,
+            default_from
+        ));
+
+        assert!(!is_allowed_sender(
+            "support@shop.example.com",
+            &allowed,
+            default_from
+        ));
+        assert!(!is_allowed_sender(
+            "a@evil-brand.example.com",
+            &allowed,
+            default_from
+        ));
+        assert!(!is_allowed_sender("other@example.com", &[], default_from));
+    }
+
+    #[test]
+    fn test_from_address_validation() {
+        let invalid = FromInput::Address("not-an-address".to_string());
+        assert!(from_address(&invalid).is_err());
+
+        let control = FromInput::Detailed {
+            email: "news@example.com".to_string(),
+            name: Some("Evil\r\nBcc: x@example.com".to_string()),
+        };
+        assert!(
+            matches!(from_address(&control), Err(AppError::Validation(msg)) if msg.contains("control"))
+        );
+    }
+
+    #[test]
+    fn test_validate_headers() {
+        let headers = BTreeMap::from([
+            ("List-Id".to_string(), "<news.example.com>".to_string()),
+            ("X-Campaign".to_string(), " spring ".to_string()),
+            ("In-Reply-To".to_string(), "<abc@example.com>".to_string()),
+        ]);
+        let validated = validate_headers(&headers).unwrap();
+        assert_eq!(
+            validated.get("X-Campaign").map(String::as_str),
+            Some("spring")
+        );
+        assert_eq!(validated.len(), 3);
+    }
+
+    #[test]
+    fn test_validate_headers_rejects_reserved_and_invalid() {
+        for (name, value) in [
+            ("From", "a@example.com"),
+            ("dkim-signature", "v=1"),
+            ("X-SES-CONFIGURATION-SET", "x"),
+            ("Bad Name", "x"),
+            ("X-Colon:", "x"),
+            ("X-Empty", " "),
+            ("X-Injected", "a\r\nBcc: x@example.com"),
+            ("X-Korean", "한글"),
+        ] {
+            let headers = BTreeMap::from([(name.to_string(), value.to_string())]);
+            assert!(
+                matches!(validate_headers(&headers), Err(AppError::Validation(_))),
+                "{name}"
+            );
+        }
+
+        let duplicate = BTreeMap::from([
+            ("X-Tag".to_string(), "a".to_string()),
+            ("x-tag".to_string(), "b".to_string()),
+        ]);
+        assert!(validate_headers(&duplicate).is_err());
+    }
+
+    #[test]
+    fn test_validate_configuration_set() {
+        assert_eq!(
+            validate_configuration_set(" marketing-ip_pool ").unwrap(),
+            "marketing-ip_pool"
+        );
+        assert!(validate_configuration_set("").is_err());
+        assert!(validate_configuration_set("has space").is_err());
+        assert!(validate_configuration_set(&"a".repeat(65)).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_applied_only_to_valid_requests() {
+        use crate::models::rate_limit::RateLimit;
+
+        let db = crate::config::init_test_db().await;
+        let (tx, _rx) = tokio::sync::mpsc::channel(10);
+        let state = AppState::new(db.clone(), tx);
+        let message = |topic_id: Option<&str>, content: Option<&str>| {
+            serde_json::json!({
+                "topic_id": topic_id,
+                "emails": ["a@example.com"],
+                "subject": "s",
+                "content": content,
+                "rate_limit_per_second": 5
+            })
+        };
+        let request = |messages: Vec<serde_json::Value>| -> CreateMessageRequest {
+            serde_json::from_value(serde_json::json!({ "messages": messages })).unwrap()
+        };
+
+        let untagged = process_messages(&state, request(vec![message(None, Some("c"))])).await;
+        assert!(matches!(untagged, Err(AppError::Validation(_))));
+
+        // The second message is invalid, so the first one's limit is not stored
+        let invalid = request(vec![
+            message(Some("promo"), Some("c")),
+            message(Some("other"), None),
+        ]);
+        let result = process_messages(&state, invalid).await;
+        assert!(matches!(result, Err(AppError::Validation(_))));
+        assert!(RateLimit::list(&db).await.unwrap().is_empty());
+
+        process_messages(&state, request(vec![message(Some("promo"), Some("c"))]))
+            .await
+            .unwrap();
+        let stored = RateLimit::get(&db, RateLimitScope::Topic, "promo")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.max_per_second, 5);
+    }
+
+    #[tokio::test]
+    async fn test_create_message_holds_sends_beyond_quota() {
+        let db = crate::config::init_test_db().await;
+        let (tx, mut rx) = tokio::sync::mpsc::channel(10);
+        let mut state = AppState::new(db.clone(), tx);
+        state.daily_quota = Arc::new(crate::services::quota::DailyQuota::new(3, 0));
+        let request = |emails: &[&str]| -> CreateMessageRequest {
+            serde_json::from_value(serde_json::json!({
+                "messages": [{"subject": "s", "content": "c", "emails": emails}]
+            }))
+            .unwrap()
+        };
+
+        let first = process_messages(&state, request(&["a@example.com", "b@example.com"]))
+            .await
+            .unwrap();
+        assert_eq!((first.success, first.held), (2, 0));
+
+        // Only one slot is left, even though nothing was sent yet
+        let second = process_messages(&state, request(&["c@example.com", "d@example.com"]))
+            .await
+            .unwrap();
+        assert_eq!((second.success, second.held), (2, 1));
+
+        let queued: Vec<String> = std::iter::from_fn(|| rx.try_recv().ok())
+            .map(|r| r.email)
+            .collect();
+        assert_eq!(queued, ["a@example.com", "b@example.com", "c@example.com"]);
+        let (status,): (i32,) =
+            sqlx::query_as("SELECT status FROM email_requests WHERE email = 'd@example.com'")
+                .fetch_one(&db)
+                .await
+                .unwrap();
+        assert_eq!(status, EmailMessageStatus::Created as i32);
+    }
+
+    fn idempotent_headers(key: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(IDEMPOTENCY_KEY_HEADER, key.parse().unwrap());
+        headers
+    }
+
+    #[tokio::test]
+    async fn test_create_message_idempotency_key() {
+        let db = crate::config::init_test_db().await;
+        let (tx, _rx) = tokio::sync::mpsc::channel(10);
+        let state = AppState::new(db, tx);
+        let body = Bytes::from_static(
+            br#"{"messages": [{"emails": ["a@example.com"], "subject": "s", "content": "c"}]}"#,
+        );
+
+        let first = create_message(
+            State(state.clone()),
+            idempotent_headers("order-1"),
+            body.clone(),
+        )
+        .await
+        .unwrap();
+        assert!(first.headers().get(IDEMPOTENT_REPLAYED_HEADER).is_none());
+
+        let replay = create_message(State(state.clone()), idempotent_headers("order-1"), body)
+            .await
+            .unwrap();
+        assert_eq!(replay.headers()[IDEMPOTENT_REPLAYED_HEADER], "true");
+
+        let (count,): (i32,) = sqlx::query_as("SELECT COUNT(*) FROM email_requests")
+            .fetch_one(&state.db_pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 1);
+
+        let other = Bytes::from_static(
+            br#"{"messages": [{"emails": ["b@example.com"], "subject": "s", "content": "c"}]}"#,
+        );
+        let result = create_message(State(state), idempotent_headers("order-1"), other).await;
+        assert!(matches!(result, Err(AppError::Conflict(_))));
+    }
+
+    #[tokio::test]
+    async fn test_failed_request_releases_idempotency_key() {
+        let db = crate::config::init_test_db().await;
+        let (tx, _rx) = tokio::sync::mpsc::channel(1);
+        let state = AppState::new(db, tx);
+        let empty = Bytes::from_static(br#"{"messages": []}"#);
+
+        let result = create_message(State(state.clone()), idempotent_headers("k"), empty).await;
+        assert!(matches!(result, Err(AppError::BadRequest(_))));
+        assert_eq!(
+            IdempotencyKey::reserve(&state.db_pool, "k", "x")
+                .await
+                .unwrap(),
+            Reservation::Reserved
+        );
+
+        let invalid = idempotent_headers("has space");
+        assert!(idempotency_key(&invalid).is_err());
+        assert_eq!(idempotency_key(&HeaderMap::new()).unwrap(), None);
+    }
+
+    #[test]
+    fn test_accept_recipients() {
+        let mut messages: Vec<Message> = serde_json::from_str(
+            r#"[
+                {"topic_id": "t1", "emails": [" A@Example.com", "a@example.com", "bad", "b@bücher.de"]},
+                {"topic_id": "t1", "emails": ["b@BÜCHER.de", ""]},
+                {"topic_id": "t2", "emails": ["a@example.com"]}
+            ]"#,
+        )
+        .unwrap();
+
+        let rejected = accept_recipients(&mut messages);
+
+        let emails = |msg: &Message| {
+            msg.emails
+                .iter()
+                .map(|r| r.email.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            emails(&messages[0]),
+            ["A@example.com", "b@xn--bcher-kva.de"]
+        );
+        assert!(messages[1].emails.is_empty());
+        assert_eq!(emails(&messages[2]), ["a@example.com"]);
+
+        let reasons: Vec<(&str, &str)> = rejected
+            .iter()
+            .map(|r| (r.email.as_str(), r.reason.as_str()))
+            .collect();
+        assert_eq!(
+            reasons,
+            [
+                ("a@example.com", "duplicate recipient in topic"),
+                ("bad", "missing '@'"),
+                ("b@BÜCHER.de", "duplicate recipient in topic"),
+                ("", "empty address"),
+            ]
+        );
+    }
 }
@@ -3,4 +3,9 @@
 pub mod event_handlers;
 pub mod health_handlers;
 pub mod message_handlers;
+pub mod rate_limit_handlers;
+pub mod schedule_handlers;
+pub mod suppression_handlers;
+pub mod template_handlers;
 pub mod topic_handlers;
+pub mod unsubscribe_handlers;
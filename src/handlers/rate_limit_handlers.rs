@@ -0,0 +1,138 @@
+//! Per-topic and per-sender rate limit handlers
+
+use axum::{
+    extract::{Path, State},
+    response::IntoResponse,
+    Json,
+};
+use serde::Deserialize;
+
+use crate::{
+    error::{AppError, AppResult},
+    models::rate_limit::{RateLimit, RateLimitScope},
+    state::AppState,
+};
+
+/// New emails-per-second cap of a topic or sender.
+#[derive(Debug, Deserialize)]
+pub struct SetRateLimitRequest {
+    pub max_per_second: u32,
+}
+
+/// Lists all topic and sender limits.
+pub async fn list_rate_limits(State(state): State<AppState>) -> AppResult<impl IntoResponse> {
+    let rate_limits = RateLimit::list(&state.db_pool).await?;
+    Ok(Json(serde_json::json!({ "rate_limits": rate_limits })))
+}
+
+/// Sets or changes a limit. It also applies to emails already queued.
+pub async fn set_rate_limit(
+    State(state): State<AppState>,
+    Path((scope, key)): Path<(String, String)>,
+    Json(payload): Json<SetRateLimitRequest>,
+) -> AppResult<impl IntoResponse> {
+    let scope = parse_scope(&scope)?;
+    let rate_limit = apply_rate_limit(&state, scope, &key, payload.max_per_second).await?;
+    Ok(Json(rate_limit))
+}
+
+/// Removes a limit (the topic or sender is then only capped globally).
+pub async fn delete_rate_limit(
+    State(state): State<AppState>,
+    Path((scope, key)): Path<(String, String)>,
+) -> AppResult<impl IntoResponse> {
+    let scope = parse_scope(&scope)?;
+    if !RateLimit::delete(&state.db_pool, scope, &key).await? {
+        return Err(AppError::NotFound(format!(
+            "No rate limit for {} {key}",
+            scope.as_str()
+        )));
+    }
+    state.rate_limiter.remove(scope, &key);
+    Ok(Json(serde_json::json!({"status": "ok"})))
+}
+
+/// Stores a limit and applies it to the running sender.
+pub async fn apply_rate_limit(
+    state: &AppState,
+    scope: RateLimitScope,
+    key: &str,
+    max_per_second: u32,
+) -> AppResult<RateLimit> {
+    validate_rate_limit(scope, key, max_per_second)?;
+
+    let rate_limit = RateLimit::upsert(&state.db_pool, scope, key, max_per_second).await?;
+    state.rate_limiter.set(scope, key, max_per_second);
+    Ok(rate_limit)
+}
+
+/// Checks a limit before it is stored.
+pub fn validate_rate_limit(scope: RateLimitScope, key: &str, max_per_second: u32) -> AppResult<()> {
+    if key.trim().is_empty() {
+        return Err(AppError::Validation(format!(
+            "{} is required",
+            scope.as_str()
+        )));
+    }
+    if max_per_second == 0 {
+        return Err(AppError::Validation(
+            "max_per_second must be at least 1 (stop the topic to pause it)".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn parse_scope(scope: &str) -> AppResult<RateLimitScope> {
+    RateLimitScope::parse(scope).ok_or_else(|| {
+        AppError::BadRequest(format!(
+            "Unknown rate limit scope '{scope}' (expected 'topic' or 'sender')"
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn test_state() -> AppState {
+        let (tx, _rx) = tokio::sync::mpsc::channel(1);
+        AppState::new(crate::config::init_test_db().await, tx)
+    }
+
+    #[tokio::test]
+    async fn test_set_and_delete_rate_limit() {
+        let state = test_state().await;
+        let path = || Path(("sender".to_string(), "News@Example.com".to_string()));
+
+        set_rate_limit(
+            State(state.clone()),
+            path(),
+            Json(SetRateLimitRequest { max_per_second: 5 }),
+        )
+        .await
+        .unwrap();
+        let stored = RateLimit::get(&state.db_pool, RateLimitScope::Sender, "news@example.com")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.max_per_second, 5);
+
+        let zero = set_rate_limit(
+            State(state.clone()),
+            path(),
+            Json(SetRateLimitRequest { max_per_second: 0 }),
+        )
+        .await;
+        assert!(matches!(zero, Err(AppError::Validation(_))));
+
+        delete_rate_limit(State(state.clone()), path())
+            .await
+            .unwrap();
+        let missing = delete_rate_limit(State(state.clone()), path()).await;
+        assert!(matches!(missing, Err(AppError::NotFound(_))));
+
+        let unknown =
+            delete_rate_limit(State(state), Path(("domain".to_string(), "x".to_string()))).await;
+        assert!(matches!(unknown, Err(AppError::BadRequest(_))));
+    }
+}
@@ -0,0 +1,381 @@
+//! Recurring schedule management handlers
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{AppError, AppResult},
+    handlers::message_handlers::{
+        accept_recipients, save_contents, Message, MAX_EMAILS_PER_REQUEST,
+    },
+    models::{
+        recurring::{NewRecurringSchedule, RecurringSchedule, ScheduleRecipient},
+        request::Priority,
+    },
+    services::schedule,
+    state::AppState,
+};
+
+/// Number of upcoming run times returned with a schedule.
+const NEXT_RUNS_COUNT: usize = 5;
+
+/// A message sent to its recipients at every occurrence of `cron`
+/// (5 fields, evaluated in `timezone`, default: `DEFAULT_TIMEZONE`).
+#[derive(Debug, Deserialize)]
+pub struct CreateScheduleRequest {
+    pub name: String,
+    pub cron: String,
+    pub timezone: Option<String>,
+    pub message: Message,
+}
+
+/// Schedule with its upcoming run times (UTC, empty while paused).
+#[derive(Serialize)]
+struct ScheduleDetailResponse {
+    #[serde(flatten)]
+    schedule: RecurringSchedule,
+    next_runs: Vec<String>,
+}
+
+impl From<RecurringSchedule> for ScheduleDetailResponse {
+    fn from(schedule: RecurringSchedule) -> Self {
+        let next_runs = if schedule.paused {
+            Vec::new()
+        } else {
+            upcoming_runs(&schedule.cron, &schedule.timezone, NEXT_RUNS_COUNT)
+        };
+        Self {
+            schedule,
+            next_runs,
+        }
+    }
+}
+
+/// Creates a recurring schedule.
+///
+/// The message content is saved once; each run creates requests for the
+/// stored recipients under the topic `{topic_id}-{YYYYMMDDHHMM}` (local run time).
+pub async fn create_schedule(
+    State(state): State<AppState>,
+    Json(payload): Json<CreateScheduleRequest>,
+) -> AppResult<impl IntoResponse> {
+    let name = payload.name.trim();
+    if name.is_empty() {
+        return Err(AppError::Validation("name must not be empty".to_string()));
+    }
+    let cron =
+        schedule::parse_cron(&payload.cron).map_err(|e| AppError::Validation(e.to_string()))?;
+    let timezone = schedule::parse_timezone(payload.timezone.as_deref())
+        .map_err(|e| AppError::Validation(e.to_string()))?;
+
+    let mut message = payload.message;
+    validate_message(&message)?;
+    let rejected = accept_recipients(std::slice::from_mut(&mut message));
+    if let Some(first) = rejected.first() {
+        return Err(AppError::Validation(format!(
+            "Invalid recipient '{}' ({})",
+            first.email, first.reason
+        )));
+    }
+
+    let (contents, _) = save_contents(&state.db_pool, std::slice::from_ref(&message)).await?;
+    let content_id = contents
+        .first()
+        .and_then(|c| c.id)
+        .ok_or_else(|| AppError::Internal("Content was not saved".to_string()))?;
+
+    let recipients: Vec<ScheduleRecipient> = message
+        .emails
+        .into_iter()
+        .map(|recipient| ScheduleRecipient {
+            email: recipient.email,
+            variables: recipient.variables,
+        })
+        .collect();
+    let next_run_at = schedule::next_runs(&cron, timezone, Utc::now(), 1)
+        .first()
+        .map(|time| schedule::to_storage(*time));
+
+    let created = RecurringSchedule::create(
+        &state.db_pool,
+        NewRecurringSchedule {
+            name,
+            cron: cron.as_str(),
+            timezone: timezone.name(),
+            topic_id: message.topic_id.as_deref().unwrap_or_default(),
+            content_id,
+            recipients: &recipients,
+            next_run_at: next_run_at.as_deref(),
+        },
+    )
+    .await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(ScheduleDetailResponse::from(created)),
+    ))
+}
+
+/// Lists all schedules.
+pub async fn list_schedules(State(state): State<AppState>) -> AppResult<impl IntoResponse> {
+    let schedules = RecurringSchedule::list(&state.db_pool).await?;
+    Ok(Json(serde_json::json!({ "schedules": schedules })))
+}
+
+/// Returns a schedule with its next run times.
+pub async fn get_schedule(
+    State(state): State<AppState>,
+    Path(schedule_id): Path<i32>,
+) -> AppResult<impl IntoResponse> {
+    let schedule = RecurringSchedule::get(&state.db_pool, schedule_id)
+        .await?
+        .ok_or_else(|| schedule_not_found(schedule_id))?;
+    Ok(Json(ScheduleDetailResponse::from(schedule)))
+}
+
+/// Pauses a schedule (no runs are created until it is resumed).
+pub async fn pause_schedule(
+    State(state): State<AppState>,
+    Path(schedule_id): Path<i32>,
+) -> AppResult<impl IntoResponse> {
+    let schedule = RecurringSchedule::set_paused(&state.db_pool, schedule_id, true, None)
+        .await?
+        .ok_or_else(|| schedule_not_found(schedule_id))?;
+    Ok(Json(ScheduleDetailResponse::from(schedule)))
+}
+
+/// Resumes a paused schedule from its next occurrence (missed runs are skipped).
+pub async fn resume_schedule(
+    State(state): State<AppState>,
+    Path(schedule_id): Path<i32>,
+) -> AppResult<impl IntoResponse> {
+    let schedule = RecurringSchedule::get(&state.db_pool, schedule_id)
+        .await?
+        .ok_or_else(|| schedule_not_found(schedule_id))?;
+    let next_run_at = upcoming_runs(&schedule.cron, &schedule.timezone, 1)
+        .into_iter()
+        .next();
+
+    let schedule =
+        RecurringSchedule::set_paused(&state.db_pool, schedule_id, false, next_run_at.as_deref())
+            .await?
+            .ok_or_else(|| schedule_not_found(schedule_id))?;
+    Ok(Json(ScheduleDetailResponse::from(schedule)))
+}
+
+/// Deletes a schedule (requests of past runs are kept).
+pub async fn delete_schedule(
+    State(state): State<AppState>,
+    Path(schedule_id): Path<i32>,
+) -> AppResult<impl IntoResponse> {
+    if !RecurringSchedule::delete(&state.db_pool, schedule_id).await? {
+        return Err(schedule_not_found(schedule_id));
+    }
+    Ok(Json(serde_json::json!({"status": "ok"})))
+}
+
+/// Checks the parts of a message that differ for schedules: a topic is
+/// required and send times come from the cron expression only.
+fn validate_message(message: &Message) -> AppResult<()> {
+    if message
+        .topic_id
+        .as_deref()
+        .is_none_or(|t| t.trim().is_empty())
+    {
+        return Err(AppError::Validation(
+            "message.topic_id is required".to_string(),
+        ));
+    }
+    if message.emails.is_empty() {
+        return Err(AppError::Validation(
+            "message.emails must not be empty".to_string(),
+        ));
+    }
+    if message.emails.len() > MAX_EMAILS_PER_REQUEST {
+        return Err(AppError::Validation(format!(
+            "Max {MAX_EMAILS_PER_REQUEST} recipients per schedule"
+        )));
+    }
+    let has_send_time = |scheduled_at: Option<&String>, send_after_seconds: Option<u64>| {
+        scheduled_at.is_some() || send_after_seconds.is_some()
+    };
+    if has_send_time(message.scheduled_at.as_ref(), message.send_after_seconds)
+        || message
+            .emails
+            .iter()
+            .any(|r| has_send_time(r.scheduled_at.as_ref(), r.send_after_seconds))
+    {
+        return Err(AppError::Validation(
+            "scheduled_at and send_after_seconds are not supported in schedules".to_string(),
+        ));
+    }
+    if message.send_window.is_some()
+        || message.spread_over_seconds.is_some()
+        || message.rate_limit_per_second.is_some()
+        || message.emails.iter().any(|r| r.timezone.is_some())
+    {
+        return Err(AppError::Validation(
+            "send_window, spread_over_seconds, rate_limit_per_second and recipient timezone are not supported in schedules"
+                .to_string(),
+        ));
+    }
+    if message.priority == Some(Priority::Transactional) {
+        return Err(AppError::Validation(
+            "schedules are always sent with bulk priority".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Next run times (storage format, UTC) of a stored cron expression.
+fn upcoming_runs(cron: &str, timezone: &str, count: usize) -> Vec<String> {
+    let (Ok(cron), Ok(timezone)) = (schedule::parse_cron(cron), timezone.parse()) else {
+        return Vec::new();
+    };
+    schedule::next_runs(&cron, timezone, Utc::now(), count)
+        .into_iter()
+        .map(schedule::to_storage)
+        .collect()
+}
+
+fn schedule_not_found(schedule_id: i32) -> AppError {
+    AppError::NotFound(format!("Schedule {schedule_id} not found"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::response::IntoResponse;
+
+    fn request(json: serde_json::Value) -> CreateScheduleRequest {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn test_validate_message() {
+        let message = |json: serde_json::Value| request(json).message;
+        let valid = serde_json::json!({
+            "name": "weekly",
+            "cron": "0 9 * * MON",
+            "message": {"topic_id": "news", "emails": ["a@example.com"], "subject": "s", "content": "c"}
+        });
+        assert!(validate_message(&message(valid.clone())).is_ok());
+
+        let mut no_topic = valid.clone();
+        no_topic["message"]["topic_id"] = serde_json::Value::Null;
+        assert!(validate_message(&message(no_topic)).is_err());
+
+        let mut no_emails = valid.clone();
+        no_emails["message"]["emails"] = serde_json::json!([]);
+        assert!(validate_message(&message(no_emails)).is_err());
+
+        let mut delayed = valid;
+        delayed["message"]["emails"] =
+            serde_json::json!([{"email": "a@example.com", "send_after_seconds": 60}]);
+        assert!(validate_message(&message(delayed)).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_create_pause_and_resume_schedule() {
+        let db = crate::config::init_test_db().await;
+        let (tx, _rx) = tokio::sync::mpsc::channel(1);
+        let state = AppState::new(db.clone(), tx);
+
+        let payload = request(serde_json::json!({
+            "name": " weekly ",
+            "cron": "0 9 * * MON",
+            "timezone": "America/New_York",
+            "message": {
+                "topic_id": "news",
+                "emails": [" A@Example.com ", {"email": "b@example.com", "variables": {"name": "B"}}],
+                "subject": "Hi {{name}}",
+                "content": "<p>Hi</p>"
+            }
+        }));
+        let response = create_schedule(State(state.clone()), Json(payload))
+            .await
+            .unwrap()
+            .into_response();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let created = RecurringSchedule::list(&db).await.unwrap().remove(0);
+        assert_eq!(created.name, "weekly");
+        assert_eq!(created.timezone, "America/New_York");
+        assert_eq!(created.recipient_count, 2);
+        assert!(created.next_run_at.is_some());
+        let (recipients,): (String,) =
+            sqlx::query_as("SELECT recipients FROM recurring_schedules WHERE id = ?")
+                .bind(created.id)
+                .fetch_one(&db)
+                .await
+                .unwrap();
+        assert!(recipients.contains("\"A@example.com\""));
+
+        pause_schedule(State(state.clone()), Path(created.id))
+            .await
+            .unwrap();
+        let paused = RecurringSchedule::get(&db, created.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(paused.paused);
+        assert!(ScheduleDetailResponse::from(paused).next_runs.is_empty());
+
+        sqlx::query("UPDATE recurring_schedules SET next_run_at = '2000-01-03 14:00:00'")
+            .execute(&db)
+            .await
+            .unwrap();
+        resume_schedule(State(state.clone()), Path(created.id))
+            .await
+            .unwrap();
+        let resumed = RecurringSchedule::get(&db, created.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!resumed.paused);
+        // Missed runs are skipped
+        assert_eq!(resumed.next_run_at, created.next_run_at);
+        assert_eq!(
+            ScheduleDetailResponse::from(resumed).next_runs.len(),
+            NEXT_RUNS_COUNT
+        );
+
+        assert!(matches!(
+            get_schedule(State(state), Path(999)).await,
+            Err(AppError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_create_schedule_rejects_invalid_input() {
+        let db = crate::config::init_test_db().await;
+        let (tx, _rx) = tokio::sync::mpsc::channel(1);
+        let state = AppState::new(db, tx);
+
+        for (cron, timezone, email) in [
+            ("0 9 * *", None, "a@example.com"),
+            ("0 9 * * MON", Some("Mars/Olympus"), "a@example.com"),
+            ("0 9 * * MON", None, "not-an-address"),
+        ] {
+            let payload = request(serde_json::json!({
+                "name": "weekly",
+                "cron": cron,
+                "timezone": timezone,
+                "message": {"topic_id": "news", "emails": [email], "subject": "s", "content": "c"}
+            }));
+            assert!(
+                matches!(
+                    create_schedule(State(state.clone()), Json(payload)).await,
+                    Err(AppError::Validation(_))
+                ),
+                "{cron} {email}"
+            );
+        }
+    }
+}
@@ -0,0 +1,141 @@
+//! Suppression list management handlers
+
+use axum::{
+    extract::{Path, Query, State},
+    response::IntoResponse,
+    Json,
+};
+use serde::Deserialize;
+
+use crate::{
+    error::{AppError, AppResult},
+    models::suppression::{Suppression, SuppressionReason},
+    services::address,
+    state::AppState,
+};
+
+const DEFAULT_LIST_LIMIT: i32 = 100;
+const MAX_LIST_LIMIT: i32 = 1000;
+
+#[derive(Debug, Deserialize)]
+pub struct ListSuppressionsParams {
+    pub limit: Option<i32>,
+    pub offset: Option<i32>,
+}
+
+/// Addresses to suppress manually.
+#[derive(Debug, Deserialize)]
+pub struct AddSuppressionsRequest {
+    pub emails: Vec<String>,
+}
+
+/// Lists suppressed recipients, most recent first.
+pub async fn list_suppressions(
+    State(state): State<AppState>,
+    Query(params): Query<ListSuppressionsParams>,
+) -> AppResult<impl IntoResponse> {
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_LIST_LIMIT)
+        .clamp(1, MAX_LIST_LIMIT);
+    let offset = params.offset.unwrap_or(0).max(0);
+
+    let suppressions = Suppression::list(&state.db_pool, limit, offset).await?;
+    Ok(Json(serde_json::json!({ "suppressions": suppressions })))
+}
+
+/// Adds addresses to the suppression list (already suppressed ones are left as-is).
+pub async fn add_suppressions(
+    State(state): State<AppState>,
+    Json(payload): Json<AddSuppressionsRequest>,
+) -> AppResult<impl IntoResponse> {
+    if payload.emails.is_empty() {
+        return Err(AppError::Validation("emails must not be empty".to_string()));
+    }
+    let emails = payload
+        .emails
+        .iter()
+        .map(|email| {
+            address::normalize(email).map_err(|e| {
+                AppError::Validation(format!("Invalid email address '{}' ({e})", email.trim()))
+            })
+        })
+        .collect::<AppResult<Vec<_>>>()?;
+
+    let mut added = 0;
+    for email in &emails {
+        if Suppression::add(&state.db_pool, email, SuppressionReason::Manual, None).await? {
+            added += 1;
+        }
+    }
+    Ok(Json(serde_json::json!({ "added": added })))
+}
+
+/// Returns the suppression of an address.
+pub async fn get_suppression(
+    State(state): State<AppState>,
+    Path(email): Path<String>,
+) -> AppResult<impl IntoResponse> {
+    let suppression = Suppression::get(&state.db_pool, &email)
+        .await?
+        .ok_or_else(|| suppression_not_found(&email))?;
+    Ok(Json(suppression))
+}
+
+/// Removes an address from the suppression list so it can receive emails again.
+pub async fn delete_suppression(
+    State(state): State<AppState>,
+    Path(email): Path<String>,
+) -> AppResult<impl IntoResponse> {
+    if !Suppression::delete(&state.db_pool, &email).await? {
+        return Err(suppression_not_found(&email));
+    }
+    Ok(Json(serde_json::json!({"status": "ok"})))
+}
+
+fn suppression_not_found(email: &str) -> AppError {
+    AppError::NotFound(format!("{email} is not suppressed"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn test_state() -> AppState {
+        let (tx, _rx) = tokio::sync::mpsc::channel(1);
+        AppState::new(crate::config::init_test_db().await, tx)
+    }
+
+    #[tokio::test]
+    async fn test_add_and_delete_suppressions() {
+        let state = test_state().await;
+        let payload = AddSuppressionsRequest {
+            emails: vec!["a@example.com".to_string(), "A@example.com".to_string()],
+        };
+        add_suppressions(State(state.clone()), Json(payload))
+            .await
+            .unwrap();
+
+        let found = Suppression::get(&state.db_pool, "a@example.com")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.reason, "Manual");
+
+        delete_suppression(State(state.clone()), Path("A@Example.com".to_string()))
+            .await
+            .unwrap();
+        let result = delete_suppression(State(state), Path("a@example.com".to_string())).await;
+        assert!(matches!(result, Err(AppError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_add_suppressions_rejects_invalid_address() {
+        let state = test_state().await;
+        let payload = AddSuppressionsRequest {
+            emails: vec!["not-an-address".to_string()],
+        };
+        let result = add_suppressions(State(state), Json(payload)).await;
+        assert!(matches!(result, Err(AppError::Validation(_))));
+    }
+}
@@ -0,0 +1,194 @@
+//! Email template management handlers
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{AppError, AppResult},
+    models::template::{EmailTemplate, EmailTemplateVersion},
+    state::AppState,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct CreateTemplateRequest {
+    pub name: String,
+    pub subject: String,
+    pub content: String,
+    pub text: Option<String>,
+}
+
+/// Creates a new immutable version; `name` optionally renames the template.
+#[derive(Debug, Deserialize)]
+pub struct UpdateTemplateRequest {
+    pub name: Option<String>,
+    pub subject: String,
+    pub content: String,
+    pub text: Option<String>,
+}
+
+/// Template with its current (latest) version and all version numbers.
+#[derive(Serialize)]
+struct TemplateDetailResponse {
+    #[serde(flatten)]
+    template: EmailTemplate,
+    current: EmailTemplateVersion,
+    versions: Vec<i32>,
+}
+
+/// Creates a template and its first version.
+pub async fn create_template(
+    State(state): State<AppState>,
+    Json(payload): Json<CreateTemplateRequest>,
+) -> AppResult<impl IntoResponse> {
+    validate_fields(Some(&payload.name), &payload.subject, &payload.content)?;
+
+    let (template, current) = EmailTemplate::create(
+        &state.db_pool,
+        payload.name.trim(),
+        &payload.subject,
+        &payload.content,
+        payload.text.as_deref(),
+    )
+    .await
+    .map_err(|e| map_name_conflict(e, &payload.name))?;
+
+    let versions = vec![current.version];
+    Ok((
+        StatusCode::CREATED,
+        Json(TemplateDetailResponse {
+            template,
+            current,
+            versions,
+        }),
+    ))
+}
+
+/// Lists all active templates.
+pub async fn list_templates(State(state): State<AppState>) -> AppResult<impl IntoResponse> {
+    let templates = EmailTemplate::list(&state.db_pool).await?;
+    Ok(Json(serde_json::json!({ "templates": templates })))
+}
+
+/// Returns a template with its latest version.
+pub async fn get_template(
+    State(state): State<AppState>,
+    Path(template_id): Path<i32>,
+) -> AppResult<impl IntoResponse> {
+    let template = EmailTemplate::get(&state.db_pool, template_id)
+        .await?
+        .ok_or_else(|| template_not_found(template_id))?;
+    let (current, versions) = tokio::join!(
+        EmailTemplateVersion::get(&state.db_pool, template_id, None),
+        EmailTemplate::versions(&state.db_pool, template_id)
+    );
+    let current = current?.ok_or_else(|| template_not_found(template_id))?;
+
+    Ok(Json(TemplateDetailResponse {
+        template,
+        current,
+        versions: versions?,
+    }))
+}
+
+/// Returns a specific version of a template.
+pub async fn get_template_version(
+    State(state): State<AppState>,
+    Path((template_id, version)): Path<(i32, i32)>,
+) -> AppResult<impl IntoResponse> {
+    let version = EmailTemplateVersion::get(&state.db_pool, template_id, Some(version))
+        .await?
+        .ok_or_else(|| {
+            AppError::NotFound(format!(
+                "Template {template_id} version {version} not found"
+            ))
+        })?;
+    Ok(Json(version))
+}
+
+/// Publishes a new version of a template.
+pub async fn update_template(
+    State(state): State<AppState>,
+    Path(template_id): Path<i32>,
+    Json(payload): Json<UpdateTemplateRequest>,
+) -> AppResult<impl IntoResponse> {
+    validate_fields(payload.name.as_deref(), &payload.subject, &payload.content)?;
+
+    let name = payload.name.as_deref().map(str::trim);
+    let version = EmailTemplate::add_version(
+        &state.db_pool,
+        template_id,
+        name,
+        &payload.subject,
+        &payload.content,
+        payload.text.as_deref(),
+    )
+    .await
+    .map_err(|e| map_name_conflict(e, name.unwrap_or_default()))?
+    .ok_or_else(|| template_not_found(template_id))?;
+
+    Ok(Json(version))
+}
+
+/// Deletes a template (existing versions stay referenced by sent contents).
+pub async fn delete_template(
+    State(state): State<AppState>,
+    Path(template_id): Path<i32>,
+) -> AppResult<impl IntoResponse> {
+    if !EmailTemplate::delete(&state.db_pool, template_id).await? {
+        return Err(template_not_found(template_id));
+    }
+    Ok(Json(serde_json::json!({"status": "ok"})))
+}
+
+fn validate_fields(name: Option<&str>, subject: &str, content: &str) -> AppResult<()> {
+    if name.is_some_and(|n| n.trim().is_empty()) {
+        return Err(AppError::Validation("name must not be empty".to_string()));
+    }
+    if subject.is_empty() || content.is_empty() {
+        return Err(AppError::Validation(
+            "subject and content are required".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn map_name_conflict(err: sqlx::Error, name: &str) -> AppError {
+    if err
+        .as_database_error()
+        .is_some_and(sqlx::error::DatabaseError::is_unique_violation)
+    {
+        AppError::Conflict(format!("Template name '{name}' already exists"))
+    } else {
+        AppError::Database(err)
+    }
+}
+
+fn template_not_found(template_id: i32) -> AppError {
+    AppError::NotFound(format!("Template {template_id} not found"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_template_request_name_is_optional() {
+        let json = r#"{"subject": "Hi", "content": "<p>Hi</p>"}"#;
+        let req: UpdateTemplateRequest = serde_json::from_str(json).unwrap();
+        assert!(req.name.is_none());
+    }
+
+    #[test]
+    fn test_validate_fields() {
+        assert!(validate_fields(Some("name"), "s", "c").is_ok());
+        assert!(validate_fields(None, "s", "c").is_ok());
+        assert!(validate_fields(Some("  "), "s", "c").is_err());
+        assert!(validate_fields(None, "", "c").is_err());
+        assert!(validate_fields(None, "s", "").is_err());
+    }
+}
@@ -1,22 +1,96 @@
 //! Topic management handlers
 
+use std::{collections::HashMap, sync::atomic::Ordering};
+
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     response::IntoResponse,
     Json,
 };
 
+use chrono::{DateTime, Utc};
+
 use crate::{
     error::{AppError, AppResult},
-    models::{request::EmailRequest, result::EmailResult},
+    models::{
+        rate_limit::{RateLimit, RateLimitScope},
+        request::{EmailMessageStatus, EmailRequest},
+        result::EmailResult,
+        template::TemplateUsage,
+    },
+    services::schedule,
     state::AppState,
 };
 
 /// Topic statistics response.
 #[derive(serde::Serialize)]
 struct TopicStatsResponse {
-    request_counts: std::collections::HashMap<String, i32>,
-    result_counts: std::collections::HashMap<String, i32>,
+    request_counts: HashMap<String, i32>,
+    result_counts: HashMap<String, i32>,
+    /// Request counts per template version used in the topic
+    template_versions: Vec<TemplateUsage>,
+    progress: TopicProgress,
+    /// Emails-per-second cap of the topic, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rate_limit_per_second: Option<i32>,
+}
+
+/// Delivery progress of a topic.
+#[derive(Debug, serde::Serialize)]
+struct TopicProgress {
+    total: i32,
+    /// Sent, failed, stopped or skipped
+    completed: i32,
+    /// Waiting for their send time or being sent
+    pending: i32,
+    /// Held until the topic is resumed
+    paused: i32,
+    percent: f64,
+    /// Latest of the last scheduled send time and the time needed to send
+    /// the pending emails at the current send rate or the topic's rate limit
+    /// (RFC 3339, UTC)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    estimated_completion_at: Option<String>,
+}
+
+impl TopicProgress {
+    fn new(
+        request_counts: &HashMap<String, i32>,
+        last_scheduled_at: Option<DateTime<Utc>>,
+        rate_limit_per_second: Option<i32>,
+        send_rate: u64,
+        now: DateTime<Utc>,
+    ) -> Self {
+        let count =
+            |status: EmailMessageStatus| request_counts.get(status.as_str()).copied().unwrap_or(0);
+        let total = request_counts.values().sum();
+        let pending = count(EmailMessageStatus::Created) + count(EmailMessageStatus::Processed);
+        let paused = count(EmailMessageStatus::Paused);
+        let completed = total - pending - paused;
+        let percent = if total > 0 {
+            (f64::from(completed) * 1000.0 / f64::from(total)).round() / 10.0
+        } else {
+            0.0
+        };
+
+        let estimated_completion_at = (pending > 0).then(|| {
+            let send_rate = i32::try_from(send_rate).unwrap_or(i32::MAX);
+            let rate = rate_limit_per_second
+                .map_or(send_rate, |limit| limit.min(send_rate))
+                .max(1);
+            let sending = now + chrono::Duration::seconds(i64::from(pending / rate));
+            schedule::to_rfc3339(last_scheduled_at.map_or(sending, |last| last.max(sending)))
+        });
+
+        Self {
+            total,
+            completed,
+            pending,
+            paused,
+            percent,
+            estimated_completion_at,
+        }
+    }
 }
 
 /// Returns email statistics for a specific topic.
@@ -30,22 +104,41 @@ pub async fn get_topic(
         return Err(AppError::BadRequest("topic_id is required".to_string()));
     }
 
-    // Execute both queries in parallel
-    let (request_result, result_result) = tokio::join!(
+    // Execute all queries in parallel
+    let (request_result, result_result, template_result, last_scheduled_result, rate_limit_result) = tokio::join!(
         EmailRequest::get_request_counts_by_topic_id(&state.db_pool, &topic_id),
-        EmailResult::get_result_counts_by_topic_id(&state.db_pool, &topic_id)
+        EmailResult::get_result_counts_by_topic_id(&state.db_pool, &topic_id),
+        TemplateUsage::get_by_topic_id(&state.db_pool, &topic_id),
+        EmailRequest::get_last_scheduled_at_by_topic_id(&state.db_pool, &topic_id),
+        RateLimit::get(&state.db_pool, RateLimitScope::Topic, &topic_id)
     );
 
     let request_counts = request_result?;
     let result_counts = result_result?;
+    let template_versions = template_result?;
+    let last_scheduled_at = last_scheduled_result?
+        .as_deref()
+        .and_then(schedule::from_storage);
+    let rate_limit_per_second = rate_limit_result?.map(|limit| limit.max_per_second);
+    let progress = TopicProgress::new(
+        &request_counts,
+        last_scheduled_at,
+        rate_limit_per_second,
+        state.send_rate.load(Ordering::Acquire),
+        Utc::now(),
+    );
 
     Ok(Json(TopicStatsResponse {
         request_counts,
         result_counts,
+        template_versions,
+        progress,
+        rate_limit_per_second,
     }))
 }
 
-/// Stops pending emails for a topic (only affects `Created` status).
+/// Stops pending and paused emails for a topic, and the ones already queued
+/// in memory for the email sender (those already handed to SES still go out).
 pub async fn stop_topic(
     State(state): State<AppState>,
     Path(topic_id): Path<String>,
@@ -54,29 +147,375 @@ pub async fn stop_topic(
         return Err(AppError::BadRequest("topic_id is required".to_string()));
     }
 
-    EmailRequest::stop_topic(&state.db_pool, &topic_id).await?;
-    Ok(Json(serde_json::json!({"status": "ok"})))
+    let stopped = EmailRequest::stop_topic(&state.db_pool, &topic_id).await?;
+    if let Some(max_id) = stopped.max_in_flight_id {
+        state.cancelled_topics.cancel(&topic_id, max_id);
+    }
+    Ok(Json(serde_json::json!({
+        "status": "ok",
+        "pending": stopped.pending,
+        "in_flight": stopped.in_flight,
+    })))
+}
+
+/// Send time of resumed emails.
+#[derive(Debug, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResumeSchedule {
+    /// Keep the original send time (emails already due are sent right away)
+    #[default]
+    Original,
+    Now,
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+pub struct ResumeTopicParams {
+    #[serde(default)]
+    pub schedule: ResumeSchedule,
+    /// Also resume emails stopped with `DELETE /v1/topics/{topic_id}`
+    #[serde(default)]
+    pub include_stopped: bool,
+}
+
+/// Pauses pending emails for a topic, and the ones already queued in memory
+/// for the email sender (those already handed to SES still go out).
+pub async fn pause_topic(
+    State(state): State<AppState>,
+    Path(topic_id): Path<String>,
+) -> AppResult<impl IntoResponse> {
+    if topic_id.is_empty() {
+        return Err(AppError::BadRequest("topic_id is required".to_string()));
+    }
+
+    let paused = EmailRequest::pause_topic(&state.db_pool, &topic_id).await?;
+    if let Some(max_id) = paused.max_in_flight_id {
+        state.cancelled_topics.pause(&topic_id, max_id);
+    }
+    Ok(Json(serde_json::json!({
+        "status": "ok",
+        "paused": paused.pending,
+        "in_flight": paused.in_flight,
+    })))
+}
+
+/// Returns paused (optionally also stopped) emails of a topic to the queue.
+pub async fn resume_topic(
+    State(state): State<AppState>,
+    Path(topic_id): Path<String>,
+    Query(params): Query<ResumeTopicParams>,
+) -> AppResult<impl IntoResponse> {
+    if topic_id.is_empty() {
+        return Err(AppError::BadRequest("topic_id is required".to_string()));
+    }
+
+    if params.include_stopped {
+        state.cancelled_topics.remove(&topic_id);
+    } else {
+        state.cancelled_topics.resume(&topic_id);
+    }
+    let resumed = EmailRequest::resume_topic(
+        &state.db_pool,
+        &topic_id,
+        params.include_stopped,
+        params.schedule == ResumeSchedule::Now,
+    )
+    .await?;
+    Ok(Json(
+        serde_json::json!({"status": "ok", "resumed": resumed}),
+    ))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::seed_scheduled_request;
 
     #[test]
     fn test_topic_stats_response_serialization() {
-        let mut request_counts = std::collections::HashMap::new();
+        let mut request_counts = HashMap::new();
         request_counts.insert("Sent".to_string(), 10);
 
-        let mut result_counts = std::collections::HashMap::new();
+        let mut result_counts = HashMap::new();
         result_counts.insert("Delivery".to_string(), 8);
 
         let response = TopicStatsResponse {
             request_counts,
             result_counts,
+            template_versions: vec![TemplateUsage {
+                template_id: 1,
+                version: 2,
+                count: 10,
+            }],
+            progress: TopicProgress::new(&HashMap::new(), None, None, 24, Utc::now()),
+            rate_limit_per_second: None,
         };
 
         let json = serde_json::to_string(&response).unwrap();
         assert!(json.contains("request_counts"));
         assert!(json.contains("result_counts"));
+        assert!(json.contains(r#""template_versions":[{"template_id":1,"version":2,"count":10}]"#));
+        assert!(json.contains(
+            r#""progress":{"total":0,"completed":0,"pending":0,"paused":0,"percent":0.0}"#
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_pause_and_resume_topic() {
+        use EmailMessageStatus::{Created, Sent};
+        let db = crate::config::init_test_db().await;
+        for (id, topic_id, email, status, scheduled_in) in [
+            (1, "t", "a@example.com", Created, "+1 day"),
+            (2, "t", "b@example.com", Created, "-1 minute"),
+            (3, "t", "c@example.com", Sent, "-1 minute"),
+            (4, "other", "d@example.com", Created, "-1 minute"),
+        ] {
+            seed_scheduled_request(&db, id, topic_id, email, status, scheduled_in).await;
+        }
+        let (tx, _rx) = tokio::sync::mpsc::channel(1);
+        let state = AppState::new(db.clone(), tx);
+        let topic = || Path("t".to_string());
+        let statuses = || async {
+            sqlx::query_as::<_, (i32,)>("SELECT status FROM email_requests ORDER BY id")
+                .fetch_all(&db)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|(status,)| status)
+                .collect::<Vec<_>>()
+        };
+
+        pause_topic(State(state.clone()), topic()).await.unwrap();
+        assert_eq!(statuses().await, [6, 6, 2, 0]);
+
+        // Resumed at the original send time
+        resume_topic(
+            State(state.clone()),
+            topic(),
+            Query(ResumeTopicParams::default()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(statuses().await, [0, 0, 2, 0]);
+        let (future,): (bool,) = sqlx::query_as(
+            "SELECT scheduled_at > datetime('now') FROM email_requests WHERE id = 1",
+        )
+        .fetch_one(&db)
+        .await
+        .unwrap();
+        assert!(future);
+
+        // Stopped emails only come back when asked for
+        pause_topic(State(state.clone()), topic()).await.unwrap();
+        stop_topic(State(state.clone()), topic()).await.unwrap();
+        assert_eq!(statuses().await, [4, 4, 2, 0]);
+        resume_topic(
+            State(state.clone()),
+            topic(),
+            Query(ResumeTopicParams::default()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(statuses().await, [4, 4, 2, 0]);
+
+        let params = ResumeTopicParams {
+            schedule: ResumeSchedule::Now,
+            include_stopped: true,
+        };
+        resume_topic(State(state), topic(), Query(params))
+            .await
+            .unwrap();
+        assert_eq!(statuses().await, [0, 0, 2, 0]);
+        let (due,): (i32,) = sqlx::query_as(
+            "SELECT COUNT(*) FROM email_requests WHERE topic_id = 't' AND scheduled_at <= datetime('now')",
+        )
+        .fetch_one(&db)
+        .await
+        .unwrap();
+        assert_eq!(due, 3);
+    }
+
+    fn queued(id: i32) -> EmailRequest {
+        EmailRequest {
+            id: Some(id),
+            topic_id: Some("t".to_string()),
+            content_id: Some(1),
+            email: "a@example.com".to_string(),
+            subject: std::sync::Arc::default(),
+            content: std::sync::Arc::default(),
+            text: None,
+            attachments: std::sync::Arc::default(),
+            options: std::sync::Arc::default(),
+            scheduled_at: None,
+            status: EmailMessageStatus::Processed as i32,
+            error: None,
+            message_id: None,
+            variables: None,
+            send_window: None,
+            priority: crate::models::request::Priority::Bulk,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stop_topic_cancels_rows_claimed_concurrently() {
+        use EmailMessageStatus::{Created, Processed};
+        let db = crate::config::init_test_db().await;
+        for (id, topic_id, email, status, scheduled_in) in [
+            (1, "t", "a@example.com", Processed, "-1 minute"),
+            (2, "t", "b@example.com", Created, "-1 minute"),
+            (3, "t", "c@example.com", Created, "-1 minute"),
+        ] {
+            seed_scheduled_request(&db, id, topic_id, email, status, scheduled_in).await;
+        }
+        let (tx, _rx) = tokio::sync::mpsc::channel(1);
+        let state = AppState::new(db.clone(), tx);
+
+        // A scheduler claim racing the stop
+        let claim =
+            sqlx::query("UPDATE email_requests SET status = 1 WHERE status = 0").execute(&db);
+        let (stopped, claimed) = tokio::join!(
+            stop_topic(State(state.clone()), Path("t".to_string())),
+            claim
+        );
+        stopped.unwrap();
+        claimed.unwrap();
+
+        // Whichever ran first, every email still in flight is cancelled
+        let processed: Vec<(i32,)> =
+            sqlx::query_as("SELECT id FROM email_requests WHERE status = 1")
+                .fetch_all(&db)
+                .await
+                .unwrap();
+        assert!(!processed.is_empty());
+        for (id,) in processed {
+            assert!(state.cancelled_topics.is_cancelled(&queued(id)), "{id}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stop_topic_cancels_in_flight() {
+        use EmailMessageStatus::{Created, Processed, Sent};
+        let db = crate::config::init_test_db().await;
+        for (id, topic_id, email, status, scheduled_in) in [
+            (1, "t", "a@example.com", Processed, "-1 minute"),
+            (2, "t", "b@example.com", Processed, "-1 minute"),
+            (3, "t", "c@example.com", Created, "+1 hour"),
+            (4, "t", "d@example.com", Sent, "-1 minute"),
+        ] {
+            seed_scheduled_request(&db, id, topic_id, email, status, scheduled_in).await;
+        }
+        let (tx, _rx) = tokio::sync::mpsc::channel(1);
+        let state = AppState::new(db, tx);
+
+        let response = stop_topic(State(state.clone()), Path("t".to_string()))
+            .await
+            .unwrap()
+            .into_response();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["pending"], 1);
+        assert_eq!(body["in_flight"], 2);
+
+        assert!(state.cancelled_topics.is_cancelled(&queued(2)));
+        assert!(!state.cancelled_topics.is_cancelled(&queued(5)));
+
+        let params = ResumeTopicParams {
+            include_stopped: true,
+            ..ResumeTopicParams::default()
+        };
+        resume_topic(State(state.clone()), Path("t".to_string()), Query(params))
+            .await
+            .unwrap();
+        assert!(!state.cancelled_topics.is_cancelled(&queued(2)));
+    }
+
+    #[tokio::test]
+    async fn test_pause_topic_holds_in_flight() {
+        use EmailMessageStatus::{Created, Processed};
+        let db = crate::config::init_test_db().await;
+        for (id, topic_id, email, status, scheduled_in) in [
+            (1, "t", "a@example.com", Processed, "-1 minute"),
+            (2, "t", "b@example.com", Created, "-1 minute"),
+        ] {
+            seed_scheduled_request(&db, id, topic_id, email, status, scheduled_in).await;
+        }
+        let (tx, _rx) = tokio::sync::mpsc::channel(1);
+        let state = AppState::new(db, tx);
+
+        let response = pause_topic(State(state.clone()), Path("t".to_string()))
+            .await
+            .unwrap()
+            .into_response();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["paused"], 1);
+        assert_eq!(body["in_flight"], 1);
+        assert_eq!(
+            state.cancelled_topics.held_status(&queued(1)),
+            Some(EmailMessageStatus::Paused)
+        );
+
+        resume_topic(
+            State(state.clone()),
+            Path("t".to_string()),
+            Query(ResumeTopicParams::default()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(state.cancelled_topics.held_status(&queued(1)), None);
+    }
+
+    #[test]
+    fn test_topic_progress() {
+        let counts = HashMap::from([
+            ("Sent".to_string(), 25),
+            ("Failed".to_string(), 5),
+            ("Created".to_string(), 60),
+            ("Processed".to_string(), 10),
+        ]);
+        let now = Utc::now();
+        let with_paused = HashMap::from([("Sent".to_string(), 2), ("Paused".to_string(), 8)]);
+        let progress = TopicProgress::new(&with_paused, None, None, 24, now);
+        assert_eq!(
+            (progress.completed, progress.pending, progress.paused),
+            (2, 0, 8)
+        );
+
+        let last = now + chrono::Duration::hours(6);
+
+        let progress = TopicProgress::new(&counts, Some(last), None, 24, now);
+        assert_eq!(progress.total, 100);
+        assert_eq!(progress.completed, 30);
+        assert_eq!(progress.pending, 70);
+        assert!((progress.percent - 30.0).abs() < f64::EPSILON);
+        assert_eq!(
+            progress.estimated_completion_at,
+            Some(schedule::to_rfc3339(last))
+        );
+
+        // Nothing scheduled: estimated from the sending rate
+        let progress = TopicProgress::new(&counts, None, None, 24, now);
+        assert!(progress.estimated_completion_at.unwrap() >= schedule::to_rfc3339(now));
+        // 70 pending at 1/s
+        let progress = TopicProgress::new(&counts, None, Some(1), 24, now);
+        assert_eq!(
+            progress.estimated_completion_at,
+            Some(schedule::to_rfc3339(now + chrono::Duration::seconds(70)))
+        );
+
+        // 70 pending at the current send rate (lowered by SES throttling)
+        let progress = TopicProgress::new(&counts, None, Some(50), 7, now);
+        assert_eq!(
+            progress.estimated_completion_at,
+            Some(schedule::to_rfc3339(now + chrono::Duration::seconds(10)))
+        );
+
+        let done = HashMap::from([("Sent".to_string(), 3)]);
+        let progress = TopicProgress::new(&done, None, None, 24, now);
+        assert!((progress.percent - 100.0).abs() < f64::EPSILON);
+        assert_eq!(progress.estimated_completion_at, None);
     }
 }
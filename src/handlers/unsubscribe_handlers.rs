@@ -0,0 +1,178 @@
+//! Unsubscribe handlers (confirmation page and RFC 8058 one-click POST)
+
+use axum::{
+    extract::{Query, State},
+    response::Html,
+};
+use serde::Deserialize;
+use tracing::info;
+
+use crate::{
+    config::APP_CONFIG,
+    error::{AppError, AppResult},
+    models::{
+        request::EmailRequest,
+        result::EmailResult,
+        suppression::{Suppression, SuppressionReason},
+    },
+    services::tracking,
+    state::AppState,
+};
+
+const UNSUBSCRIBE_STATUS: &str = "Unsubscribe";
+
+const CONFIRM_PAGE: &str = r#"<!DOCTYPE html>
+<html><head><meta charset="utf-8"><meta name="viewport" content="width=device-width, initial-scale=1"><title>Unsubscribe</title></head>
+<body style="font-family: sans-serif; text-align: center; padding: 48px 16px;">
+<h1>Unsubscribe</h1>
+<p>Do you want to stop receiving these emails?</p>
+<form method="post">
+<input type="hidden" name="List-Unsubscribe" value="One-Click">
+<button type="submit">Unsubscribe</button>
+</form>
+</body></html>"#;
+
+const DONE_PAGE: &str = r#"<!DOCTYPE html>
+<html><head><meta charset="utf-8"><meta name="viewport" content="width=device-width, initial-scale=1"><title>Unsubscribed</title></head>
+<body style="font-family: sans-serif; text-align: center; padding: 48px 16px;">
+<h1>You have been unsubscribed</h1>
+<p>You will no longer receive these emails.</p>
+</body></html>"#;
+
+#[derive(Debug, Deserialize)]
+pub struct UnsubscribeQueryParams {
+    pub request_id: Option<String>,
+    pub token: Option<String>,
+}
+
+/// Shows a confirmation page.
+///
+/// GET never unsubscribes, so link scanners that prefetch URLs are harmless.
+pub async fn unsubscribe_page(
+    Query(query): Query<UnsubscribeQueryParams>,
+) -> AppResult<Html<&'static str>> {
+    verified_request_id(&query)?;
+    Ok(Html(CONFIRM_PAGE))
+}
+
+/// Records the unsubscribe (one-click from mail clients or the confirmation form)
+/// and suppresses the recipient.
+pub async fn unsubscribe(
+    State(state): State<AppState>,
+    Query(query): Query<UnsubscribeQueryParams>,
+) -> AppResult<Html<&'static str>> {
+    let request_id = verified_request_id(&query)?;
+
+    if !EmailResult::exists(&state.db_pool, request_id, UNSUBSCRIBE_STATUS).await? {
+        EmailResult {
+            id: None,
+            request_id,
+            status: UNSUBSCRIBE_STATUS.to_owned(),
+            raw: None,
+        }
+        .save(&state.db_pool)
+        .await?;
+
+        if let Some(email) = EmailRequest::get_email(&state.db_pool, request_id).await? {
+            Suppression::add(
+                &state.db_pool,
+                &email,
+                SuppressionReason::Unsubscribe,
+                Some(request_id),
+            )
+            .await?;
+        }
+        info!("Unsubscribed: request_id={request_id}");
+    }
+
+    Ok(Html(DONE_PAGE))
+}
+
+fn verified_request_id(query: &UnsubscribeQueryParams) -> AppResult<i32> {
+    query
+        .request_id
+        .as_deref()
+        .and_then(|id| id.parse::<i32>().ok())
+        .filter(|&id| {
+            query.token.as_deref().is_some_and(|token| {
+                tracking::verify_unsubscribe_token(&APP_CONFIG.tracking_secret, id, token)
+            })
+        })
+        .ok_or_else(|| AppError::BadRequest("Invalid unsubscribe link".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::seed_request;
+
+    async fn setup_state() -> AppState {
+        let db = crate::config::init_test_db().await;
+        seed_request(&db, 7, "topic", "user@example.com", None).await;
+        let (tx, _rx) = tokio::sync::mpsc::channel(1);
+        AppState::new(db, tx)
+    }
+
+    fn query(request_id: &str, token: &str) -> UnsubscribeQueryParams {
+        UnsubscribeQueryParams {
+            request_id: Some(request_id.to_string()),
+            token: Some(token.to_string()),
+        }
+    }
+
+    fn valid_query() -> UnsubscribeQueryParams {
+        query(
+            "7",
+            &tracking::unsubscribe_token(&APP_CONFIG.tracking_secret, 7),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_unsubscribe_page_does_not_record() {
+        let state = setup_state().await;
+
+        let page = unsubscribe_page(Query(valid_query())).await.unwrap();
+        assert!(page.0.contains("<form method=\"post\">"));
+        assert!(!EmailResult::exists(&state.db_pool, 7, UNSUBSCRIBE_STATUS)
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_unsubscribe_records_once() {
+        let state = setup_state().await;
+
+        for _ in 0..2 {
+            let page = unsubscribe(State(state.clone()), Query(valid_query()))
+                .await
+                .unwrap();
+            assert!(page.0.contains("You have been unsubscribed"));
+        }
+
+        let (count,): (i32,) = sqlx::query_as(
+            "SELECT COUNT(*) FROM email_results WHERE request_id = 7 AND status = 'Unsubscribe'",
+        )
+        .fetch_one(&state.db_pool)
+        .await
+        .unwrap();
+        assert_eq!(count, 1);
+
+        let suppression = Suppression::get(&state.db_pool, "user@example.com")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(suppression.reason, "Unsubscribe");
+    }
+
+    #[tokio::test]
+    async fn test_unsubscribe_rejects_invalid_token() {
+        let state = setup_state().await;
+
+        let result = unsubscribe(State(state.clone()), Query(query("7", "forged"))).await;
+        assert!(matches!(result, Err(AppError::BadRequest(_))));
+
+        let other = tracking::unsubscribe_token(&APP_CONFIG.tracking_secret, 8);
+        let result = unsubscribe_page(Query(query("7", &other))).await;
+        assert!(matches!(result, Err(AppError::BadRequest(_))));
+    }
+}
@@ -33,15 +33,26 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let _sentry_guard = init_sentry();
 
     let db_pool = init_db().await?;
-    let (tx_send, rx_send) = tokio::sync::mpsc::channel(APP_CONFIG.send_channel_buffer);
+    let (tx_send, rx_send) = services::queue::channel(APP_CONFIG.send_channel_buffer);
     let (tx_post_send, rx_post_send) =
         tokio::sync::mpsc::channel(APP_CONFIG.post_send_channel_buffer);
 
-    spawn_scheduler(tx_send.clone(), db_pool.clone());
-    spawn_email_sender(rx_send, tx_post_send);
-    spawn_post_processor(rx_post_send, db_pool.clone());
+    let state = state::AppState::new(db_pool.clone(), tx_send.clone());
+    state
+        .rate_limiter
+        .load(&models::rate_limit::RateLimit::list(&db_pool).await?);
+
+    spawn_scheduler(tx_send, db_pool.clone(), state.daily_quota.clone());
+    spawn_email_sender(
+        rx_send,
+        tx_post_send,
+        state.rate_limiter.clone(),
+        state.daily_quota.clone(),
+        state.cancelled_topics.clone(),
+        state.send_rate.clone(),
+    );
+    spawn_post_processor(rx_post_send, db_pool);
 
-    let state = state::AppState::new(db_pool, tx_send);
     let app = app::app(state);
 
     let port = &APP_CONFIG.server_port;
@@ -119,20 +130,33 @@ fn init_sentry() -> sentry::ClientInitGuard {
 }
 
 fn spawn_scheduler(
-    tx: tokio::sync::mpsc::Sender<models::request::EmailRequest>,
+    tx: services::queue::SendQueue,
     db: sqlx::SqlitePool,
+    daily_quota: std::sync::Arc<services::quota::DailyQuota>,
 ) {
     tokio::spawn(async move {
-        schedule_pre_send_message(&tx, db).await;
+        schedule_pre_send_message(&tx, db, &daily_quota).await;
     });
 }
 
 fn spawn_email_sender(
-    rx: tokio::sync::mpsc::Receiver<models::request::EmailRequest>,
+    rx: services::queue::PriorityReceiver,
     tx: tokio::sync::mpsc::Sender<models::request::EmailRequest>,
+    rate_limiter: std::sync::Arc<services::limiter::RateLimiter>,
+    daily_quota: std::sync::Arc<services::quota::DailyQuota>,
+    cancelled_topics: std::sync::Arc<services::queue::CancelledTopics>,
+    send_rate: std::sync::Arc<std::sync::atomic::AtomicU64>,
 ) {
     tokio::spawn(async move {
-        receive_send_message(rx, tx).await;
+        receive_send_message(
+            rx,
+            tx,
+            rate_limiter,
+            daily_quota,
+            cancelled_topics,
+            send_rate,
+        )
+        .await;
     });
 }
 
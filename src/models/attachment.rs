@@ -0,0 +1,200 @@
+//! Email attachment model (payloads deduplicated by SHA-256)
+
+use std::{collections::HashMap, fmt::Write as _};
+
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+
+/// File attached to a content.
+///
+/// `cid` is the MIME `Content-ID` for inline images referenced as
+/// `<img src="cid:...">`; regular attachments leave it empty.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Attachment {
+    pub filename: String,
+    pub content_type: String,
+    pub cid: Option<String>,
+    pub data: Vec<u8>,
+}
+
+#[derive(sqlx::FromRow)]
+struct AttachmentRow {
+    content_id: i32,
+    filename: String,
+    content_type: String,
+    cid: Option<String>,
+    data: Vec<u8>,
+}
+
+impl Attachment {
+    /// Returns the hex-encoded SHA-256 of the payload (deduplication key).
+    pub fn sha256(&self) -> String {
+        Sha256::digest(&self.data)
+            .iter()
+            .fold(String::with_capacity(64), |mut hex, b| {
+                let _ = write!(hex, "{b:02x}");
+                hex
+            })
+    }
+
+    /// Links attachments to their contents in a single transaction.
+    ///
+    /// Identical payloads are stored once in `email_attachments` and shared.
+    pub async fn save_for_contents(
+        db_pool: &SqlitePool,
+        items: &[(i32, &[Self])],
+    ) -> Result<(), sqlx::Error> {
+        if items.iter().all(|(_, attachments)| attachments.is_empty()) {
+            return Ok(());
+        }
+
+        let mut tx = db_pool.begin().await?;
+
+        for (content_id, attachments) in items {
+            for (position, attachment) in attachments.iter().enumerate() {
+                // No-op update on conflict so RETURNING yields the existing row's ID
+                let (attachment_id,): (i64,) = sqlx::query_as(
+                    "INSERT INTO email_attachments (sha256, size, data, created_at)
+                     VALUES (?, ?, ?, datetime('now'))
+                     ON CONFLICT(sha256) DO UPDATE SET sha256 = excluded.sha256
+                     RETURNING id",
+                )
+                .bind(attachment.sha256())
+                .bind(i64::try_from(attachment.data.len()).unwrap_or(i64::MAX))
+                .bind(&attachment.data)
+                .fetch_one(&mut *tx)
+                .await?;
+
+                sqlx::query(
+                    "INSERT INTO email_content_attachments (content_id, attachment_id, filename, content_type, cid, position)
+                     VALUES (?, ?, ?, ?, ?, ?)",
+                )
+                .bind(content_id)
+                .bind(attachment_id)
+                .bind(&attachment.filename)
+                .bind(&attachment.content_type)
+                .bind(&attachment.cid)
+                .bind(i64::try_from(position).unwrap_or(i64::MAX))
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Loads attachments for the given contents, keyed by content ID.
+    pub async fn find_by_content_ids(
+        db_pool: &SqlitePool,
+        content_ids: &[i32],
+    ) -> Result<HashMap<i32, Vec<Self>>, sqlx::Error> {
+        if content_ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let placeholders = vec!["?"; content_ids.len()].join(",");
+        let sql = format!(
+            "SELECT ca.content_id, ca.filename, ca.content_type, ca.cid, a.data
+             FROM email_content_attachments ca
+             JOIN email_attachments a ON ca.attachment_id = a.id
+             WHERE ca.content_id IN ({placeholders})
+             ORDER BY ca.content_id, ca.position"
+        );
+
+        let mut query = sqlx::query_as::<_, AttachmentRow>(&sql);
+        for id in content_ids {
+            query = query.bind(id);
+        }
+
+        let mut result: HashMap<i32, Vec<Self>> = HashMap::new();
+        for row in query.fetch_all(db_pool).await? {
+            result.entry(row.content_id).or_default().push(Self {
+                filename: row.filename,
+                content_type: row.content_type,
+                cid: row.cid,
+                data: row.data,
+            });
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::init_test_db;
+    use crate::models::content::EmailContent;
+
+    fn attachment(filename: &str, data: &[u8]) -> Attachment {
+        Attachment {
+            filename: filename.to_string(),
+            content_type: "application/pdf".to_string(),
+            cid: None,
+            data: data.to_vec(),
+        }
+    }
+
+    async fn insert_contents(db: &SqlitePool, count: usize) -> Vec<i32> {
+        let contents = (0..count)
+            .map(|i| EmailContent {
+                subject: format!("Subject {i}"),
+                content: format!("Content {i}"),
+                ..Default::default()
+            })
+            .collect();
+        EmailContent::save_batch(contents, db)
+            .await
+            .unwrap()
+            .into_iter()
+            .filter_map(|c| c.id)
+            .collect()
+    }
+
+    #[test]
+    fn test_sha256_hex() {
+        let hash = attachment("a.txt", b"abc").sha256();
+        assert_eq!(
+            hash,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_identical_payloads_are_stored_once() {
+        let db = init_test_db().await;
+        let ids = insert_contents(&db, 2).await;
+
+        let first = [attachment("invoice.pdf", b"%PDF-1.4 same")];
+        let second = [
+            attachment("copy.pdf", b"%PDF-1.4 same"),
+            attachment("other.csv", b"a,b\n1,2"),
+        ];
+        Attachment::save_for_contents(&db, &[(ids[0], &first), (ids[1], &second)])
+            .await
+            .unwrap();
+
+        let (stored,): (i32,) = sqlx::query_as("SELECT COUNT(*) FROM email_attachments")
+            .fetch_one(&db)
+            .await
+            .unwrap();
+        assert_eq!(stored, 2);
+
+        let loaded = Attachment::find_by_content_ids(&db, &ids).await.unwrap();
+        assert_eq!(loaded[&ids[0]], first.to_vec());
+        assert_eq!(loaded[&ids[1]], second.to_vec());
+    }
+
+    #[tokio::test]
+    async fn test_find_by_content_ids_without_attachments() {
+        let db = init_test_db().await;
+        let ids = insert_contents(&db, 1).await;
+
+        let loaded = Attachment::find_by_content_ids(&db, &ids).await.unwrap();
+        assert!(loaded.is_empty());
+        assert!(Attachment::find_by_content_ids(&db, &[])
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
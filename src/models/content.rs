@@ -1,17 +1,73 @@
 //! Email content model for deduplication
 
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 use sqlx::SqlitePool;
 use tracing::debug;
 
 use crate::constants::BATCH_INSERT_SIZE;
 
-/// Email content entity (subject + body)
-#[derive(Clone, Debug, Deserialize, Serialize)]
+/// Email content entity (subject + HTML body + optional plain-text body)
+///
+/// `template_id`/`template_version` record the template version the content
+/// was created from (`None` for inline content).
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct EmailContent {
     pub id: Option<i32>,
     pub subject: String,
     pub content: String,
+    /// Plain-text alternative; derived from `content` at send time when `None`.
+    pub text: Option<String>,
+    pub template_id: Option<i32>,
+    pub template_version: Option<i32>,
+    #[serde(default)]
+    pub options: MessageOptions,
+}
+
+/// Message-level delivery options shared by every recipient of a content.
+///
+/// Stored as JSON in `email_contents.options` (`NULL` when all defaults).
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct MessageOptions {
+    /// Sender identity (`None` = `AWS_SES_FROM_EMAIL`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<FromAddress>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cc: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bcc: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reply_to: Vec<String>,
+    /// Custom headers (e.g. `List-Id`, `X-Campaign`) added to every send.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    /// SES configuration set (`None` = `AWS_SES_CONFIGURATION_SET`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub configuration_set: Option<String>,
+}
+
+/// Sender address with an optional display name.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct FromAddress {
+    pub email: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+impl MessageOptions {
+    /// Serializes the options for storage (`None` when nothing is set).
+    pub fn to_json(&self) -> Option<String> {
+        (self != &Self::default())
+            .then(|| serde_json::to_string(self).ok())
+            .flatten()
+    }
+
+    /// Parses the stored `options` column (defaults when `NULL` or invalid).
+    pub fn from_json(raw: Option<&str>) -> Self {
+        raw.and_then(|s| serde_json::from_str(s).ok())
+            .unwrap_or_default()
+    }
 }
 
 impl EmailContent {
@@ -19,12 +75,16 @@ impl EmailContent {
     #[cfg(test)]
     pub async fn save(self, db_pool: &SqlitePool) -> Result<Self, sqlx::Error> {
         let row: (i64,) = sqlx::query_as(
-            "INSERT INTO email_contents (subject, content, created_at)
-             VALUES (?, ?, datetime('now'))
+            "INSERT INTO email_contents (subject, content, text, template_id, template_version, options, created_at)
+             VALUES (?, ?, ?, ?, ?, ?, datetime('now'))
              RETURNING id",
         )
         .bind(&self.subject)
         .bind(&self.content)
+        .bind(&self.text)
+        .bind(self.template_id)
+        .bind(self.template_version)
+        .bind(self.options.to_json())
         .fetch_one(db_pool)
         .await?;
 
@@ -54,17 +114,23 @@ impl EmailContent {
             let chunk_size = chunk.len();
 
             let placeholders = (0..chunk_size)
-                .map(|_| "(?, ?, datetime('now'))")
+                .map(|_| "(?, ?, ?, ?, ?, ?, datetime('now'))")
                 .collect::<Vec<_>>()
                 .join(", ");
 
             let sql = format!(
-                "INSERT INTO email_contents (subject, content, created_at) VALUES {placeholders}"
+                "INSERT INTO email_contents (subject, content, text, template_id, template_version, options, created_at) VALUES {placeholders}"
             );
 
             let mut query = sqlx::query(&sql);
             for c in chunk {
-                query = query.bind(&c.subject).bind(&c.content);
+                query = query
+                    .bind(&c.subject)
+                    .bind(&c.content)
+                    .bind(&c.text)
+                    .bind(c.template_id)
+                    .bind(c.template_version)
+                    .bind(c.options.to_json());
             }
             query.execute(&mut *tx).await?;
 
@@ -92,28 +158,10 @@ impl EmailContent {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use sqlx::sqlite::SqlitePoolOptions;
+    use crate::config::init_test_db;
 
     async fn setup_db() -> SqlitePool {
-        let pool = SqlitePoolOptions::new()
-            .max_connections(1)
-            .connect("sqlite::memory:")
-            .await
-            .unwrap();
-
-        sqlx::query(
-            "CREATE TABLE email_contents (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                subject VARCHAR(255) NOT NULL,
-                content TEXT NOT NULL,
-                created_at DATETIME NOT NULL DEFAULT (datetime('now'))
-            )",
-        )
-        .execute(&pool)
-        .await
-        .unwrap();
-
-        pool
+        init_test_db().await
     }
 
     #[tokio::test]
@@ -124,6 +172,7 @@ mod tests {
             id: None,
             subject: "Test Subject".to_string(),
             content: "<p>Test Content</p>".to_string(),
+            ..Default::default()
         };
 
         let saved = content.save(&db).await.unwrap();
@@ -140,11 +189,13 @@ mod tests {
                 id: None,
                 subject: "Subject 1".to_string(),
                 content: "Content 1".to_string(),
+                ..Default::default()
             },
             EmailContent {
                 id: None,
                 subject: "Subject 2".to_string(),
                 content: "Content 2".to_string(),
+                ..Default::default()
             },
         ];
 
@@ -163,6 +214,7 @@ mod tests {
                 id: None,
                 subject: format!("Subject {i}"),
                 content: format!("Content {i}"),
+                ..Default::default()
             })
             .collect();
 
@@ -188,6 +240,7 @@ mod tests {
             id: None,
             subject: "Single Subject".to_string(),
             content: "Single Content".to_string(),
+            ..Default::default()
         }];
 
         let saved = EmailContent::save_batch(contents, &db).await.unwrap();
@@ -204,6 +257,7 @@ mod tests {
             id: None,
             subject: "Hello 'World' \"Test\" <>&".to_string(),
             content: "<p>HTML content with émojis 🎉 and 한글</p>".to_string(),
+            ..Default::default()
         };
 
         let saved = content.save(&db).await.unwrap();
@@ -220,6 +274,7 @@ mod tests {
                 id: None,
                 subject: format!("Subject {i}"),
                 content: format!("Content {i}"),
+                ..Default::default()
             })
             .collect();
 
@@ -240,6 +295,7 @@ mod tests {
             id: None,
             subject: "Long Content Test".to_string(),
             content: long_content.clone(),
+            ..Default::default()
         };
 
         let saved = content.save(&db).await.unwrap();
@@ -251,4 +307,92 @@ mod tests {
             .unwrap();
         assert_eq!(row.0.len(), 100_000);
     }
+
+    #[tokio::test]
+    async fn test_save_batch_stores_text() {
+        let db = setup_db().await;
+
+        let contents = vec![
+            EmailContent {
+                id: None,
+                subject: "With text".to_string(),
+                content: "<p>Hello</p>".to_string(),
+                text: Some("Hello".to_string()),
+                ..Default::default()
+            },
+            EmailContent {
+                id: None,
+                subject: "Without text".to_string(),
+                content: "<p>Hello</p>".to_string(),
+                ..Default::default()
+            },
+        ];
+        EmailContent::save_batch(contents, &db).await.unwrap();
+
+        let rows: Vec<(Option<String>,)> =
+            sqlx::query_as("SELECT text FROM email_contents ORDER BY id")
+                .fetch_all(&db)
+                .await
+                .unwrap();
+        assert_eq!(rows[0].0.as_deref(), Some("Hello"));
+        assert_eq!(rows[1].0, None);
+    }
+
+    #[tokio::test]
+    async fn test_save_batch_stores_options() {
+        let db = setup_db().await;
+
+        let options = MessageOptions {
+            from: Some(FromAddress {
+                email: "news@brand.example.com".to_string(),
+                name: Some("브랜드 뉴스".to_string()),
+            }),
+            cc: vec!["cc@example.com".to_string()],
+            reply_to: vec!["support@example.com".to_string()],
+            headers: BTreeMap::from([("X-Campaign".to_string(), "spring".to_string())]),
+            configuration_set: Some("marketing".to_string()),
+            ..Default::default()
+        };
+        let contents = vec![
+            EmailContent {
+                subject: "With options".to_string(),
+                content: "<p>Hello</p>".to_string(),
+                options: options.clone(),
+                ..Default::default()
+            },
+            EmailContent {
+                subject: "Without options".to_string(),
+                content: "<p>Hello</p>".to_string(),
+                ..Default::default()
+            },
+        ];
+        EmailContent::save_batch(contents, &db).await.unwrap();
+
+        let rows: Vec<(Option<String>,)> =
+            sqlx::query_as("SELECT options FROM email_contents ORDER BY id")
+                .fetch_all(&db)
+                .await
+                .unwrap();
+        assert_eq!(MessageOptions::from_json(rows[0].0.as_deref()), options);
+        assert_eq!(rows[1].0, None);
+    }
+
+    #[test]
+    fn test_message_options_json() {
+        assert_eq!(MessageOptions::default().to_json(), None);
+        assert_eq!(MessageOptions::from_json(None), MessageOptions::default());
+        assert_eq!(
+            MessageOptions::from_json(Some("not json")),
+            MessageOptions::default()
+        );
+
+        let options = MessageOptions {
+            bcc: vec!["audit@example.com".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            options.to_json().as_deref(),
+            Some(r#"{"bcc":["audit@example.com"]}"#)
+        );
+    }
 }
@@ -0,0 +1,199 @@
+//! Idempotency keys for safely retrying `POST /v1/messages`
+
+use std::fmt::Write as _;
+
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+
+use crate::constants::{IDEMPOTENCY_KEY_IN_PROGRESS_TIMEOUT_SECS, IDEMPOTENCY_KEY_RETENTION_HOURS};
+
+/// Outcome of reserving an idempotency key.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Reservation {
+    /// First use of the key: the caller processes the request.
+    Reserved,
+    /// Another request with the key has not finished yet.
+    InProgress { request_hash: String },
+    /// The key was already used; `response` is the stored response JSON.
+    Completed {
+        request_hash: String,
+        response: String,
+    },
+}
+
+/// Idempotency keys stored with the request hash and the response they produced.
+pub struct IdempotencyKey;
+
+impl IdempotencyKey {
+    /// Returns the hex-encoded SHA-256 of a request body.
+    pub fn request_hash(body: &[u8]) -> String {
+        Sha256::digest(body)
+            .iter()
+            .fold(String::with_capacity(64), |mut hex, b| {
+                let _ = write!(hex, "{b:02x}");
+                hex
+            })
+    }
+
+    /// Atomically reserves a key, or returns how it was used before.
+    ///
+    /// Keys older than the retention window are purged first and can be reused.
+    /// A reservation left without a response for longer than
+    /// `IDEMPOTENCY_KEY_IN_PROGRESS_TIMEOUT_SECS` is stale and is taken over.
+    pub async fn reserve(
+        db_pool: &SqlitePool,
+        key: &str,
+        request_hash: &str,
+    ) -> Result<Reservation, sqlx::Error> {
+        sqlx::query("DELETE FROM idempotency_keys WHERE created_at < datetime('now', ?)")
+            .bind(format!("-{IDEMPOTENCY_KEY_RETENTION_HOURS} hours"))
+            .execute(db_pool)
+            .await?;
+
+        let inserted = sqlx::query(
+            "INSERT INTO idempotency_keys (idempotency_key, request_hash, created_at)
+             VALUES (?, ?, datetime('now'))
+             ON CONFLICT(idempotency_key) DO NOTHING",
+        )
+        .bind(key)
+        .bind(request_hash)
+        .execute(db_pool)
+        .await?;
+        if inserted.rows_affected() > 0 {
+            return Ok(Reservation::Reserved);
+        }
+
+        let taken_over = sqlx::query(
+            "UPDATE idempotency_keys SET request_hash = ?, created_at = datetime('now')
+             WHERE idempotency_key = ? AND response IS NULL AND created_at < datetime('now', ?)",
+        )
+        .bind(request_hash)
+        .bind(key)
+        .bind(format!(
+            "-{IDEMPOTENCY_KEY_IN_PROGRESS_TIMEOUT_SECS} seconds"
+        ))
+        .execute(db_pool)
+        .await?;
+        if taken_over.rows_affected() > 0 {
+            return Ok(Reservation::Reserved);
+        }
+
+        let (request_hash, response): (String, Option<String>) = sqlx::query_as(
+            "SELECT request_hash, response FROM idempotency_keys WHERE idempotency_key = ?",
+        )
+        .bind(key)
+        .fetch_one(db_pool)
+        .await?;
+
+        Ok(match response {
+            Some(response) => Reservation::Completed {
+                request_hash,
+                response,
+            },
+            None => Reservation::InProgress { request_hash },
+        })
+    }
+
+    /// Stores the response of a reserved key for later replays.
+    pub async fn complete(
+        db_pool: &SqlitePool,
+        key: &str,
+        response: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE idempotency_keys SET response = ? WHERE idempotency_key = ?")
+            .bind(response)
+            .bind(key)
+            .execute(db_pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Releases a reserved key after a failed request so the client can retry.
+    pub async fn release(db_pool: &SqlitePool, key: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM idempotency_keys WHERE idempotency_key = ? AND response IS NULL")
+            .bind(key)
+            .execute(db_pool)
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::init_test_db;
+
+    #[tokio::test]
+    async fn test_reserve_complete_and_replay() {
+        let db = init_test_db().await;
+        let hash = IdempotencyKey::request_hash(b"{}");
+
+        assert_eq!(
+            IdempotencyKey::reserve(&db, "key-1", &hash).await.unwrap(),
+            Reservation::Reserved
+        );
+        assert_eq!(
+            IdempotencyKey::reserve(&db, "key-1", &hash).await.unwrap(),
+            Reservation::InProgress {
+                request_hash: hash.clone()
+            }
+        );
+
+        IdempotencyKey::complete(&db, "key-1", r#"{"total":1}"#)
+            .await
+            .unwrap();
+        assert_eq!(
+            IdempotencyKey::reserve(&db, "key-1", "other")
+                .await
+                .unwrap(),
+            Reservation::Completed {
+                request_hash: hash,
+                response: r#"{"total":1}"#.to_string()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_release_and_expiry() {
+        let db = init_test_db().await;
+
+        IdempotencyKey::reserve(&db, "key-1", "a").await.unwrap();
+        IdempotencyKey::release(&db, "key-1").await.unwrap();
+        assert_eq!(
+            IdempotencyKey::reserve(&db, "key-1", "b").await.unwrap(),
+            Reservation::Reserved
+        );
+
+        sqlx::query(
+            "UPDATE idempotency_keys SET response = '{}', created_at = datetime('now', '-25 hours')",
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+        assert_eq!(
+            IdempotencyKey::reserve(&db, "key-1", "c").await.unwrap(),
+            Reservation::Reserved
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stale_reservation_is_taken_over() {
+        let db = init_test_db().await;
+
+        IdempotencyKey::reserve(&db, "key-1", "a").await.unwrap();
+        sqlx::query("UPDATE idempotency_keys SET created_at = datetime('now', '-10 minutes')")
+            .execute(&db)
+            .await
+            .unwrap();
+        assert_eq!(
+            IdempotencyKey::reserve(&db, "key-1", "a").await.unwrap(),
+            Reservation::Reserved
+        );
+        assert_eq!(
+            IdempotencyKey::reserve(&db, "key-1", "a").await.unwrap(),
+            Reservation::InProgress {
+                request_hash: "a".to_string()
+            }
+        );
+    }
+}
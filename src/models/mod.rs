@@ -1,5 +1,12 @@
-//! Data models for email contents, requests and results
+//! Data models for email contents, attachments, requests, results, suppressions,
+//! templates, idempotency keys, recurring schedules and rate limits
 
+pub mod attachment;
 pub mod content;
+pub mod idempotency;
+pub mod rate_limit;
+pub mod recurring;
 pub mod request;
 pub mod result;
+pub mod suppression;
+pub mod template;
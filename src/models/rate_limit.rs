@@ -0,0 +1,150 @@
+//! Per-topic and per-sender rate limits
+
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+/// What a rate limit applies to.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RateLimitScope {
+    /// Requests of a topic
+    Topic,
+    /// Requests sent from an address or any address of a domain
+    Sender,
+}
+
+impl RateLimitScope {
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Topic => "topic",
+            Self::Sender => "sender",
+        }
+    }
+
+    #[must_use]
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "topic" => Some(Self::Topic),
+            "sender" => Some(Self::Sender),
+            _ => None,
+        }
+    }
+
+    /// Normalizes a key: topics are kept as-is, senders are trimmed and lowercased.
+    #[must_use]
+    pub fn normalize(self, key: &str) -> String {
+        match self {
+            Self::Topic => key.to_string(),
+            Self::Sender => key.trim().to_lowercase(),
+        }
+    }
+}
+
+/// Stored rate limit (emails per second).
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct RateLimit {
+    pub scope: String,
+    pub key: String,
+    pub max_per_second: i32,
+    pub updated_at: String,
+}
+
+impl RateLimit {
+    /// Creates or replaces the limit of `key`.
+    pub async fn upsert(
+        db_pool: &SqlitePool,
+        scope: RateLimitScope,
+        key: &str,
+        max_per_second: u32,
+    ) -> Result<Self, sqlx::Error> {
+        sqlx::query_as(
+            "INSERT INTO rate_limits (scope, key, max_per_second, updated_at)
+             VALUES (?, ?, ?, datetime('now'))
+             ON CONFLICT(scope, key) DO UPDATE
+             SET max_per_second = excluded.max_per_second, updated_at = excluded.updated_at
+             RETURNING scope, key, max_per_second, updated_at",
+        )
+        .bind(scope.as_str())
+        .bind(scope.normalize(key))
+        .bind(max_per_second)
+        .fetch_one(db_pool)
+        .await
+    }
+
+    /// Returns all limits ordered by scope and key.
+    pub async fn list(db_pool: &SqlitePool) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT scope, key, max_per_second, updated_at FROM rate_limits ORDER BY scope, key",
+        )
+        .fetch_all(db_pool)
+        .await
+    }
+
+    pub async fn get(
+        db_pool: &SqlitePool,
+        scope: RateLimitScope,
+        key: &str,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT scope, key, max_per_second, updated_at FROM rate_limits
+             WHERE scope = ? AND key = ?",
+        )
+        .bind(scope.as_str())
+        .bind(scope.normalize(key))
+        .fetch_optional(db_pool)
+        .await
+    }
+
+    /// Removes a limit. Returns `false` if there was none.
+    pub async fn delete(
+        db_pool: &SqlitePool,
+        scope: RateLimitScope,
+        key: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM rate_limits WHERE scope = ? AND key = ?")
+            .bind(scope.as_str())
+            .bind(scope.normalize(key))
+            .execute(db_pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::init_test_db;
+
+    #[tokio::test]
+    async fn test_upsert_and_delete() {
+        let db = init_test_db().await;
+
+        RateLimit::upsert(&db, RateLimitScope::Sender, " News@Example.com", 10)
+            .await
+            .unwrap();
+        let updated = RateLimit::upsert(&db, RateLimitScope::Sender, "news@example.com", 5)
+            .await
+            .unwrap();
+        assert_eq!(updated.key, "news@example.com");
+        assert_eq!(updated.max_per_second, 5);
+        RateLimit::upsert(&db, RateLimitScope::Topic, "Partner", 2)
+            .await
+            .unwrap();
+
+        let limits = RateLimit::list(&db).await.unwrap();
+        assert_eq!(limits.len(), 2);
+        assert_eq!(limits[0].scope, "sender");
+        assert!(RateLimit::get(&db, RateLimitScope::Topic, "partner")
+            .await
+            .unwrap()
+            .is_none());
+
+        assert!(RateLimit::delete(&db, RateLimitScope::Topic, "Partner")
+            .await
+            .unwrap());
+        assert!(!RateLimit::delete(&db, RateLimitScope::Topic, "Partner")
+            .await
+            .unwrap());
+    }
+}
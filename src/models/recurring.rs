@@ -0,0 +1,282 @@
+//! Recurring schedules (cron definitions materialized into email requests)
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::models::request::EmailMessageStatus;
+
+const COLUMNS: &str = "id, name, cron, timezone, topic_id, content_id,
+    json_array_length(recipients) AS recipient_count, paused, next_run_at, last_run_at,
+    created_at, updated_at";
+
+/// A recipient stored with a schedule (copied into `email_requests` at each run).
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ScheduleRecipient {
+    pub email: String,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub variables: HashMap<String, String>,
+}
+
+/// Recurring schedule. Times are UTC; `next_run_at` is `None` once the
+/// cron expression has no further occurrences.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct RecurringSchedule {
+    pub id: i32,
+    pub name: String,
+    pub cron: String,
+    pub timezone: String,
+    pub topic_id: String,
+    pub content_id: i32,
+    pub recipient_count: i32,
+    pub paused: bool,
+    pub next_run_at: Option<String>,
+    pub last_run_at: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// Fields of a schedule to create.
+pub struct NewRecurringSchedule<'a> {
+    pub name: &'a str,
+    pub cron: &'a str,
+    pub timezone: &'a str,
+    pub topic_id: &'a str,
+    pub content_id: i32,
+    pub recipients: &'a [ScheduleRecipient],
+    pub next_run_at: Option<&'a str>,
+}
+
+impl RecurringSchedule {
+    pub async fn create(
+        db_pool: &SqlitePool,
+        new: NewRecurringSchedule<'_>,
+    ) -> Result<Self, sqlx::Error> {
+        let recipients = serde_json::to_string(new.recipients).unwrap_or_else(|_| "[]".into());
+        sqlx::query_as(&format!(
+            "INSERT INTO recurring_schedules
+             (name, cron, timezone, topic_id, content_id, recipients, next_run_at, created_at, updated_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, datetime('now'), datetime('now'))
+             RETURNING {COLUMNS}"
+        ))
+        .bind(new.name)
+        .bind(new.cron)
+        .bind(new.timezone)
+        .bind(new.topic_id)
+        .bind(new.content_id)
+        .bind(recipients)
+        .bind(new.next_run_at)
+        .fetch_one(db_pool)
+        .await
+    }
+
+    /// Returns all schedules ordered by ID.
+    pub async fn list(db_pool: &SqlitePool) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as(&format!(
+            "SELECT {COLUMNS} FROM recurring_schedules ORDER BY id"
+        ))
+        .fetch_all(db_pool)
+        .await
+    }
+
+    pub async fn get(db_pool: &SqlitePool, id: i32) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as(&format!(
+            "SELECT {COLUMNS} FROM recurring_schedules WHERE id = ?"
+        ))
+        .bind(id)
+        .fetch_optional(db_pool)
+        .await
+    }
+
+    /// Pauses or resumes a schedule; resuming sets the next run to `next_run_at`.
+    pub async fn set_paused(
+        db_pool: &SqlitePool,
+        id: i32,
+        paused: bool,
+        next_run_at: Option<&str>,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as(&format!(
+            "UPDATE recurring_schedules
+             SET paused = ?, next_run_at = CASE WHEN ? THEN next_run_at ELSE ? END,
+                 updated_at = datetime('now')
+             WHERE id = ?
+             RETURNING {COLUMNS}"
+        ))
+        .bind(paused)
+        .bind(paused)
+        .bind(next_run_at)
+        .bind(id)
+        .fetch_optional(db_pool)
+        .await
+    }
+
+    /// Deletes a schedule. Requests it already created are kept.
+    pub async fn delete(db_pool: &SqlitePool, id: i32) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM recurring_schedules WHERE id = ?")
+            .bind(id)
+            .execute(db_pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Returns active schedules whose next run is due.
+    pub async fn due(db_pool: &SqlitePool) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as(&format!(
+            "SELECT {COLUMNS} FROM recurring_schedules
+             WHERE paused = 0 AND next_run_at <= datetime('now')
+             ORDER BY next_run_at"
+        ))
+        .fetch_all(db_pool)
+        .await
+    }
+
+    /// Creates the requests of the due run (scheduled at the run time, under
+    /// `topic_id`) and advances the schedule to `next_run_at`, atomically.
+    ///
+    /// Returns `None` if the run was already claimed or the schedule was
+    /// paused or deleted in the meantime.
+    pub async fn materialize(
+        &self,
+        db_pool: &SqlitePool,
+        topic_id: &str,
+        next_run_at: Option<&str>,
+    ) -> Result<Option<u64>, sqlx::Error> {
+        let mut tx = db_pool.begin().await?;
+
+        let claimed = sqlx::query(
+            "UPDATE recurring_schedules
+             SET last_run_at = next_run_at, next_run_at = ?, updated_at = datetime('now')
+             WHERE id = ? AND paused = 0 AND next_run_at = ?",
+        )
+        .bind(next_run_at)
+        .bind(self.id)
+        .bind(&self.next_run_at)
+        .execute(&mut *tx)
+        .await?;
+        if claimed.rows_affected() == 0 {
+            return Ok(None);
+        }
+
+        let created = sqlx::query(
+            "INSERT INTO email_requests (topic_id, content_id, email, scheduled_at, status, variables, created_at, updated_at)
+             SELECT ?, s.content_id, json_extract(r.value, '$.email'), s.last_run_at, ?,
+                    json_extract(r.value, '$.variables'), datetime('now'), datetime('now')
+             FROM recurring_schedules s, json_each(s.recipients) r
+             WHERE s.id = ?",
+        )
+        .bind(topic_id)
+        .bind(EmailMessageStatus::Created as i32)
+        .bind(self.id)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(Some(created.rows_affected()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{init_test_db, seed_content};
+
+    async fn create_schedule(db: &SqlitePool, next_run_at: &str) -> RecurringSchedule {
+        seed_content(db).await;
+        let recipients = [
+            ScheduleRecipient {
+                email: "a@example.com".to_string(),
+                variables: HashMap::from([("name".to_string(), "A".to_string())]),
+            },
+            ScheduleRecipient {
+                email: "b@example.com".to_string(),
+                variables: HashMap::new(),
+            },
+        ];
+        RecurringSchedule::create(
+            db,
+            NewRecurringSchedule {
+                name: "weekly",
+                cron: "0 9 * * MON",
+                timezone: "Asia/Seoul",
+                topic_id: "newsletter",
+                content_id: 1,
+                recipients: &recipients,
+                next_run_at: Some(next_run_at),
+            },
+        )
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_materialize_due_schedule() {
+        let db = init_test_db().await;
+        let schedule = create_schedule(&db, "2000-01-03 00:00:00").await;
+        assert_eq!(schedule.recipient_count, 2);
+
+        let due = RecurringSchedule::due(&db).await.unwrap();
+        assert_eq!(due.len(), 1);
+
+        let created = due[0]
+            .materialize(&db, "newsletter-200001030900", Some("2099-01-05 00:00:00"))
+            .await
+            .unwrap();
+        assert_eq!(created, Some(2));
+        // The same run is not materialized twice
+        assert_eq!(
+            due[0]
+                .materialize(&db, "newsletter-200001030900", Some("2099-01-05 00:00:00"))
+                .await
+                .unwrap(),
+            None
+        );
+        assert!(RecurringSchedule::due(&db).await.unwrap().is_empty());
+
+        let rows: Vec<(String, String, i32, Option<String>)> = sqlx::query_as(
+            "SELECT topic_id, scheduled_at, status, variables FROM email_requests ORDER BY email",
+        )
+        .fetch_all(&db)
+        .await
+        .unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].0, "newsletter-200001030900");
+        assert_eq!(rows[0].1, "2000-01-03 00:00:00");
+        assert_eq!(rows[0].2, EmailMessageStatus::Created as i32);
+        assert_eq!(rows[0].3.as_deref(), Some(r#"{"name":"A"}"#));
+        assert_eq!(rows[1].3, None);
+
+        let schedule = RecurringSchedule::get(&db, schedule.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(schedule.last_run_at.as_deref(), Some("2000-01-03 00:00:00"));
+        assert_eq!(schedule.next_run_at.as_deref(), Some("2099-01-05 00:00:00"));
+    }
+
+    #[tokio::test]
+    async fn test_paused_schedule_is_not_due() {
+        let db = init_test_db().await;
+        let schedule = create_schedule(&db, "2000-01-03 00:00:00").await;
+
+        let paused = RecurringSchedule::set_paused(&db, schedule.id, true, None)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(paused.paused);
+        assert_eq!(paused.next_run_at.as_deref(), Some("2000-01-03 00:00:00"));
+        assert!(RecurringSchedule::due(&db).await.unwrap().is_empty());
+        assert_eq!(schedule.materialize(&db, "t", None).await.unwrap(), None);
+
+        let resumed =
+            RecurringSchedule::set_paused(&db, schedule.id, false, Some("2099-01-05 00:00:00"))
+                .await
+                .unwrap()
+                .unwrap();
+        assert!(!resumed.paused);
+        assert_eq!(resumed.next_run_at.as_deref(), Some("2099-01-05 00:00:00"));
+
+        assert!(RecurringSchedule::delete(&db, schedule.id).await.unwrap());
+        assert!(!RecurringSchedule::delete(&db, schedule.id).await.unwrap());
+    }
+}
@@ -2,12 +2,16 @@
 
 use std::{collections::HashMap, sync::Arc};
 
-use chrono::{FixedOffset, NaiveDateTime, TimeZone, Utc};
-use serde::Deserialize;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
 use sqlx::SqlitePool;
 use tracing::debug;
 
-use crate::constants::BATCH_INSERT_SIZE;
+use crate::{
+    constants::BATCH_INSERT_SIZE,
+    models::{attachment::Attachment, content::MessageOptions},
+    services::schedule,
+};
 
 /// Email delivery status
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -18,6 +22,10 @@ pub enum EmailMessageStatus {
     Sent = 2,
     Failed = 3,
     Stopped = 4,
+    /// Recipient is on the suppression list (never sent)
+    Skipped = 5,
+    /// Held by a topic pause until the topic is resumed
+    Paused = 6,
 }
 
 impl EmailMessageStatus {
@@ -29,6 +37,8 @@ impl EmailMessageStatus {
             2 => Some(Self::Sent),
             3 => Some(Self::Failed),
             4 => Some(Self::Stopped),
+            5 => Some(Self::Skipped),
+            6 => Some(Self::Paused),
             _ => None,
         }
     }
@@ -41,6 +51,29 @@ impl EmailMessageStatus {
             Self::Sent => "Sent",
             Self::Failed => "Failed",
             Self::Stopped => "Stopped",
+            Self::Skipped => "Skipped",
+            Self::Paused => "Paused",
+        }
+    }
+}
+
+/// Sending lane of a request. Transactional emails (password resets, receipts)
+/// are claimed and sent ahead of bulk ones (newsletters, announcements).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+#[repr(i32)]
+pub enum Priority {
+    Transactional = 0,
+    #[default]
+    Bulk = 1,
+}
+
+impl Priority {
+    #[must_use]
+    pub const fn from_i32(value: i32) -> Self {
+        match value {
+            0 => Self::Transactional,
+            _ => Self::Bulk,
         }
     }
 }
@@ -50,6 +83,17 @@ fn default_arc_string() -> Arc<String> {
     Arc::new(String::new())
 }
 
+/// Emails of a topic affected by stopping or pausing it.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct TopicHalt {
+    /// Pending emails whose status was changed
+    pub pending: u64,
+    /// Emails handed to the sender (`Processed`) that have no result yet
+    pub in_flight: i32,
+    /// Highest ID among the in-flight emails
+    pub max_in_flight_id: Option<i32>,
+}
+
 /// Email request entity
 ///
 /// - `content_id`: FK to `email_contents` table (for storage efficiency)
@@ -70,10 +114,39 @@ pub struct EmailRequest {
     /// Uses `Arc<String>` to share; cloned only when adding tracking pixel.
     #[serde(skip, default = "default_arc_string")]
     pub content: Arc<String>,
+    /// Plain-text body loaded from `email_contents` (`None` = derive from HTML).
+    #[serde(skip)]
+    pub text: Option<Arc<String>>,
+    /// Attachments loaded from `email_content_attachments` (shared per content).
+    #[serde(skip)]
+    pub attachments: Arc<Vec<Attachment>>,
+    /// CC/BCC/Reply-To loaded from `email_contents` (shared per content).
+    #[serde(skip)]
+    pub options: Arc<MessageOptions>,
     pub scheduled_at: Option<String>,
     pub status: i32,
     pub error: Option<String>,
     pub message_id: Option<String>,
+    /// Per-recipient template variables (stored as JSON, rendered at send time).
+    #[serde(default)]
+    pub variables: Option<HashMap<String, String>>,
+    /// Recipient-local send window (`None` = send any time).
+    #[serde(skip)]
+    pub send_window: Option<RequestWindow>,
+    #[serde(default)]
+    pub priority: Priority,
+}
+
+/// Send window of a request, kept to defer it to the next opening if it is
+/// still queued when the window closes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RequestWindow {
+    /// Daily local window (`HH:MM-HH:MM`)
+    pub window: String,
+    /// IANA timezone of the recipient
+    pub timezone: String,
+    /// End (UTC) of the window the request is scheduled in
+    pub ends_at: String,
 }
 
 impl EmailRequest {
@@ -82,11 +155,14 @@ impl EmailRequest {
     #[cfg(test)]
     #[allow(dead_code)]
     pub async fn save(self, db_pool: &SqlitePool) -> Result<Self, sqlx::Error> {
-        let scheduled_at = parse_scheduled_at(self.scheduled_at.as_deref());
+        let scheduled_at = scheduled_at_or_now(self.scheduled_at.as_deref());
+
+        let window = self.send_window.as_ref();
 
         let row: (i64,) = sqlx::query_as(
-            "INSERT INTO email_requests (topic_id, content_id, email, scheduled_at, status, created_at, updated_at)
-             VALUES (?, ?, ?, ?, ?, datetime('now'), datetime('now'))
+            "INSERT INTO email_requests (topic_id, content_id, email, scheduled_at, status, variables,
+                send_window, timezone, send_window_ends_at, priority, created_at, updated_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, datetime('now'), datetime('now'))
              RETURNING id",
         )
         .bind(&self.topic_id)
@@ -94,6 +170,11 @@ impl EmailRequest {
         .bind(&self.email)
         .bind(&scheduled_at)
         .bind(self.status)
+        .bind(self.variables_json())
+        .bind(window.map(|w| &w.window))
+        .bind(window.map(|w| &w.timezone))
+        .bind(window.map(|w| &w.ends_at))
+        .bind(self.priority as i32)
         .fetch_one(db_pool)
         .await?;
 
@@ -132,16 +213,177 @@ impl EmailRequest {
         Ok(row.0)
     }
 
-    /// Stops all pending emails for the specified topic.
-    pub async fn stop_topic(db_pool: &SqlitePool, topic_id: &str) -> Result<(), sqlx::Error> {
-        sqlx::query(
-            "UPDATE email_requests SET status=?, updated_at=datetime('now') WHERE status=? AND topic_id=?",
+    /// Returns the count of emails sent or being sent within the specified
+    /// hours, by send time (the rolling window of the SES 24-hour quota).
+    pub async fn rolling_sent_count(db_pool: &SqlitePool, hours: i32) -> Result<i32, sqlx::Error> {
+        let hours_str = format!("-{hours} hours");
+        let row: (i32,) = sqlx::query_as(
+            "SELECT COUNT(*) FROM email_requests WHERE status IN (?, ?) AND updated_at >= datetime('now', ?)",
         )
-        .bind(EmailMessageStatus::Stopped as i32)
+        .bind(EmailMessageStatus::Processed as i32)
+        .bind(EmailMessageStatus::Sent as i32)
+        .bind(&hours_str)
+        .fetch_one(db_pool)
+        .await?;
+
+        Ok(row.0)
+    }
+
+    /// Stops all pending (or paused) emails for the specified topic.
+    pub async fn stop_topic(
+        db_pool: &SqlitePool,
+        topic_id: &str,
+    ) -> Result<TopicHalt, sqlx::Error> {
+        Self::halt_topic(
+            db_pool,
+            topic_id,
+            EmailMessageStatus::Stopped,
+            EmailMessageStatus::Paused,
+        )
+        .await
+    }
+
+    /// Pauses all pending emails for the specified topic.
+    pub async fn pause_topic(
+        db_pool: &SqlitePool,
+        topic_id: &str,
+    ) -> Result<TopicHalt, sqlx::Error> {
+        Self::halt_topic(
+            db_pool,
+            topic_id,
+            EmailMessageStatus::Paused,
+            EmailMessageStatus::Created,
+        )
+        .await
+    }
+
+    /// Moves the topic's pending (and `also`) emails to `status`.
+    ///
+    /// Runs in one transaction with the in-flight lookup, so no email can be
+    /// claimed between them: every email not halted here is counted in flight.
+    async fn halt_topic(
+        db_pool: &SqlitePool,
+        topic_id: &str,
+        status: EmailMessageStatus,
+        also: EmailMessageStatus,
+    ) -> Result<TopicHalt, sqlx::Error> {
+        let mut tx = db_pool.begin().await?;
+        let result = sqlx::query(
+            "UPDATE email_requests SET status=?, updated_at=datetime('now') WHERE status IN (?, ?) AND topic_id=?",
+        )
+        .bind(status as i32)
         .bind(EmailMessageStatus::Created as i32)
+        .bind(also as i32)
+        .bind(topic_id)
+        .execute(&mut *tx)
+        .await?;
+        let (in_flight, max_in_flight_id): (i32, Option<i32>) = sqlx::query_as(
+            "SELECT COUNT(*), MAX(id) FROM email_requests WHERE status=? AND topic_id=?",
+        )
+        .bind(EmailMessageStatus::Processed as i32)
+        .bind(topic_id)
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(TopicHalt {
+            pending: result.rows_affected(),
+            in_flight,
+            max_in_flight_id,
+        })
+    }
+
+    /// Returns paused (and, with `include_stopped`, stopped) emails of the
+    /// topic to the queue, at their original send time or, with
+    /// `reschedule_now`, right away. Returns the count resumed.
+    pub async fn resume_topic(
+        db_pool: &SqlitePool,
+        topic_id: &str,
+        include_stopped: bool,
+        reschedule_now: bool,
+    ) -> Result<u64, sqlx::Error> {
+        let also = if include_stopped {
+            EmailMessageStatus::Stopped
+        } else {
+            EmailMessageStatus::Paused
+        };
+        let result = sqlx::query(
+            "UPDATE email_requests
+             SET status=?, scheduled_at=CASE WHEN ? THEN datetime('now') ELSE scheduled_at END,
+                 updated_at=datetime('now')
+             WHERE status IN (?, ?) AND topic_id=?",
+        )
+        .bind(EmailMessageStatus::Created as i32)
+        .bind(reschedule_now)
+        .bind(EmailMessageStatus::Paused as i32)
+        .bind(also as i32)
         .bind(topic_id)
         .execute(db_pool)
         .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Marks requests as skipped (recipient suppressed).
+    pub async fn mark_skipped(db_pool: &SqlitePool, ids: &[i32]) -> Result<(), sqlx::Error> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+
+        let placeholders = vec!["?"; ids.len()].join(",");
+        let sql = format!(
+            "UPDATE email_requests SET status=?, updated_at=datetime('now') WHERE id IN ({placeholders})"
+        );
+
+        let mut query = sqlx::query(&sql).bind(EmailMessageStatus::Skipped as i32);
+        for id in ids {
+            query = query.bind(id);
+        }
+        query.execute(db_pool).await?;
+        Ok(())
+    }
+
+    /// Returns queued requests whose send window has closed, as
+    /// `(id, send_window, timezone)`.
+    pub async fn find_closed_windows(
+        db_pool: &SqlitePool,
+        limit: i32,
+    ) -> Result<Vec<(i32, String, String)>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT id, send_window, timezone FROM email_requests
+             WHERE status = ? AND send_window_ends_at <= datetime('now')
+             LIMIT ?",
+        )
+        .bind(EmailMessageStatus::Created as i32)
+        .bind(limit)
+        .fetch_all(db_pool)
+        .await
+    }
+
+    /// Moves queued requests to the window opening at `scheduled_at` and closing at `ends_at`.
+    pub async fn defer_to_window(
+        db_pool: &SqlitePool,
+        ids: &[i32],
+        scheduled_at: &str,
+        ends_at: &str,
+    ) -> Result<(), sqlx::Error> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+
+        let placeholders = vec!["?"; ids.len()].join(",");
+        let sql = format!(
+            "UPDATE email_requests SET scheduled_at=?, send_window_ends_at=?, updated_at=datetime('now')
+             WHERE status=? AND id IN ({placeholders})"
+        );
+
+        let mut query = sqlx::query(&sql)
+            .bind(scheduled_at)
+            .bind(ends_at)
+            .bind(EmailMessageStatus::Created as i32);
+        for id in ids {
+            query = query.bind(id);
+        }
+        query.execute(db_pool).await?;
         Ok(())
     }
 
@@ -169,6 +411,22 @@ impl EmailRequest {
         Ok(counts)
     }
 
+    /// Returns the latest send time of the topic's queued requests.
+    pub async fn get_last_scheduled_at_by_topic_id(
+        db_pool: &SqlitePool,
+        topic_id: &str,
+    ) -> Result<Option<String>, sqlx::Error> {
+        let row: (Option<String>,) = sqlx::query_as(
+            "SELECT MAX(scheduled_at) FROM email_requests WHERE topic_id=? AND status=?",
+        )
+        .bind(topic_id)
+        .bind(EmailMessageStatus::Created as i32)
+        .fetch_one(db_pool)
+        .await?;
+
+        Ok(row.0)
+    }
+
     /// Finds the request ID by SES message ID.
     pub async fn get_request_id_by_message_id(
         db_pool: &SqlitePool,
@@ -182,6 +440,32 @@ impl EmailRequest {
         Ok(row.0)
     }
 
+    /// Checks whether `message_id` is the SES message ID stored for a request.
+    pub async fn has_message_id(
+        db_pool: &SqlitePool,
+        id: i32,
+        message_id: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let (matches,): (bool,) = sqlx::query_as(
+            "SELECT EXISTS(SELECT 1 FROM email_requests WHERE id = ? AND message_id = ?)",
+        )
+        .bind(id)
+        .bind(message_id)
+        .fetch_one(db_pool)
+        .await?;
+        Ok(matches)
+    }
+
+    /// Finds the recipient address of a request.
+    pub async fn get_email(db_pool: &SqlitePool, id: i32) -> Result<Option<String>, sqlx::Error> {
+        let row: Option<(String,)> = sqlx::query_as("SELECT email FROM email_requests WHERE id=?")
+            .bind(id)
+            .fetch_optional(db_pool)
+            .await?;
+
+        Ok(row.map(|(email,)| email))
+    }
+
     /// Saves multiple requests in a single transaction using multi-row INSERT.
     ///
     /// Note: `content_id` must be set for all requests before calling this method.
@@ -202,24 +486,31 @@ impl EmailRequest {
             let chunk_size = chunk.len();
 
             let placeholders = (0..chunk_size)
-                .map(|_| "(?, ?, ?, ?, ?, datetime('now'), datetime('now'))")
+                .map(|_| "(?, ?, ?, ?, ?, ?, ?, ?, ?, ?, datetime('now'), datetime('now'))")
                 .collect::<Vec<_>>()
                 .join(", ");
 
             let sql = format!(
-                "INSERT INTO email_requests (topic_id, content_id, email, scheduled_at, status, created_at, updated_at) VALUES {placeholders}"
+                "INSERT INTO email_requests (topic_id, content_id, email, scheduled_at, status, variables,
+                    send_window, timezone, send_window_ends_at, priority, created_at, updated_at) VALUES {placeholders}"
             );
 
             let mut query = sqlx::query(&sql);
 
             for req in chunk {
-                let scheduled_at = parse_scheduled_at(req.scheduled_at.as_deref());
+                let scheduled_at = scheduled_at_or_now(req.scheduled_at.as_deref());
+                let window = req.send_window.as_ref();
                 query = query
                     .bind(&req.topic_id)
                     .bind(req.content_id)
                     .bind(&req.email)
                     .bind(scheduled_at)
-                    .bind(req.status);
+                    .bind(req.status)
+                    .bind(req.variables_json())
+                    .bind(window.map(|w| &w.window))
+                    .bind(window.map(|w| &w.timezone))
+                    .bind(window.map(|w| &w.ends_at))
+                    .bind(req.priority as i32);
             }
 
             query.execute(&mut *tx).await?;
@@ -243,32 +534,28 @@ impl EmailRequest {
 
         Ok(results)
     }
-}
 
-/// Parses `scheduled_at` string and converts KST to UTC.
-///
-/// Input is expected to be in KST (Asia/Seoul, UTC+9) format: "YYYY-MM-DD HH:MM:SS"
-/// Returns UTC formatted string for `SQLite` datetime comparison.
-fn parse_scheduled_at(scheduled: Option<&str>) -> String {
-    let now = Utc::now();
-    let now_str = || now.format("%Y-%m-%d %H:%M:%S").to_string();
+    /// Serializes `variables` for storage (`None` when there is nothing to render).
+    fn variables_json(&self) -> Option<String> {
+        self.variables
+            .as_ref()
+            .filter(|v| !v.is_empty())
+            .and_then(|v| serde_json::to_string(v).ok())
+    }
 
-    // KST is UTC+9
-    let kst = FixedOffset::east_opt(9 * 3600).expect("valid offset");
+    /// Parses the stored `variables` column back into a map.
+    pub fn parse_variables(raw: Option<&str>) -> Option<HashMap<String, String>> {
+        raw.and_then(|s| serde_json::from_str(s).ok())
+    }
+}
 
+/// Returns the stored `scheduled_at`, or the current time if unset.
+///
+/// Values are normalized to UTC (`schedule::STORAGE_FORMAT`) by the handler.
+fn scheduled_at_or_now(scheduled: Option<&str>) -> String {
     match scheduled {
-        Some(s) if !s.is_empty() => NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S")
-            .ok()
-            .and_then(|dt| kst.from_local_datetime(&dt).single())
-            .map(|kst_dt| kst_dt.with_timezone(&Utc))
-            .map_or_else(
-                || {
-                    tracing::warn!("Invalid scheduled_at '{}', using now", s);
-                    now_str()
-                },
-                |utc| utc.format("%Y-%m-%d %H:%M:%S").to_string(),
-            ),
-        _ => now_str(),
+        Some(s) if !s.is_empty() => s.to_string(),
+        _ => schedule::to_storage(Utc::now()),
     }
 }
 
@@ -277,194 +564,106 @@ mod tests {
     use super::*;
     use regex::Regex;
 
-    fn datetime_format_regex() -> Regex {
-        Regex::new(r"^\d{4}-\d{2}-\d{2} \d{2}:\d{2}:\d{2}$").unwrap()
-    }
-
     #[test]
-    fn test_parse_scheduled_at_none_returns_valid_format() {
-        let result = parse_scheduled_at(None);
-        assert!(datetime_format_regex().is_match(&result));
-    }
-
-    #[test]
-    fn test_parse_scheduled_at_empty_returns_valid_format() {
-        let result = parse_scheduled_at(Some(""));
-        assert!(datetime_format_regex().is_match(&result));
-    }
-
-    #[test]
-    fn test_parse_scheduled_at_invalid_uses_fallback() {
-        let result = parse_scheduled_at(Some("invalid"));
-        assert!(datetime_format_regex().is_match(&result));
+    fn test_variables_json_roundtrip() {
+        let mut variables = HashMap::new();
+        variables.insert("name".to_string(), "홍길동".to_string());
+
+        let request = EmailRequest {
+            id: None,
+            topic_id: None,
+            content_id: None,
+            email: "test@example.com".to_string(),
+            subject: default_arc_string(),
+            content: default_arc_string(),
+            text: None,
+            attachments: Arc::default(),
+            options: Arc::default(),
+            scheduled_at: None,
+            status: EmailMessageStatus::Created as i32,
+            error: None,
+            message_id: None,
+            variables: Some(variables.clone()),
+            send_window: None,
+            priority: Priority::Bulk,
+        };
+
+        let json = request.variables_json();
+        assert_eq!(
+            EmailRequest::parse_variables(json.as_deref()),
+            Some(variables)
+        );
     }
 
     #[test]
-    fn test_parse_scheduled_at_kst_converts_to_utc() {
-        // KST 15:30:45 should become UTC 06:30:45 (9 hours earlier)
-        let kst_input = "2025-12-27 15:30:45";
-        let result = parse_scheduled_at(Some(kst_input));
-
-        assert_eq!(result, "2025-12-27 06:30:45");
+    fn test_variables_json_empty_is_none() {
+        let request = EmailRequest {
+            id: None,
+            topic_id: None,
+            content_id: None,
+            email: "test@example.com".to_string(),
+            subject: default_arc_string(),
+            content: default_arc_string(),
+            text: None,
+            attachments: Arc::default(),
+            options: Arc::default(),
+            scheduled_at: None,
+            status: EmailMessageStatus::Created as i32,
+            error: None,
+            message_id: None,
+            variables: Some(HashMap::new()),
+            send_window: None,
+            priority: Priority::Bulk,
+        };
+
+        assert_eq!(request.variables_json(), None);
+        assert_eq!(EmailRequest::parse_variables(None), None);
     }
 
-    #[test]
-    fn test_parse_scheduled_at_kst_midnight_crosses_date() {
-        // KST 2025-12-28 03:00:00 should become UTC 2025-12-27 18:00:00
-        let kst_input = "2025-12-28 03:00:00";
-        let result = parse_scheduled_at(Some(kst_input));
-
-        assert_eq!(result, "2025-12-27 18:00:00");
-    }
-
-    #[test]
-    fn test_parse_scheduled_at_kst_early_morning_previous_day() {
-        // KST 2025-01-01 08:00:00 should become UTC 2024-12-31 23:00:00
-        let kst_input = "2025-01-01 08:00:00";
-        let result = parse_scheduled_at(Some(kst_input));
-
-        assert_eq!(result, "2024-12-31 23:00:00");
-    }
-
-    #[test]
-    fn test_parse_scheduled_at_preserves_format() {
-        let kst_input = "2025-06-15 12:00:00";
-        let result = parse_scheduled_at(Some(kst_input));
-
-        // KST 12:00 -> UTC 03:00
-        assert_eq!(result, "2025-06-15 03:00:00");
-        assert!(datetime_format_regex().is_match(&result));
-    }
-
-    // === Edge case tests for parse_scheduled_at ===
-
-    #[test]
-    fn test_parse_scheduled_at_invalid_month() {
-        // Invalid month (13)
-        let result = parse_scheduled_at(Some("2025-13-01 12:00:00"));
-        assert!(datetime_format_regex().is_match(&result));
-        // Should fallback to current time
-    }
-
-    #[test]
-    fn test_parse_scheduled_at_invalid_day() {
-        // Invalid day (32)
-        let result = parse_scheduled_at(Some("2025-01-32 12:00:00"));
-        assert!(datetime_format_regex().is_match(&result));
-    }
-
-    #[test]
-    fn test_parse_scheduled_at_invalid_hour() {
-        // Invalid hour (25)
-        let result = parse_scheduled_at(Some("2025-01-15 25:00:00"));
-        assert!(datetime_format_regex().is_match(&result));
-    }
-
-    #[test]
-    fn test_parse_scheduled_at_wrong_format_slash() {
-        // Wrong format with slashes
-        let result = parse_scheduled_at(Some("2025/01/15 12:00:00"));
-        assert!(datetime_format_regex().is_match(&result));
-    }
-
-    #[test]
-    fn test_parse_scheduled_at_iso_format() {
-        // ISO format with T separator (should fail and fallback)
-        let result = parse_scheduled_at(Some("2025-01-15T12:00:00"));
-        assert!(datetime_format_regex().is_match(&result));
-    }
-
-    #[test]
-    fn test_parse_scheduled_at_with_timezone() {
-        // Input with timezone info (should fail and fallback)
-        let result = parse_scheduled_at(Some("2025-01-15 12:00:00+09:00"));
-        assert!(datetime_format_regex().is_match(&result));
-    }
-
-    #[test]
-    fn test_parse_scheduled_at_whitespace_only() {
-        let result = parse_scheduled_at(Some("   "));
-        assert!(datetime_format_regex().is_match(&result));
-    }
-
-    #[test]
-    fn test_parse_scheduled_at_partial_date() {
-        // Missing time part
-        let result = parse_scheduled_at(Some("2025-01-15"));
-        assert!(datetime_format_regex().is_match(&result));
-    }
-
-    #[test]
-    fn test_parse_scheduled_at_leap_year_valid() {
-        // Valid leap year date
-        let result = parse_scheduled_at(Some("2024-02-29 12:00:00"));
-        assert_eq!(result, "2024-02-29 03:00:00");
-    }
-
-    #[test]
-    fn test_parse_scheduled_at_leap_year_invalid() {
-        // Invalid leap year date (2025 is not a leap year)
-        let result = parse_scheduled_at(Some("2025-02-29 12:00:00"));
-        assert!(datetime_format_regex().is_match(&result));
-        // Should fallback since Feb 29, 2025 doesn't exist
-    }
-
-    #[test]
-    fn test_parse_scheduled_at_end_of_year() {
-        // KST 2025-12-31 23:59:59 -> UTC 2025-12-31 14:59:59
-        let result = parse_scheduled_at(Some("2025-12-31 23:59:59"));
-        assert_eq!(result, "2025-12-31 14:59:59");
-    }
-
-    #[test]
-    fn test_parse_scheduled_at_start_of_year() {
-        // KST 2025-01-01 00:00:00 -> UTC 2024-12-31 15:00:00
-        let result = parse_scheduled_at(Some("2025-01-01 00:00:00"));
-        assert_eq!(result, "2024-12-31 15:00:00");
-    }
-
-    #[test]
-    fn test_parse_scheduled_at_exactly_nine_hours() {
-        // KST 09:00:00 -> UTC 00:00:00 (same day)
-        let result = parse_scheduled_at(Some("2025-06-15 09:00:00"));
-        assert_eq!(result, "2025-06-15 00:00:00");
-    }
-
-    #[test]
-    fn test_parse_scheduled_at_sql_injection_attempt() {
-        // SQL injection attempt should be safely handled
-        let result = parse_scheduled_at(Some("2025-01-15'; DROP TABLE--"));
-        assert!(datetime_format_regex().is_match(&result));
+    fn datetime_format_regex() -> Regex {
+        Regex::new(r"^\d{4}-\d{2}-\d{2} \d{2}:\d{2}:\d{2}$").unwrap()
     }
 
     #[test]
-    fn test_parse_scheduled_at_very_long_string() {
-        let long_string = "2025-01-15 12:00:00".to_string() + &"x".repeat(1000);
-        let result = parse_scheduled_at(Some(&long_string));
+    fn test_scheduled_at_or_now_none_returns_valid_format() {
+        let result = scheduled_at_or_now(None);
         assert!(datetime_format_regex().is_match(&result));
     }
 
     #[test]
-    fn test_parse_scheduled_at_unicode() {
-        // Unicode characters in date
-        let result = parse_scheduled_at(Some("２０２５-01-15 12:00:00")); // Full-width numbers
+    fn test_scheduled_at_or_now_empty_returns_valid_format() {
+        let result = scheduled_at_or_now(Some(""));
         assert!(datetime_format_regex().is_match(&result));
     }
 
     #[test]
-    fn test_parse_scheduled_at_negative_values() {
-        // Negative year is parsed by chrono as a valid date (year -2025)
-        // The result will have format "-2025-..." which doesn't match standard regex
-        // Just verify the function doesn't panic and returns a non-empty string
-        let result = parse_scheduled_at(Some("-2025-01-15 12:00:00"));
-        assert!(!result.is_empty());
+    fn test_scheduled_at_or_now_keeps_normalized_value() {
+        let result = scheduled_at_or_now(Some("2025-12-27 06:30:45"));
+        assert_eq!(result, "2025-12-27 06:30:45");
     }
 
-    #[test]
-    fn test_parse_scheduled_at_zero_padded() {
-        // Properly zero-padded should work
-        // KST 2025-01-05 08:05:09 -> UTC 2025-01-04 23:05:09 (subtract 9 hours)
-        let result = parse_scheduled_at(Some("2025-01-05 08:05:09"));
-        assert_eq!(result, "2025-01-04 23:05:09");
+    #[tokio::test]
+    async fn test_rolling_sent_count_uses_send_time() {
+        let db = crate::config::init_test_db().await;
+        crate::config::seed_content(&db).await;
+        // Created two days ago: sent (2), being sent (1), failed (3), queued (0)
+        sqlx::query(
+            "INSERT INTO email_requests (topic_id, content_id, email, scheduled_at, status, created_at)
+             VALUES ('t', 1, 'a@example.com', datetime('now'), 2, datetime('now', '-2 days')),
+                    ('t', 1, 'b@example.com', datetime('now'), 1, datetime('now', '-2 days')),
+                    ('t', 1, 'c@example.com', datetime('now'), 3, datetime('now', '-2 days')),
+                    ('t', 1, 'd@example.com', datetime('now'), 0, datetime('now', '-2 days'));",
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+        assert_eq!(EmailRequest::rolling_sent_count(&db, 24).await.unwrap(), 2);
+        assert_eq!(EmailRequest::sent_count(&db, 24).await.unwrap(), 0);
+
+        sqlx::query("UPDATE email_requests SET updated_at = datetime('now', '-25 hours')")
+            .execute(&db)
+            .await
+            .unwrap();
+        assert_eq!(EmailRequest::rolling_sent_count(&db, 24).await.unwrap(), 0);
     }
 }
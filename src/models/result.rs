@@ -33,6 +33,22 @@ impl EmailResult {
         })
     }
 
+    /// Checks whether a result with the given status was already recorded for a request.
+    pub async fn exists(
+        db_pool: &SqlitePool,
+        request_id: i32,
+        status: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let (exists,): (bool,) = sqlx::query_as(
+            "SELECT EXISTS(SELECT 1 FROM email_results WHERE request_id = ? AND status = ?)",
+        )
+        .bind(request_id)
+        .bind(status)
+        .fetch_one(db_pool)
+        .await?;
+        Ok(exists)
+    }
+
     /// Returns result counts by status for the specified topic.
     ///
     /// Uses JOIN instead of subquery for better performance.
@@ -0,0 +1,218 @@
+//! Suppression list (recipients that must not receive emails)
+
+use std::collections::HashSet;
+
+use serde::Serialize;
+use sqlx::SqlitePool;
+
+use crate::services::address;
+
+/// Max addresses per `IN (...)` lookup (`SQLite` allows 999 bound parameters).
+const LOOKUP_CHUNK_SIZE: usize = 500;
+
+/// Why a recipient was suppressed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SuppressionReason {
+    /// Permanent (hard) bounce reported by SES
+    Bounce,
+    /// Spam complaint reported by SES
+    Complaint,
+    /// Recipient used the unsubscribe link
+    Unsubscribe,
+    /// Added through the API
+    Manual,
+}
+
+impl SuppressionReason {
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Bounce => "Bounce",
+            Self::Complaint => "Complaint",
+            Self::Unsubscribe => "Unsubscribe",
+            Self::Manual => "Manual",
+        }
+    }
+}
+
+/// Suppressed recipient. `request_id` is the request that triggered it, if any.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct Suppression {
+    pub email: String,
+    pub reason: String,
+    pub request_id: Option<i32>,
+    pub created_at: String,
+}
+
+impl Suppression {
+    /// Normalizes an address for storage and lookups.
+    ///
+    /// Built on [`address::normalize`] so IDN domains match their punycode
+    /// form, then lowercased as a whole since suppressions are case-insensitive.
+    /// Addresses it rejects (e.g. reported by SES) are only trimmed and lowercased.
+    pub fn normalize(email: &str) -> String {
+        address::normalize(email)
+            .unwrap_or_else(|_| email.trim().to_owned())
+            .to_lowercase()
+    }
+
+    /// Suppresses an address. Existing entries keep their original reason.
+    ///
+    /// Returns `false` if the address was already suppressed.
+    pub async fn add(
+        db_pool: &SqlitePool,
+        email: &str,
+        reason: SuppressionReason,
+        request_id: Option<i32>,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            "INSERT INTO suppressions (email, reason, request_id, created_at)
+             VALUES (?, ?, ?, datetime('now'))
+             ON CONFLICT(email) DO NOTHING",
+        )
+        .bind(Self::normalize(email))
+        .bind(reason.as_str())
+        .bind(request_id)
+        .execute(db_pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Returns suppressions, most recent first.
+    pub async fn list(
+        db_pool: &SqlitePool,
+        limit: i32,
+        offset: i32,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT email, reason, request_id, created_at FROM suppressions
+             ORDER BY created_at DESC, email LIMIT ? OFFSET ?",
+        )
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(db_pool)
+        .await
+    }
+
+    /// Finds the suppression of an address.
+    pub async fn get(db_pool: &SqlitePool, email: &str) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT email, reason, request_id, created_at FROM suppressions WHERE email = ?",
+        )
+        .bind(Self::normalize(email))
+        .fetch_optional(db_pool)
+        .await
+    }
+
+    /// Removes an address from the list. Returns `false` if it was not suppressed.
+    pub async fn delete(db_pool: &SqlitePool, email: &str) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM suppressions WHERE email = ?")
+            .bind(Self::normalize(email))
+            .execute(db_pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Returns the normalized addresses among `emails` that are suppressed.
+    pub async fn find_suppressed(
+        db_pool: &SqlitePool,
+        emails: &[&str],
+    ) -> Result<HashSet<String>, sqlx::Error> {
+        let mut normalized: Vec<String> = emails.iter().map(|e| Self::normalize(e)).collect();
+        normalized.sort_unstable();
+        normalized.dedup();
+
+        let mut suppressed = HashSet::new();
+        for chunk in normalized.chunks(LOOKUP_CHUNK_SIZE) {
+            let placeholders = vec!["?"; chunk.len()].join(",");
+            let sql = format!("SELECT email FROM suppressions WHERE email IN ({placeholders})");
+
+            let mut query = sqlx::query_as::<_, (String,)>(&sql);
+            for email in chunk {
+                query = query.bind(email);
+            }
+            suppressed.extend(query.fetch_all(db_pool).await?.into_iter().map(|(e,)| e));
+        }
+        Ok(suppressed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::init_test_db;
+
+    #[tokio::test]
+    async fn test_add_get_delete() {
+        let db = init_test_db().await;
+
+        assert!(
+            Suppression::add(&db, " User@Example.com ", SuppressionReason::Bounce, None)
+                .await
+                .unwrap()
+        );
+        // Already suppressed: the original reason is kept
+        assert!(
+            !Suppression::add(&db, "user@example.com", SuppressionReason::Manual, None)
+                .await
+                .unwrap()
+        );
+
+        let found = Suppression::get(&db, "USER@example.com")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.email, "user@example.com");
+        assert_eq!(found.reason, "Bounce");
+
+        assert!(Suppression::delete(&db, "user@EXAMPLE.com").await.unwrap());
+        assert!(!Suppression::delete(&db, "user@example.com").await.unwrap());
+        assert!(Suppression::get(&db, "user@example.com")
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_find_suppressed() {
+        let db = init_test_db().await;
+        Suppression::add(&db, "a@example.com", SuppressionReason::Complaint, None)
+            .await
+            .unwrap();
+        Suppression::add(&db, "b@example.com", SuppressionReason::Unsubscribe, None)
+            .await
+            .unwrap();
+
+        let mut emails: Vec<String> = (0..1200).map(|i| format!("u{i}@example.com")).collect();
+        emails.push("A@Example.com".to_string());
+        let refs: Vec<&str> = emails.iter().map(String::as_str).collect();
+
+        let suppressed = Suppression::find_suppressed(&db, &refs).await.unwrap();
+        assert_eq!(suppressed, HashSet::from(["a@example.com".to_string()]));
+
+        let listed = Suppression::list(&db, 10, 0).await.unwrap();
+        assert_eq!(listed.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_normalize_matches_recipient_normalization() {
+        let db = init_test_db().await;
+        Suppression::add(&db, "User@Bücher.de", SuppressionReason::Manual, None)
+            .await
+            .unwrap();
+
+        // Recipients are stored with the local part kept and the domain in punycode
+        let recipient = address::normalize("User@bücher.de").unwrap();
+        assert_eq!(recipient, "User@xn--bcher-kva.de");
+        assert_eq!(Suppression::normalize(&recipient), "user@xn--bcher-kva.de");
+
+        let suppressed = Suppression::find_suppressed(&db, &[recipient.as_str()])
+            .await
+            .unwrap();
+        assert_eq!(
+            suppressed,
+            HashSet::from(["user@xn--bcher-kva.de".to_string()])
+        );
+        assert_eq!(Suppression::normalize(" Not An Address "), "not an address");
+    }
+}
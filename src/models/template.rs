@@ -0,0 +1,307 @@
+//! Stored email templates with immutable versions
+
+use serde::Serialize;
+use sqlx::SqlitePool;
+
+/// Email template metadata (the body lives in `EmailTemplateVersion`).
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct EmailTemplate {
+    pub id: i32,
+    pub name: String,
+    pub latest_version: i32,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// Immutable snapshot of a template's subject and bodies.
+#[derive(Clone, Debug, Serialize, sqlx::FromRow)]
+pub struct EmailTemplateVersion {
+    pub template_id: i32,
+    pub version: i32,
+    pub subject: String,
+    pub content: String,
+    pub text: Option<String>,
+    pub created_at: String,
+}
+
+/// Number of requests in a topic that were sent from a template version.
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct TemplateUsage {
+    pub template_id: i32,
+    pub version: i32,
+    pub count: i32,
+}
+
+impl EmailTemplate {
+    /// Creates a template together with its first version.
+    pub async fn create(
+        db_pool: &SqlitePool,
+        name: &str,
+        subject: &str,
+        content: &str,
+        text: Option<&str>,
+    ) -> Result<(Self, EmailTemplateVersion), sqlx::Error> {
+        let mut tx = db_pool.begin().await?;
+
+        let template: Self = sqlx::query_as(
+            "INSERT INTO email_templates (name, latest_version, created_at, updated_at)
+             VALUES (?, 1, datetime('now'), datetime('now'))
+             RETURNING id, name, latest_version, created_at, updated_at",
+        )
+        .bind(name)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let version: EmailTemplateVersion = sqlx::query_as(
+            "INSERT INTO email_template_versions (template_id, version, subject, content, text, created_at)
+             VALUES (?, 1, ?, ?, ?, datetime('now'))
+             RETURNING template_id, version, subject, content, text, created_at",
+        )
+        .bind(template.id)
+        .bind(subject)
+        .bind(content)
+        .bind(text)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok((template, version))
+    }
+
+    /// Returns all active templates ordered by ID.
+    pub async fn list(db_pool: &SqlitePool) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT id, name, latest_version, created_at, updated_at
+             FROM email_templates WHERE deleted_at IS NULL ORDER BY id",
+        )
+        .fetch_all(db_pool)
+        .await
+    }
+
+    /// Finds an active template by ID.
+    pub async fn get(db_pool: &SqlitePool, id: i32) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT id, name, latest_version, created_at, updated_at
+             FROM email_templates WHERE id = ? AND deleted_at IS NULL",
+        )
+        .bind(id)
+        .fetch_optional(db_pool)
+        .await
+    }
+
+    /// Stores a new version (existing versions are never modified).
+    ///
+    /// Optionally renames the template. Returns `None` if the template does not exist.
+    pub async fn add_version(
+        db_pool: &SqlitePool,
+        id: i32,
+        name: Option<&str>,
+        subject: &str,
+        content: &str,
+        text: Option<&str>,
+    ) -> Result<Option<EmailTemplateVersion>, sqlx::Error> {
+        let mut tx = db_pool.begin().await?;
+
+        let next: Option<(i32,)> = sqlx::query_as(
+            "UPDATE email_templates
+             SET latest_version = latest_version + 1, name = COALESCE(?, name), updated_at = datetime('now')
+             WHERE id = ? AND deleted_at IS NULL
+             RETURNING latest_version",
+        )
+        .bind(name)
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let Some((version,)) = next else {
+            return Ok(None);
+        };
+
+        let saved: EmailTemplateVersion = sqlx::query_as(
+            "INSERT INTO email_template_versions (template_id, version, subject, content, text, created_at)
+             VALUES (?, ?, ?, ?, ?, datetime('now'))
+             RETURNING template_id, version, subject, content, text, created_at",
+        )
+        .bind(id)
+        .bind(version)
+        .bind(subject)
+        .bind(content)
+        .bind(text)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(Some(saved))
+    }
+
+    /// Soft-deletes a template so contents that reference it stay intact.
+    ///
+    /// Returns `false` if the template does not exist.
+    pub async fn delete(db_pool: &SqlitePool, id: i32) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE email_templates SET deleted_at = datetime('now'), updated_at = datetime('now')
+             WHERE id = ? AND deleted_at IS NULL",
+        )
+        .bind(id)
+        .execute(db_pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Returns all version numbers of a template in ascending order.
+    pub async fn versions(db_pool: &SqlitePool, id: i32) -> Result<Vec<i32>, sqlx::Error> {
+        let rows: Vec<(i32,)> = sqlx::query_as(
+            "SELECT version FROM email_template_versions WHERE template_id = ? ORDER BY version",
+        )
+        .bind(id)
+        .fetch_all(db_pool)
+        .await?;
+        Ok(rows.into_iter().map(|(v,)| v).collect())
+    }
+}
+
+impl EmailTemplateVersion {
+    /// Finds a version of an active template (latest when `version` is `None`).
+    pub async fn get(
+        db_pool: &SqlitePool,
+        template_id: i32,
+        version: Option<i32>,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT v.template_id, v.version, v.subject, v.content, v.text, v.created_at
+             FROM email_template_versions v
+             JOIN email_templates t ON v.template_id = t.id
+             WHERE t.id = ? AND t.deleted_at IS NULL AND v.version = COALESCE(?, t.latest_version)",
+        )
+        .bind(template_id)
+        .bind(version)
+        .fetch_optional(db_pool)
+        .await
+    }
+}
+
+impl TemplateUsage {
+    /// Returns how many requests of a topic used each template version.
+    pub async fn get_by_topic_id(
+        db_pool: &SqlitePool,
+        topic_id: &str,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT c.template_id, c.template_version AS version, COUNT(*) AS count
+             FROM email_requests r
+             INNER JOIN email_contents c ON r.content_id = c.id
+             WHERE r.topic_id = ? AND c.template_id IS NOT NULL
+             GROUP BY c.template_id, c.template_version
+             ORDER BY c.template_id, c.template_version",
+        )
+        .bind(topic_id)
+        .fetch_all(db_pool)
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::init_test_db;
+
+    #[tokio::test]
+    async fn test_create_template_with_first_version() {
+        let db = init_test_db().await;
+
+        let (template, version) =
+            EmailTemplate::create(&db, "welcome", "Hi {{name}}", "<p>Hi</p>", Some("Hi"))
+                .await
+                .unwrap();
+
+        assert_eq!(template.name, "welcome");
+        assert_eq!(template.latest_version, 1);
+        assert_eq!(version.template_id, template.id);
+        assert_eq!(version.version, 1);
+        assert_eq!(version.subject, "Hi {{name}}");
+        assert_eq!(version.text.as_deref(), Some("Hi"));
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_active_name_is_rejected() {
+        let db = init_test_db().await;
+
+        EmailTemplate::create(&db, "dup", "s", "c", None)
+            .await
+            .unwrap();
+        let err = EmailTemplate::create(&db, "dup", "s", "c", None)
+            .await
+            .unwrap_err();
+        assert!(err
+            .as_database_error()
+            .is_some_and(sqlx::error::DatabaseError::is_unique_violation));
+    }
+
+    #[tokio::test]
+    async fn test_add_version_keeps_previous_versions() {
+        let db = init_test_db().await;
+        let (template, _) = EmailTemplate::create(&db, "news", "v1", "<p>v1</p>", None)
+            .await
+            .unwrap();
+
+        let v2 =
+            EmailTemplate::add_version(&db, template.id, Some("news-2"), "v2", "<p>v2</p>", None)
+                .await
+                .unwrap()
+                .unwrap();
+        assert_eq!(v2.version, 2);
+
+        let latest = EmailTemplateVersion::get(&db, template.id, None)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(latest.subject, "v2");
+
+        let first = EmailTemplateVersion::get(&db, template.id, Some(1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(first.subject, "v1");
+
+        let renamed = EmailTemplate::get(&db, template.id).await.unwrap().unwrap();
+        assert_eq!(renamed.name, "news-2");
+        assert_eq!(
+            EmailTemplate::versions(&db, template.id).await.unwrap(),
+            vec![1, 2]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_add_version_missing_template() {
+        let db = init_test_db().await;
+        let result = EmailTemplate::add_version(&db, 999, None, "s", "c", None)
+            .await
+            .unwrap();
+        assert!(result.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_delete_hides_template() {
+        let db = init_test_db().await;
+        let (template, _) = EmailTemplate::create(&db, "gone", "s", "c", None)
+            .await
+            .unwrap();
+
+        assert!(EmailTemplate::delete(&db, template.id).await.unwrap());
+        assert!(!EmailTemplate::delete(&db, template.id).await.unwrap());
+        assert!(EmailTemplate::get(&db, template.id)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(EmailTemplateVersion::get(&db, template.id, None)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(EmailTemplate::list(&db).await.unwrap().is_empty());
+
+        // Name can be reused after deletion
+        EmailTemplate::create(&db, "gone", "s", "c", None)
+            .await
+            .unwrap();
+    }
+}
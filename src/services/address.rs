@@ -0,0 +1,168 @@
+//! Email address validation and normalization (RFC 5321 syntax, IDN domains)
+
+use thiserror::Error;
+
+/// RFC 5321 limits (octets).
+const MAX_ADDRESS_LENGTH: usize = 254;
+const MAX_LOCAL_PART_LENGTH: usize = 64;
+
+/// Why an address was rejected.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum AddressError {
+    #[error("empty address")]
+    Empty,
+
+    #[error("missing '@'")]
+    MissingAt,
+
+    #[error("invalid local part")]
+    InvalidLocalPart,
+
+    #[error("local part exceeds {MAX_LOCAL_PART_LENGTH} characters")]
+    LocalPartTooLong,
+
+    #[error("invalid domain")]
+    InvalidDomain,
+
+    #[error("address exceeds {MAX_ADDRESS_LENGTH} characters")]
+    TooLong,
+}
+
+/// Validates an address and returns its normalized form.
+///
+/// Surrounding whitespace is trimmed and the domain is lowercased, with
+/// internationalized domains converted to punycode (`user@bücher.de` →
+/// `user@xn--bcher-kva.de`). The local part is kept as-is (it is
+/// case-sensitive per RFC 5321) and must be an ASCII dot-atom, since SES
+/// does not support SMTPUTF8.
+pub fn normalize(address: &str) -> Result<String, AddressError> {
+    let address = address.trim();
+    if address.is_empty() {
+        return Err(AddressError::Empty);
+    }
+
+    let (local, domain) = address.rsplit_once('@').ok_or(AddressError::MissingAt)?;
+    validate_local_part(local)?;
+    let domain = normalize_domain(domain)?;
+
+    let normalized = format!("{local}@{domain}");
+    if normalized.len() > MAX_ADDRESS_LENGTH {
+        return Err(AddressError::TooLong);
+    }
+    Ok(normalized)
+}
+
+/// Dot-atom: `atext` characters separated by single dots.
+fn validate_local_part(local: &str) -> Result<(), AddressError> {
+    if local.len() > MAX_LOCAL_PART_LENGTH {
+        return Err(AddressError::LocalPartTooLong);
+    }
+    let is_atext = |c: char| c.is_ascii_alphanumeric() || "!#$%&'*+/=?^_`{|}~-".contains(c);
+    let valid = local
+        .split('.')
+        .all(|atom| !atom.is_empty() && atom.chars().all(is_atext));
+    if valid {
+        Ok(())
+    } else {
+        Err(AddressError::InvalidLocalPart)
+    }
+}
+
+/// Converts the domain to lowercase ASCII and checks its labels (letters,
+/// digits and inner hyphens, at least two labels, no address literals).
+fn normalize_domain(domain: &str) -> Result<String, AddressError> {
+    let ascii = idna::domain_to_ascii_strict(domain).map_err(|_| AddressError::InvalidDomain)?;
+    let labels: Vec<&str> = ascii.split('.').collect();
+
+    let valid = labels.len() >= 2
+        && labels.iter().all(|label| {
+            !label.is_empty()
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+        })
+        // Top-level domains are never all-numeric (rejects IP addresses)
+        && labels
+            .last()
+            .is_some_and(|tld| !tld.bytes().all(|b| b.is_ascii_digit()));
+
+    if valid {
+        Ok(ascii)
+    } else {
+        Err(AddressError::InvalidDomain)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_valid_addresses() {
+        assert_eq!(
+            normalize("  John.Doe+tag@Example.COM ").unwrap(),
+            "John.Doe+tag@example.com"
+        );
+        assert_eq!(
+            normalize("user@mail.sub-domain.example.co.kr").unwrap(),
+            "user@mail.sub-domain.example.co.kr"
+        );
+        assert_eq!(
+            normalize("user@bücher.de").unwrap(),
+            "user@xn--bcher-kva.de"
+        );
+        assert_eq!(normalize("user@한국.kr").unwrap(), "user@xn--3e0b707e.kr");
+    }
+
+    #[test]
+    fn test_normalize_rejects_invalid_addresses() {
+        let cases = [
+            ("", AddressError::Empty),
+            ("   ", AddressError::Empty),
+            ("user.example.com", AddressError::MissingAt),
+            ("@example.com", AddressError::InvalidLocalPart),
+            ("a..b@example.com", AddressError::InvalidLocalPart),
+            (".a@example.com", AddressError::InvalidLocalPart),
+            ("a b@example.com", AddressError::InvalidLocalPart),
+            ("a@b@example.com", AddressError::InvalidLocalPart),
+            ("홍길동@example.com", AddressError::InvalidLocalPart),
+            ("user@", AddressError::InvalidDomain),
+            ("user@localhost", AddressError::InvalidDomain),
+            ("user@example..com", AddressError::InvalidDomain),
+            ("user@-example.com", AddressError::InvalidDomain),
+            ("user@exa_mple.com", AddressError::InvalidDomain),
+            ("user@192.168.0.1", AddressError::InvalidDomain),
+            ("user@[192.168.0.1]", AddressError::InvalidDomain),
+            (
+                "user@example.com,other@example.com",
+                AddressError::InvalidLocalPart,
+            ),
+            ("user@example.com;", AddressError::InvalidDomain),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(normalize(input), Err(expected), "{input}");
+        }
+    }
+
+    #[test]
+    fn test_normalize_length_limits() {
+        let local = "a".repeat(65);
+        assert_eq!(
+            normalize(&format!("{local}@example.com")),
+            Err(AddressError::LocalPartTooLong)
+        );
+
+        let domain = format!(
+            "{}.{}.{}.com",
+            "a".repeat(63),
+            "b".repeat(63),
+            "c".repeat(63)
+        );
+        assert_eq!(
+            normalize(&format!("{}@{domain}", "u".repeat(60))),
+            Err(AddressError::TooLong)
+        );
+    }
+}
@@ -0,0 +1,210 @@
+//! Per-topic and per-sender token buckets below the global `MAX_SEND_PER_SECOND` bucket
+//!
+//! Buckets are hierarchical: a request is only sent once it got a token from
+//! its topic, its sender address and its sender domain (each if limited), and
+//! then from the global bucket.
+
+use std::{collections::HashMap, sync::Mutex, time::Instant};
+
+use crate::{
+    config::APP_CONFIG,
+    models::{
+        rate_limit::{RateLimit, RateLimitScope},
+        request::EmailRequest,
+    },
+};
+
+/// Token bucket refilled continuously at `max_per_second` (burst: one second).
+#[derive(Debug)]
+struct Bucket {
+    max_per_second: u32,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl Bucket {
+    fn new(max_per_second: u32, now: Instant) -> Self {
+        Self {
+            max_per_second,
+            tokens: f64::from(max_per_second),
+            refilled_at: now,
+        }
+    }
+
+    fn try_acquire(&mut self, now: Instant) -> bool {
+        let capacity = f64::from(self.max_per_second);
+        let elapsed = now.duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = elapsed.mul_add(capacity, self.tokens).min(capacity);
+        self.refilled_at = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn refund(&mut self) {
+        self.tokens = (self.tokens + 1.0).min(f64::from(self.max_per_second));
+    }
+}
+
+/// Rate limits in effect, shared by the API (which changes them) and the sender.
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    buckets: Mutex<HashMap<(RateLimitScope, String), Bucket>>,
+}
+
+impl RateLimiter {
+    /// Loads the stored limits.
+    pub fn load(&self, limits: &[RateLimit]) {
+        for limit in limits {
+            if let (Some(scope), Ok(max_per_second)) = (
+                RateLimitScope::parse(&limit.scope),
+                u32::try_from(limit.max_per_second),
+            ) {
+                self.set(scope, &limit.key, max_per_second);
+            }
+        }
+    }
+
+    /// Sets or changes a limit; it applies to requests already queued.
+    pub fn set(&self, scope: RateLimitScope, key: &str, max_per_second: u32) {
+        let mut buckets = self.lock();
+        let now = Instant::now();
+        buckets
+            .entry((scope, scope.normalize(key)))
+            .and_modify(|bucket| {
+                bucket.max_per_second = max_per_second;
+                bucket.tokens = bucket.tokens.min(f64::from(max_per_second));
+            })
+            .or_insert_with(|| Bucket::new(max_per_second, now));
+    }
+
+    pub fn remove(&self, scope: RateLimitScope, key: &str) {
+        self.lock().remove(&(scope, scope.normalize(key)));
+    }
+
+    /// Takes a token from every bucket that applies to `request`, or from none
+    /// of them if one is empty.
+    pub fn try_acquire(&self, request: &EmailRequest) -> bool {
+        let mut buckets = self.lock();
+        if buckets.is_empty() {
+            return true;
+        }
+
+        let sender = request.options.from.as_ref().map_or_else(
+            || APP_CONFIG.aws_ses_from_email.to_lowercase(),
+            |from| from.email.to_lowercase(),
+        );
+        let domain = sender
+            .rsplit_once('@')
+            .map(|(_, domain)| domain.to_string());
+        let keys = [
+            request
+                .topic_id
+                .clone()
+                .map(|topic| (RateLimitScope::Topic, topic)),
+            Some((RateLimitScope::Sender, sender)),
+            domain.map(|domain| (RateLimitScope::Sender, domain)),
+        ];
+
+        let now = Instant::now();
+        let mut acquired = Vec::with_capacity(keys.len());
+        for key in keys.into_iter().flatten() {
+            let Some(bucket) = buckets.get_mut(&key) else {
+                continue;
+            };
+            if !bucket.try_acquire(now) {
+                for key in acquired {
+                    if let Some(bucket) = buckets.get_mut(&key) {
+                        bucket.refund();
+                    }
+                }
+                return false;
+            }
+            acquired.push(key);
+        }
+        true
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<(RateLimitScope, String), Bucket>> {
+        self.buckets
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use super::*;
+    use crate::models::content::{FromAddress, MessageOptions};
+
+    fn request(topic_id: &str, from: &str) -> EmailRequest {
+        EmailRequest {
+            id: None,
+            topic_id: Some(topic_id.to_string()),
+            content_id: None,
+            email: "to@example.com".to_string(),
+            subject: Arc::default(),
+            content: Arc::default(),
+            text: None,
+            attachments: Arc::default(),
+            options: Arc::new(MessageOptions {
+                from: Some(FromAddress {
+                    email: from.to_string(),
+                    name: None,
+                }),
+                ..MessageOptions::default()
+            }),
+            scheduled_at: None,
+            status: 0,
+            error: None,
+            message_id: None,
+            variables: None,
+            send_window: None,
+            priority: crate::models::request::Priority::Bulk,
+        }
+    }
+
+    #[test]
+    fn test_bucket_refills_over_time() {
+        let start = Instant::now();
+        let mut bucket = Bucket::new(2, start);
+        assert!(bucket.try_acquire(start));
+        assert!(bucket.try_acquire(start));
+        assert!(!bucket.try_acquire(start));
+        assert!(bucket.try_acquire(start + Duration::from_millis(500)));
+        assert!(!bucket.try_acquire(start + Duration::from_millis(500)));
+    }
+
+    #[test]
+    fn test_every_level_must_have_a_token() {
+        let limiter = RateLimiter::default();
+        let partner = request("partner", "news@example.com");
+        assert!(limiter.try_acquire(&partner));
+
+        limiter.set(RateLimitScope::Topic, "partner", 5);
+        limiter.set(RateLimitScope::Sender, "Example.com", 1);
+
+        assert!(limiter.try_acquire(&partner));
+        // The domain bucket is empty: the topic token is given back
+        assert!(!limiter.try_acquire(&partner));
+        assert!(!limiter.try_acquire(&request("other", "alerts@example.com")));
+        assert!(limiter.try_acquire(&request("other", "a@other.com")));
+
+        limiter.remove(RateLimitScope::Sender, "example.com");
+        for _ in 0..4 {
+            assert!(limiter.try_acquire(&partner));
+        }
+        assert!(!limiter.try_acquire(&partner));
+
+        // Lowering a running topic's limit applies immediately
+        limiter.set(RateLimitScope::Topic, "other", 1);
+        assert!(limiter.try_acquire(&request("other", "a@other.com")));
+        assert!(!limiter.try_acquire(&request("other", "a@other.com")));
+    }
+}
@@ -0,0 +1,398 @@
+//! MIME message builder for the SES raw content path (attachments)
+
+use std::{
+    fmt::Write as _,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+
+use crate::{models::attachment::Attachment, services::sender::OutgoingEmail};
+
+/// Max line length of base64 bodies (RFC 2045).
+const BASE64_LINE_LENGTH: usize = 76;
+
+/// Max payload bytes per RFC 2047 encoded-word (45 bytes = 60 base64 chars, word <= 75).
+const ENCODED_WORD_BYTES: usize = 45;
+
+/// Rough allowance for headers and boundaries of each MIME part.
+const PART_OVERHEAD_BYTES: usize = 256;
+
+static BOUNDARY_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Builds a complete MIME message (CRLF line endings).
+///
+/// Layout: `multipart/mixed` (regular attachments) > `multipart/related`
+/// (inline images with a Content-ID) > `multipart/alternative` (text + HTML).
+/// Each wrapper is only added when it has parts.
+pub fn build_message(email: &OutgoingEmail<'_>) -> String {
+    let (inline, attached): (Vec<&Attachment>, Vec<&Attachment>) =
+        email.attachments.iter().partition(|a| a.cid.is_some());
+
+    let mut out = String::with_capacity(estimate_size(
+        email.subject,
+        email.html,
+        Some(email.text),
+        email.attachments,
+    ));
+
+    let _ = write!(out, "From: {}\r\n", email.from);
+    let _ = write!(out, "To: {}\r\n", email.to);
+    // Bcc is intentionally omitted; SES delivers it from the envelope only
+    if !email.options.cc.is_empty() {
+        let _ = write!(out, "Cc: {}\r\n", email.options.cc.join(", "));
+    }
+    if !email.options.reply_to.is_empty() {
+        let _ = write!(out, "Reply-To: {}\r\n", email.options.reply_to.join(", "));
+    }
+    for (name, value) in email.headers() {
+        let _ = write!(out, "{name}: {value}\r\n");
+    }
+    let _ = write!(out, "Subject: {}\r\n", encode_header(email.subject));
+    out.push_str("MIME-Version: 1.0\r\n");
+
+    let mixed = (!attached.is_empty()).then(|| boundary("mixed"));
+    let related = (!inline.is_empty()).then(|| boundary("related"));
+    let alternative = boundary("alt");
+
+    if let Some(b) = &mixed {
+        let _ = write!(
+            out,
+            "Content-Type: multipart/mixed; boundary=\"{b}\"\r\n\r\n--{b}\r\n"
+        );
+    }
+    if let Some(b) = &related {
+        let _ = write!(
+            out,
+            "Content-Type: multipart/related; type=\"multipart/alternative\"; boundary=\"{b}\"\r\n\r\n--{b}\r\n"
+        );
+    }
+
+    let _ = write!(
+        out,
+        "Content-Type: multipart/alternative; boundary=\"{alternative}\"\r\n\r\n"
+    );
+    let _ = write!(out, "--{alternative}\r\n");
+    push_text_part(&mut out, "text/plain", email.text);
+    let _ = write!(out, "--{alternative}\r\n");
+    push_text_part(&mut out, "text/html", email.html);
+    let _ = write!(out, "--{alternative}--\r\n");
+
+    if let Some(b) = &related {
+        for attachment in &inline {
+            let _ = write!(out, "--{b}\r\n");
+            push_attachment_part(&mut out, attachment);
+        }
+        let _ = write!(out, "--{b}--\r\n");
+    }
+    if let Some(b) = &mixed {
+        for attachment in &attached {
+            let _ = write!(out, "--{b}\r\n");
+            push_attachment_part(&mut out, attachment);
+        }
+        let _ = write!(out, "--{b}--\r\n");
+    }
+
+    out
+}
+
+/// Estimates the encoded size of a message before it is built.
+///
+/// Without `text`, the derived plain-text body is assumed to be no larger
+/// than the HTML. Per-recipient variables are not taken into account.
+pub fn estimate_size(
+    subject: &str,
+    html: &str,
+    text: Option<&str>,
+    attachments: &[Attachment],
+) -> usize {
+    let text_len = text.map_or(html.len(), str::len);
+    let parts = 2 + attachments.len();
+
+    PART_OVERHEAD_BYTES
+        + base64_size(subject.len())
+        + base64_size(html.len())
+        + base64_size(text_len)
+        + attachments
+            .iter()
+            .map(|a| base64_size(a.data.len()) + a.filename.len() * 2)
+            .sum::<usize>()
+        + parts * PART_OVERHEAD_BYTES
+}
+
+/// Size of base64-encoded data including CRLF line breaks.
+const fn base64_size(len: usize) -> usize {
+    let encoded = len.div_ceil(3) * 4;
+    encoded + encoded.div_ceil(BASE64_LINE_LENGTH) * 2
+}
+
+/// Encodes a header value as RFC 2047 encoded-words when it is not plain ASCII.
+pub fn encode_header(value: &str) -> String {
+    if is_printable_ascii(value) {
+        return value.to_owned();
+    }
+    encoded_words(value).join("\r\n ")
+}
+
+/// Formats a mailbox (`Name <address>`), encoding non-ASCII display names.
+pub fn format_mailbox(address: &str, name: Option<&str>) -> String {
+    match name.map(str::trim).filter(|n| !n.is_empty()) {
+        None => address.to_owned(),
+        Some(name) if is_printable_ascii(name) => {
+            let escaped = name.replace('\\', "\\\\").replace('"', "\\\"");
+            format!("\"{escaped}\" <{address}>")
+        }
+        // Space-separated (unfolded) words: also used as the SES `FromEmailAddress`
+        Some(name) => format!("{} <{address}>", encoded_words(name).join(" ")),
+    }
+}
+
+fn is_printable_ascii(value: &str) -> bool {
+    value.bytes().all(|b| (0x20..0x7f).contains(&b))
+}
+
+/// Splits a value into base64 encoded-words without breaking UTF-8 characters.
+fn encoded_words(value: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut start = 0;
+    let mut end = 0;
+    for (i, c) in value.char_indices() {
+        let next = i + c.len_utf8();
+        if next - start > ENCODED_WORD_BYTES {
+            words.push(&value[start..end]);
+            start = end;
+        }
+        end = next;
+    }
+    words.push(&value[start..end]);
+
+    words
+        .iter()
+        .map(|word| format!("=?UTF-8?B?{}?=", STANDARD.encode(word)))
+        .collect()
+}
+
+/// Formats a `name=value` MIME parameter (RFC 2231 for non-ASCII values).
+fn parameter(name: &str, value: &str) -> String {
+    let is_plain = value
+        .bytes()
+        .all(|b| (0x20..0x7f).contains(&b) && b != b'"' && b != b'\\');
+    if is_plain {
+        return format!("{name}=\"{value}\"");
+    }
+
+    let mut encoded = String::with_capacity(value.len() * 3);
+    for b in value.bytes() {
+        if b.is_ascii_alphanumeric() || matches!(b, b'.' | b'-' | b'_') {
+            encoded.push(b as char);
+        } else {
+            let _ = write!(encoded, "%{b:02X}");
+        }
+    }
+    format!("{name}*=UTF-8''{encoded}")
+}
+
+fn boundary(kind: &str) -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.subsec_nanos());
+    let counter = BOUNDARY_COUNTER.fetch_add(1, Ordering::Relaxed);
+    // "=_" never occurs in base64 bodies, so the boundary cannot collide
+    format!("=_{kind}_{nanos:08x}{counter:x}")
+}
+
+fn push_text_part(out: &mut String, mime_type: &str, body: &str) {
+    let _ = write!(
+        out,
+        "Content-Type: {mime_type}; charset=UTF-8\r\nContent-Transfer-Encoding: base64\r\n\r\n"
+    );
+    push_base64(out, body.as_bytes());
+}
+
+fn push_attachment_part(out: &mut String, attachment: &Attachment) {
+    let _ = write!(
+        out,
+        "Content-Type: {}; {}\r\nContent-Transfer-Encoding: base64\r\n",
+        attachment.content_type,
+        parameter("name", &attachment.filename)
+    );
+    match &attachment.cid {
+        Some(cid) => {
+            let _ = write!(
+                out,
+                "Content-Disposition: inline; {}\r\nContent-ID: <{cid}>\r\n",
+                parameter("filename", &attachment.filename)
+            );
+        }
+        None => {
+            let _ = write!(
+                out,
+                "Content-Disposition: attachment; {}\r\n",
+                parameter("filename", &attachment.filename)
+            );
+        }
+    }
+    out.push_str("\r\n");
+    push_base64(out, &attachment.data);
+}
+
+fn push_base64(out: &mut String, data: &[u8]) {
+    let encoded = STANDARD.encode(data);
+    // base64 output is ASCII, so byte chunks are valid UTF-8
+    for line in encoded.as_bytes().chunks(BASE64_LINE_LENGTH) {
+        out.push_str(std::str::from_utf8(line).unwrap_or_default());
+        out.push_str("\r\n");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::models::content::MessageOptions;
+
+    static NO_OPTIONS: MessageOptions = MessageOptions {
+        from: None,
+        cc: Vec::new(),
+        bcc: Vec::new(),
+        reply_to: Vec::new(),
+        headers: BTreeMap::new(),
+        configuration_set: None,
+    };
+
+    fn attachment(filename: &str, cid: Option<&str>, data: &[u8]) -> Attachment {
+        Attachment {
+            filename: filename.to_string(),
+            content_type: "application/pdf".to_string(),
+            cid: cid.map(str::to_string),
+            data: data.to_vec(),
+        }
+    }
+
+    fn email(attachments: &[Attachment]) -> OutgoingEmail<'_> {
+        OutgoingEmail {
+            from: "sender@example.com",
+            to: "user@example.com",
+            subject: "Invoice",
+            html: "<p>Hi</p>",
+            text: "Hi",
+            attachments,
+            options: &NO_OPTIONS,
+            unsubscribe_url: None,
+            topic_id: None,
+            request_id: None,
+        }
+    }
+
+    #[test]
+    fn test_build_message_with_attachment() {
+        let attachments = [attachment("invoice.pdf", None, b"%PDF-1.4")];
+        let message = build_message(&email(&attachments));
+
+        assert!(message.starts_with("From: sender@example.com\r\nTo: user@example.com\r\n"));
+        assert!(message.contains("Content-Type: multipart/mixed; boundary="));
+        assert!(message.contains("Content-Type: multipart/alternative; boundary="));
+        assert!(!message.contains("multipart/related"));
+        assert!(message.contains("Content-Disposition: attachment; filename=\"invoice.pdf\""));
+        assert!(message.contains(&STANDARD.encode(b"%PDF-1.4")));
+        assert!(message.contains(&STANDARD.encode("<p>Hi</p>")));
+        assert!(message.ends_with("--\r\n"));
+        assert!(!message.replace("\r\n", "").contains('\n'));
+    }
+
+    #[test]
+    fn test_build_message_inline_image_uses_related() {
+        let attachments = [attachment("logo.png", Some("logo"), b"\x89PNG")];
+        let message = build_message(&email(&attachments));
+
+        assert!(message.contains("multipart/related; type=\"multipart/alternative\""));
+        assert!(message.contains("Content-Disposition: inline; filename=\"logo.png\""));
+        assert!(message.contains("Content-ID: <logo>"));
+        assert!(!message.contains("multipart/mixed"));
+    }
+
+    #[test]
+    fn test_build_message_option_headers() {
+        let attachments = [attachment("a.pdf", None, b"a")];
+        let options = MessageOptions {
+            from: None,
+            cc: vec!["a@example.com".to_string(), "b@example.com".to_string()],
+            bcc: vec!["hidden@example.com".to_string()],
+            reply_to: vec!["support@example.com".to_string()],
+            headers: BTreeMap::from([("X-Campaign".to_string(), "spring-sale".to_string())]),
+            configuration_set: None,
+        };
+        let message = build_message(&OutgoingEmail {
+            options: &options,
+            unsubscribe_url: Some("https://mail.example.com/v1/unsubscribe?request_id=1"),
+            ..email(&attachments)
+        });
+
+        assert!(message.contains("Cc: a@example.com, b@example.com\r\n"));
+        assert!(message.contains("Reply-To: support@example.com\r\n"));
+        assert!(message.contains("X-Campaign: spring-sale\r\n"));
+        assert!(message.contains(
+            "List-Unsubscribe: <https://mail.example.com/v1/unsubscribe?request_id=1>\r\n"
+        ));
+        assert!(message.contains("List-Unsubscribe-Post: List-Unsubscribe=One-Click\r\n"));
+        assert!(!message.contains("hidden@example.com"));
+    }
+
+    #[test]
+    fn test_base64_lines_are_wrapped() {
+        let attachments = [attachment("big.bin", None, &[7u8; 1000])];
+        let message = build_message(&email(&attachments));
+        assert!(message.split("\r\n").all(|line| line.len() <= 998));
+        assert!(message
+            .split("\r\n")
+            .filter(|line| line.starts_with("BwcH"))
+            .all(|line| line.len() <= BASE64_LINE_LENGTH));
+    }
+
+    #[test]
+    fn test_encode_header() {
+        assert_eq!(encode_header("Hello"), "Hello");
+        assert_eq!(encode_header("안녕"), "=?UTF-8?B?7JWI64WV?=");
+
+        let long = "가".repeat(40);
+        let encoded = encode_header(&long);
+        assert!(encoded.split("\r\n ").all(|word| word.len() <= 75));
+        assert_eq!(encoded.split("\r\n ").count(), 3);
+    }
+
+    #[test]
+    fn test_format_mailbox() {
+        assert_eq!(format_mailbox("a@example.com", None), "a@example.com");
+        assert_eq!(format_mailbox("a@example.com", Some(" ")), "a@example.com");
+        assert_eq!(
+            format_mailbox("a@example.com", Some("Shop \"Deals\"")),
+            "\"Shop \\\"Deals\\\"\" <a@example.com>"
+        );
+        assert_eq!(
+            format_mailbox("a@example.com", Some("안녕")),
+            "=?UTF-8?B?7JWI64WV?= <a@example.com>"
+        );
+    }
+
+    #[test]
+    fn test_parameter_encoding() {
+        assert_eq!(parameter("filename", "a.pdf"), "filename=\"a.pdf\"");
+        assert_eq!(
+            parameter("filename", "청구서 1.pdf"),
+            "filename*=UTF-8''%EC%B2%AD%EA%B5%AC%EC%84%9C%201.pdf"
+        );
+    }
+
+    #[test]
+    fn test_estimate_size_covers_built_message() {
+        let attachments = [
+            attachment("a.pdf", None, &[1u8; 3000]),
+            attachment("b.png", Some("b"), &[2u8; 500]),
+        ];
+        let message = build_message(&email(&attachments));
+        let estimate = estimate_size("Invoice", "<p>Hi</p>", Some("Hi"), &attachments);
+        assert!(estimate >= message.len());
+    }
+}
@@ -1,5 +1,13 @@
 //! Background email processing services
 
+pub mod address;
+pub mod limiter;
+pub mod mime;
+pub mod queue;
+pub mod quota;
 pub mod receiver;
+pub mod renderer;
+pub mod schedule;
 pub mod scheduler;
 pub mod sender;
+pub mod tracking;
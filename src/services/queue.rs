@@ -0,0 +1,582 @@
+//! Priority lanes between the producers (API, scheduler) and the sender
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use tokio::sync::mpsc::{
+    self,
+    error::{SendError, TrySendError},
+};
+
+use crate::{
+    models::request::{EmailMessageStatus, EmailRequest, Priority},
+    services::limiter::RateLimiter,
+};
+
+/// How long a stopped or paused topic is remembered (longer than any queue backlog).
+const CANCELLED_TOPIC_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Transactional emails sent for each bulk email while both lanes are busy.
+const TRANSACTIONAL_WEIGHT: u32 = 4;
+
+/// How often rate-limited requests are retried while nothing else arrives.
+const THROTTLED_RETRY_MS: u64 = 50;
+
+/// Creates the lanes, each buffering up to `buffer` requests.
+pub fn channel(buffer: usize) -> (SendQueue, PriorityReceiver) {
+    let (transactional_tx, transactional_rx) = mpsc::channel(buffer);
+    let (bulk_tx, bulk_rx) = mpsc::channel(buffer);
+    (
+        SendQueue {
+            transactional: transactional_tx,
+            bulk: bulk_tx,
+        },
+        PriorityReceiver {
+            transactional: Lane::new(transactional_rx, buffer),
+            bulk: Lane::new(bulk_rx, buffer),
+            streak: 0,
+        },
+    )
+}
+
+/// Sending side: routes each request to the lane of its priority, so a full
+/// bulk lane never blocks transactional emails.
+#[derive(Clone, Debug)]
+pub struct SendQueue {
+    transactional: mpsc::Sender<EmailRequest>,
+    bulk: mpsc::Sender<EmailRequest>,
+}
+
+impl SendQueue {
+    const fn lane(&self, priority: Priority) -> &mpsc::Sender<EmailRequest> {
+        match priority {
+            Priority::Transactional => &self.transactional,
+            Priority::Bulk => &self.bulk,
+        }
+    }
+
+    #[allow(clippy::result_large_err)]
+    pub fn try_send(&self, request: EmailRequest) -> Result<(), TrySendError<EmailRequest>> {
+        self.lane(request.priority).try_send(request)
+    }
+
+    pub async fn send(&self, request: EmailRequest) -> Result<(), SendError<EmailRequest>> {
+        self.lane(request.priority).send(request).await
+    }
+}
+
+/// Single lane shared by both priorities (used by tests that read one channel).
+impl From<mpsc::Sender<EmailRequest>> for SendQueue {
+    fn from(tx: mpsc::Sender<EmailRequest>) -> Self {
+        Self {
+            transactional: tx.clone(),
+            bulk: tx,
+        }
+    }
+}
+
+/// Receiving side: weighted round between the lanes, `TRANSACTIONAL_WEIGHT`
+/// transactional emails per bulk email. An idle lane never holds back the other,
+/// and requests held by a per-topic or per-sender limit do not block the rest.
+pub struct PriorityReceiver {
+    transactional: Lane,
+    bulk: Lane,
+    /// Transactional emails received since the last bulk email
+    streak: u32,
+}
+
+impl PriorityReceiver {
+    /// Receives the next request allowed by `limiter` (its tokens are taken),
+    /// or `None` once both lanes are closed and empty. Requests of cancelled
+    /// topics are returned without taking tokens.
+    pub async fn recv(
+        &mut self,
+        limiter: &RateLimiter,
+        cancelled: &CancelledTopics,
+    ) -> Option<EmailRequest> {
+        loop {
+            if let Some(request) = self.try_recv(limiter, cancelled) {
+                return Some(request);
+            }
+
+            let throttled = self.transactional.topics.len > 0 || self.bulk.topics.len > 0;
+            tokio::select! {
+                biased;
+                Some(request) = self.transactional.rx.recv() => self.transactional.topics.push(request),
+                Some(request) = self.bulk.rx.recv() => self.bulk.topics.push(request),
+                () = tokio::time::sleep(Duration::from_millis(THROTTLED_RETRY_MS)), if throttled => {}
+                else => return None,
+            }
+        }
+    }
+
+    fn try_recv(
+        &mut self,
+        limiter: &RateLimiter,
+        cancelled: &CancelledTopics,
+    ) -> Option<EmailRequest> {
+        if self.streak < TRANSACTIONAL_WEIGHT {
+            if let Some(request) = self.transactional.try_recv(limiter, cancelled) {
+                self.streak += 1;
+                return Some(request);
+            }
+        }
+        if let Some(request) = self.bulk.try_recv(limiter, cancelled) {
+            self.streak = 0;
+            return Some(request);
+        }
+        // No bulk email waiting: transactional keeps going past its weight
+        self.transactional.try_recv(limiter, cancelled)
+    }
+}
+
+/// Topics stopped or paused while some of their emails were already queued
+/// in memory.
+///
+/// Each entry keeps the highest request ID in flight when the topic was
+/// stopped (or paused), so emails sent to the topic afterwards are not affected.
+#[derive(Debug, Default)]
+pub struct CancelledTopics {
+    topics: Mutex<HashMap<String, TopicHold>>,
+}
+
+#[derive(Debug)]
+struct TopicHold {
+    stopped: Option<i32>,
+    paused: Option<i32>,
+    at: Instant,
+}
+
+impl CancelledTopics {
+    /// Cancels the topic's queued requests up to `max_id`.
+    pub fn cancel(&self, topic_id: &str, max_id: i32) {
+        self.hold(topic_id, |hold| hold.stopped = Some(max_id));
+    }
+
+    /// Holds back the topic's queued requests up to `max_id` until it is resumed.
+    pub fn pause(&self, topic_id: &str, max_id: i32) {
+        self.hold(topic_id, |hold| hold.paused = Some(max_id));
+    }
+
+    /// Releases a paused topic (stopped requests stay cancelled).
+    pub fn resume(&self, topic_id: &str) {
+        let mut topics = self.lock();
+        if let Some(hold) = topics.get_mut(topic_id) {
+            hold.paused = None;
+            if hold.stopped.is_none() {
+                topics.remove(topic_id);
+            }
+        }
+    }
+
+    /// Forgets a topic (resumed emails are sent again).
+    pub fn remove(&self, topic_id: &str) {
+        self.lock().remove(topic_id);
+    }
+
+    /// Checks if `request` was queued before its topic was stopped or paused.
+    pub fn is_cancelled(&self, request: &EmailRequest) -> bool {
+        self.held_status(request).is_some()
+    }
+
+    /// Status to save instead of sending `request`: `Stopped` or `Paused` if it
+    /// was queued before its topic was stopped or paused.
+    pub fn held_status(&self, request: &EmailRequest) -> Option<EmailMessageStatus> {
+        let (Some(topic_id), Some(id)) = (request.topic_id.as_deref(), request.id) else {
+            return None;
+        };
+        let (stopped, paused) = self
+            .lock()
+            .get(topic_id)
+            .map(|hold| (hold.stopped, hold.paused))?;
+        if stopped.is_some_and(|max_id| id <= max_id) {
+            Some(EmailMessageStatus::Stopped)
+        } else if paused.is_some_and(|max_id| id <= max_id) {
+            Some(EmailMessageStatus::Paused)
+        } else {
+            None
+        }
+    }
+
+    fn hold(&self, topic_id: &str, update: impl FnOnce(&mut TopicHold)) {
+        let mut topics = self.lock();
+        topics.retain(|_, hold| hold.at.elapsed() < CANCELLED_TOPIC_TTL);
+        let hold = topics
+            .entry(topic_id.to_string())
+            .or_insert_with(|| TopicHold {
+                stopped: None,
+                paused: None,
+                at: Instant::now(),
+            });
+        hold.at = Instant::now();
+        update(hold);
+        drop(topics);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, TopicHold>> {
+        self.topics
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+/// A lane with per-topic sub-queues: requests already waiting in the channel
+/// are buffered (up to `capacity`) and taken one topic at a time, so a large
+/// campaign does not hold back the topics queued after it.
+struct Lane {
+    rx: mpsc::Receiver<EmailRequest>,
+    topics: TopicQueues,
+    capacity: usize,
+}
+
+impl Lane {
+    fn new(rx: mpsc::Receiver<EmailRequest>, capacity: usize) -> Self {
+        Self {
+            rx,
+            topics: TopicQueues::default(),
+            capacity: capacity.max(1),
+        }
+    }
+
+    fn try_recv(
+        &mut self,
+        limiter: &RateLimiter,
+        cancelled: &CancelledTopics,
+    ) -> Option<EmailRequest> {
+        while self.topics.len < self.capacity {
+            let Ok(request) = self.rx.try_recv() else {
+                break;
+            };
+            self.topics.push(request);
+        }
+        self.topics.pop(limiter, cancelled)
+    }
+}
+
+/// FIFO queue per topic, dequeued round-robin across topics.
+#[derive(Default)]
+struct TopicQueues {
+    queues: HashMap<String, VecDeque<EmailRequest>>,
+    /// Topics with queued requests, in turn order
+    turns: VecDeque<String>,
+    len: usize,
+}
+
+impl TopicQueues {
+    fn push(&mut self, request: EmailRequest) {
+        let topic = request.topic_id.clone().unwrap_or_default();
+        let queue = self.queues.entry(topic.clone()).or_default();
+        if queue.is_empty() {
+            self.turns.push_back(topic);
+        }
+        queue.push_back(request);
+        self.len += 1;
+    }
+
+    /// Takes the next request whose rate limits have a token, skipping the
+    /// topics that are held back. Cancelled requests are taken right away,
+    /// leaving the tokens to the emails that are sent.
+    fn pop(&mut self, limiter: &RateLimiter, cancelled: &CancelledTopics) -> Option<EmailRequest> {
+        for _ in 0..self.turns.len() {
+            let topic = self.turns.pop_front()?;
+            let queue = self.queues.get_mut(&topic)?;
+            if !queue
+                .front()
+                .is_some_and(|r| cancelled.is_cancelled(r) || limiter.try_acquire(r))
+            {
+                self.turns.push_back(topic);
+                continue;
+            }
+
+            let request = queue.pop_front();
+            if queue.is_empty() {
+                self.queues.remove(&topic);
+            } else {
+                self.turns.push_back(topic);
+            }
+            self.len -= 1;
+            return request;
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::rate_limit::RateLimitScope;
+
+    fn request(email: &str, priority: Priority) -> EmailRequest {
+        topic_request(None, email, priority)
+    }
+
+    fn topic_request(topic_id: Option<&str>, email: &str, priority: Priority) -> EmailRequest {
+        EmailRequest {
+            id: None,
+            topic_id: topic_id.map(str::to_string),
+            content_id: None,
+            email: email.to_string(),
+            subject: std::sync::Arc::default(),
+            content: std::sync::Arc::default(),
+            text: None,
+            attachments: std::sync::Arc::default(),
+            options: std::sync::Arc::default(),
+            scheduled_at: None,
+            status: 0,
+            error: None,
+            message_id: None,
+            variables: None,
+            send_window: None,
+            priority,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_transactional_is_not_stuck_behind_bulk() {
+        let (queue, mut rx) = channel(10);
+        for i in 0..6 {
+            queue
+                .send(request(&format!("bulk{i}"), Priority::Bulk))
+                .await
+                .unwrap();
+        }
+        for i in 0..6 {
+            queue
+                .send(request(&format!("tx{i}"), Priority::Transactional))
+                .await
+                .unwrap();
+        }
+
+        let mut order = Vec::new();
+        for _ in 0..12 {
+            order.push(
+                rx.recv(&RateLimiter::default(), &CancelledTopics::default())
+                    .await
+                    .unwrap()
+                    .email,
+            );
+        }
+        assert_eq!(
+            order,
+            [
+                "tx0", "tx1", "tx2", "tx3", "bulk0", "tx4", "tx5", "bulk1", "bulk2", "bulk3",
+                "bulk4", "bulk5"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_full_bulk_lane_does_not_block_transactional() {
+        let (queue, mut rx) = channel(1);
+        queue.try_send(request("bulk0", Priority::Bulk)).unwrap();
+        assert!(matches!(
+            queue.try_send(request("bulk1", Priority::Bulk)),
+            Err(TrySendError::Full(_))
+        ));
+        queue
+            .try_send(request("tx0", Priority::Transactional))
+            .unwrap();
+        assert_eq!(
+            rx.recv(&RateLimiter::default(), &CancelledTopics::default())
+                .await
+                .unwrap()
+                .email,
+            "tx0"
+        );
+        assert_eq!(
+            rx.recv(&RateLimiter::default(), &CancelledTopics::default())
+                .await
+                .unwrap()
+                .email,
+            "bulk0"
+        );
+
+        drop(queue);
+        assert!(rx
+            .recv(&RateLimiter::default(), &CancelledTopics::default())
+            .await
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_topics_are_dequeued_round_robin() {
+        let (queue, mut rx) = channel(100);
+        for i in 0..4 {
+            queue
+                .send(topic_request(
+                    Some("big"),
+                    &format!("big{i}"),
+                    Priority::Bulk,
+                ))
+                .await
+                .unwrap();
+        }
+        for i in 0..2 {
+            queue
+                .send(topic_request(
+                    Some("small"),
+                    &format!("small{i}"),
+                    Priority::Bulk,
+                ))
+                .await
+                .unwrap();
+        }
+        queue
+            .send(topic_request(None, "none0", Priority::Bulk))
+            .await
+            .unwrap();
+
+        let mut order = Vec::new();
+        for _ in 0..7 {
+            order.push(
+                rx.recv(&RateLimiter::default(), &CancelledTopics::default())
+                    .await
+                    .unwrap()
+                    .email,
+            );
+        }
+        assert_eq!(
+            order,
+            ["big0", "small0", "none0", "big1", "small1", "big2", "big3"]
+        );
+    }
+
+    #[test]
+    fn test_cancelled_topics() {
+        let cancelled = CancelledTopics::default();
+        let queued = |topic_id, id| EmailRequest {
+            id: Some(id),
+            ..topic_request(Some(topic_id), "a@example.com", Priority::Bulk)
+        };
+        assert!(!cancelled.is_cancelled(&queued("news", 1)));
+
+        cancelled.cancel("news", 10);
+        assert!(cancelled.is_cancelled(&queued("news", 1)));
+        assert!(cancelled.is_cancelled(&queued("news", 10)));
+        // Sent to the topic after it was stopped
+        assert!(!cancelled.is_cancelled(&queued("news", 11)));
+        assert!(!cancelled.is_cancelled(&queued("other", 1)));
+        assert!(!cancelled.is_cancelled(&topic_request(None, "a@example.com", Priority::Bulk)));
+
+        cancelled.remove("news");
+        assert!(!cancelled.is_cancelled(&queued("news", 1)));
+    }
+
+    #[test]
+    fn test_paused_topics() {
+        let cancelled = CancelledTopics::default();
+        let queued = |id| EmailRequest {
+            id: Some(id),
+            ..topic_request(Some("news"), "a@example.com", Priority::Bulk)
+        };
+
+        cancelled.pause("news", 10);
+        assert_eq!(
+            cancelled.held_status(&queued(10)),
+            Some(EmailMessageStatus::Paused)
+        );
+        assert_eq!(cancelled.held_status(&queued(11)), None);
+
+        // Stopping takes precedence over the pause
+        cancelled.cancel("news", 5);
+        assert_eq!(
+            cancelled.held_status(&queued(5)),
+            Some(EmailMessageStatus::Stopped)
+        );
+        assert_eq!(
+            cancelled.held_status(&queued(6)),
+            Some(EmailMessageStatus::Paused)
+        );
+
+        cancelled.resume("news");
+        assert_eq!(
+            cancelled.held_status(&queued(5)),
+            Some(EmailMessageStatus::Stopped)
+        );
+        assert_eq!(cancelled.held_status(&queued(6)), None);
+
+        cancelled.remove("news");
+        assert_eq!(cancelled.held_status(&queued(5)), None);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_topic_does_not_block_others() {
+        let limiter = RateLimiter::default();
+        limiter.set(RateLimitScope::Topic, "partner", 1);
+
+        let (queue, mut rx) = channel(100);
+        for i in 0..3 {
+            queue
+                .send(topic_request(
+                    Some("partner"),
+                    &format!("partner{i}"),
+                    Priority::Bulk,
+                ))
+                .await
+                .unwrap();
+        }
+        for i in 0..2 {
+            queue
+                .send(topic_request(
+                    Some("news"),
+                    &format!("news{i}"),
+                    Priority::Bulk,
+                ))
+                .await
+                .unwrap();
+        }
+
+        let mut order = Vec::new();
+        for _ in 0..3 {
+            order.push(
+                rx.recv(&limiter, &CancelledTopics::default())
+                    .await
+                    .unwrap()
+                    .email,
+            );
+        }
+        assert_eq!(order, ["partner0", "news0", "news1"]);
+
+        // The next partner email waits for its bucket to refill
+        let started = std::time::Instant::now();
+        assert_eq!(
+            rx.recv(&limiter, &CancelledTopics::default())
+                .await
+                .unwrap()
+                .email,
+            "partner1"
+        );
+        assert!(started.elapsed() >= Duration::from_millis(500));
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_requests_do_not_take_tokens() {
+        let limiter = RateLimiter::default();
+        limiter.set(RateLimitScope::Topic, "partner", 1);
+        let cancelled = CancelledTopics::default();
+        cancelled.cancel("partner", 2);
+
+        let (queue, mut rx) = channel(100);
+        for id in 1..=3 {
+            queue
+                .send(EmailRequest {
+                    id: Some(id),
+                    ..topic_request(Some("partner"), &format!("partner{id}"), Priority::Bulk)
+                })
+                .await
+                .unwrap();
+        }
+
+        // Dropping the stopped emails leaves the token to the next one
+        let started = std::time::Instant::now();
+        let mut order = Vec::new();
+        for _ in 0..3 {
+            order.push(rx.recv(&limiter, &cancelled).await.unwrap().email);
+        }
+        assert_eq!(order, ["partner1", "partner2", "partner3"]);
+        assert!(started.elapsed() < Duration::from_millis(500));
+    }
+}
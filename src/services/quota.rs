@@ -0,0 +1,266 @@
+//! Rolling 24-hour send quota guard
+//!
+//! SES rejects every send once `Max24HourSend` is reached, so new work stops
+//! shortly before. Slots are reserved when a request is queued for sending
+//! (an immediate send or a scheduler claim); what cannot be reserved is stored
+//! as `Created` and waits until the window frees up.
+
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use sqlx::SqlitePool;
+
+use crate::{config::APP_CONFIG, models::request::EmailRequest};
+
+/// Length of the SES quota window.
+const WINDOW_HOURS: i32 = 24;
+
+/// Sends allowed in the rolling 24-hour window, shared by the API, the
+/// scheduler and the sender.
+#[derive(Debug)]
+pub struct DailyQuota {
+    /// `MAX_24_HOUR_SEND` (0 = not set)
+    configured: u64,
+    headroom_percent: u64,
+    /// Counters checked and updated together, so concurrent reservations
+    /// never see a stale count
+    counts: Mutex<Counts>,
+}
+
+#[derive(Debug)]
+struct Counts {
+    /// `Max24HourSend` reported by SES (0 = not known yet)
+    ses_limit: u64,
+    /// `SentLast24Hours` reported by SES (includes other senders of the account)
+    ses_sent: u64,
+    /// Sends counted since `ses_sent` was reported
+    sent_since_ses: u64,
+    /// Sent or being sent by this service in the window
+    sent: u64,
+    /// Reserved slots whose requests are not stored as `Processed` yet
+    pending: u64,
+}
+
+impl Counts {
+    /// Sends of the window: our count, or the one reported by SES plus the
+    /// sends made since (0 until SES reported), whichever is higher.
+    const fn used(&self) -> u64 {
+        let ses_sent = self.ses_sent + self.sent_since_ses;
+        if self.sent > ses_sent {
+            self.sent
+        } else {
+            ses_sent
+        }
+    }
+}
+
+impl Default for DailyQuota {
+    fn default() -> Self {
+        Self::new(
+            APP_CONFIG.max_24_hour_send,
+            APP_CONFIG.quota_headroom_percent,
+        )
+    }
+}
+
+impl DailyQuota {
+    pub const fn new(configured: u64, headroom_percent: u64) -> Self {
+        Self {
+            configured,
+            headroom_percent,
+            counts: Mutex::new(Counts {
+                ses_limit: 0,
+                ses_sent: 0,
+                sent_since_ses: 0,
+                sent: 0,
+                pending: 0,
+            }),
+        }
+    }
+
+    /// Applies the quota reported by SES `GetAccount`.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    pub fn set_ses_quota(&self, max_24_hour_send: f64, sent_last_24_hours: f64) {
+        let mut counts = self.lock();
+        counts.ses_limit = max_24_hour_send.max(0.0) as u64;
+        counts.ses_sent = sent_last_24_hours.max(0.0) as u64;
+        counts.sent_since_ses = 0;
+    }
+
+    /// Recounts the sends of the window from the database.
+    ///
+    /// Reservations not stored yet are added on top. Pending counts from before
+    /// and after the query are both considered, so a reservation committed
+    /// meanwhile is counted (at worst twice, until the next refresh).
+    pub async fn refresh(&self, db_pool: &SqlitePool) -> Result<(), sqlx::Error> {
+        let pending_before = self.lock().pending;
+        let stored = EmailRequest::rolling_sent_count(db_pool, WINDOW_HOURS).await?;
+        let mut counts = self.lock();
+        counts.sent = u64::try_from(stored).unwrap_or(0) + pending_before.max(counts.pending);
+        drop(counts);
+        Ok(())
+    }
+
+    /// Sends allowed in the window: the lower of the configured and SES
+    /// limits minus the headroom (`None` = no limit known).
+    pub fn limit(&self) -> Option<u64> {
+        let ses_limit = self.lock().ses_limit;
+        self.limit_with(ses_limit)
+    }
+
+    /// Sends left in the window.
+    pub fn remaining(&self) -> u64 {
+        self.limit()
+            .map_or(u64::MAX, |limit| limit.saturating_sub(self.lock().used()))
+    }
+
+    /// Atomically reserves up to `n` sends and returns how many were granted.
+    ///
+    /// Granted slots must be passed to [`Self::commit`] once their requests are
+    /// stored as `Processed`, or to [`Self::release`] if they are not.
+    pub fn try_reserve(&self, n: u64) -> u64 {
+        let mut counts = self.lock();
+        let used = counts.used();
+        let granted = self
+            .limit_with(counts.ses_limit)
+            .map_or(n, |limit| n.min(limit.saturating_sub(used)));
+        counts.sent += granted;
+        counts.pending += granted;
+        if counts.ses_limit > 0 {
+            counts.sent_since_ses += granted;
+        }
+        drop(counts);
+        granted
+    }
+
+    /// Marks reserved slots as stored: the database count now includes them.
+    pub fn commit(&self, n: u64) {
+        let mut counts = self.lock();
+        counts.pending = counts.pending.saturating_sub(n);
+    }
+
+    /// Returns reserved slots that were not used.
+    pub fn release(&self, n: u64) {
+        let mut counts = self.lock();
+        counts.pending = counts.pending.saturating_sub(n);
+        counts.sent = counts.sent.saturating_sub(n);
+        counts.sent_since_ses = counts.sent_since_ses.saturating_sub(n);
+    }
+
+    fn limit_with(&self, ses_limit: u64) -> Option<u64> {
+        [self.configured, ses_limit]
+            .into_iter()
+            .filter(|limit| *limit > 0)
+            .min()
+            .map(|limit| limit - limit * self.headroom_percent.min(100) / 100)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Counts> {
+        self.counts.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn test_no_limit_until_known() {
+        let quota = DailyQuota::new(0, 1);
+        assert_eq!(quota.limit(), None);
+        assert_eq!(quota.remaining(), u64::MAX);
+        assert_eq!(quota.try_reserve(5), 5);
+    }
+
+    #[test]
+    fn test_lower_limit_with_headroom() {
+        let quota = DailyQuota::new(50_000, 10);
+        assert_eq!(quota.limit(), Some(45_000));
+
+        quota.set_ses_quota(200.0, 0.0);
+        assert_eq!(quota.limit(), Some(180));
+        quota.set_ses_quota(100_000.0, 0.0);
+        assert_eq!(quota.limit(), Some(45_000));
+    }
+
+    #[test]
+    fn test_try_reserve_stops_at_limit() {
+        let quota = DailyQuota::new(3, 0);
+        assert_eq!(quota.try_reserve(1), 1);
+        assert_eq!(quota.remaining(), 2);
+        // Only what is left is granted
+        assert_eq!(quota.try_reserve(5), 2);
+        assert_eq!(quota.try_reserve(1), 0);
+        assert_eq!(quota.remaining(), 0);
+
+        quota.release(2);
+        assert_eq!(quota.try_reserve(5), 2);
+
+        // Sends reported by SES (e.g. other services of the account) count too
+        let quota = DailyQuota::new(10, 0);
+        quota.set_ses_quota(10.0, 9.0);
+        assert_eq!(quota.try_reserve(3), 1);
+        assert_eq!(quota.try_reserve(1), 0);
+    }
+
+    #[test]
+    fn test_concurrent_reservations_stay_within_limit() {
+        // Without SES figures, and with SES reporting most of the window used
+        for (ses_sent, expected) in [(None, 1_000), (Some(900.0), 100)] {
+            let quota = DailyQuota::new(1_000, 0);
+            if let Some(ses_sent) = ses_sent {
+                quota.set_ses_quota(1_000.0, ses_sent);
+            }
+            let granted = AtomicU64::new(0);
+            std::thread::scope(|scope| {
+                for _ in 0..8 {
+                    scope.spawn(|| {
+                        for _ in 0..100 {
+                            granted.fetch_add(quota.try_reserve(3), Ordering::Relaxed);
+                        }
+                    });
+                }
+            });
+            assert_eq!(granted.into_inner(), expected);
+        }
+    }
+
+    #[test]
+    fn test_release_returns_slots_counted_since_ses() {
+        let quota = DailyQuota::new(0, 0);
+        quota.set_ses_quota(200.0, 0.0);
+        assert_eq!(quota.try_reserve(1_000), 200);
+        // Nothing was claimed: every slot goes back
+        quota.release(200);
+        assert_eq!(quota.remaining(), 200);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_frees_the_window() {
+        let db = crate::config::init_test_db().await;
+        let quota = DailyQuota::new(1, 0);
+        assert_eq!(quota.try_reserve(1), 1);
+        quota.commit(1);
+        assert_eq!(quota.try_reserve(1), 0);
+
+        // Nothing was sent in the last 24 hours
+        quota.refresh(&db).await.unwrap();
+        assert_eq!(quota.try_reserve(1), 1);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_keeps_pending_reservations() {
+        let db = crate::config::init_test_db().await;
+        let quota = DailyQuota::new(5, 0);
+        assert_eq!(quota.try_reserve(3), 3);
+
+        // Reserved but not stored yet: the database has nothing to count
+        quota.refresh(&db).await.unwrap();
+        assert_eq!(quota.remaining(), 2);
+
+        quota.release(3);
+        quota.refresh(&db).await.unwrap();
+        assert_eq!(quota.remaining(), 5);
+    }
+}
@@ -16,11 +16,26 @@ use tracing::{debug, error, info, warn};
 use crate::{
     config::APP_CONFIG,
     models::request::{EmailMessageStatus, EmailRequest},
+    services::{
+        limiter::RateLimiter,
+        mime::format_mailbox,
+        queue::{CancelledTopics, PriorityReceiver},
+        quota::DailyQuota,
+        renderer::{html_to_text, render},
+        sender::{get_send_quota, send_email, throttled_count, OutgoingEmail},
+        tracking::{rewrite_links, unsubscribe_url},
+    },
 };
 
 // Token bucket configuration
 const TOKEN_REFILL_INTERVAL_MS: u64 = 100;
 
+// Adaptive rate: after a throttled second the rate drops to 3/4, then it
+// climbs back by 1/20 of the quota per second
+const THROTTLE_BACKOFF_NUMERATOR: u64 = 3;
+const THROTTLE_BACKOFF_DENOMINATOR: u64 = 4;
+const RATE_RECOVERY_STEPS: u64 = 20;
+
 // Batch update configuration
 const BATCH_SIZE: usize = 100;
 const BATCH_FLUSH_INTERVAL_MS: u64 = 500;
@@ -29,7 +44,7 @@ const BATCH_RECV_TIMEOUT_MS: u64 = 100;
 /// Event-driven token bucket for rate limiting.
 struct TokenBucket {
     tokens: AtomicU64,
-    max_per_sec: u64,
+    max_per_sec: AtomicU64,
     notify: Notify,
 }
 
@@ -37,11 +52,25 @@ impl TokenBucket {
     fn new(max_per_sec: u64) -> Self {
         Self {
             tokens: AtomicU64::new(max_per_sec),
-            max_per_sec,
+            max_per_sec: AtomicU64::new(max_per_sec),
             notify: Notify::new(),
         }
     }
 
+    fn max_per_sec(&self) -> u64 {
+        self.max_per_sec.load(Ordering::Acquire)
+    }
+
+    /// Changes the rate; tokens above the new rate are dropped.
+    fn set_rate(&self, max_per_sec: u64) {
+        self.max_per_sec.store(max_per_sec, Ordering::Release);
+        let _ = self
+            .tokens
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |current| {
+                (current > max_per_sec).then_some(max_per_sec)
+            });
+    }
+
     /// Acquires a token, waiting if necessary.
     async fn acquire(&self) {
         loop {
@@ -65,11 +94,12 @@ impl TokenBucket {
     }
 
     fn refill(&self, amount: u64) {
+        let max_per_sec = self.max_per_sec();
         let _ = self
             .tokens
             .fetch_update(Ordering::AcqRel, Ordering::Acquire, |current| {
-                if current < self.max_per_sec {
-                    Some((current + amount).min(self.max_per_sec))
+                if current < max_per_sec {
+                    Some((current + amount).min(max_per_sec))
                 } else {
                     None
                 }
@@ -78,58 +108,151 @@ impl TokenBucket {
     }
 
     fn reset(&self) {
-        self.tokens.store(self.max_per_sec, Ordering::Release);
+        self.tokens.store(self.max_per_sec(), Ordering::Release);
         self.notify.notify_waiters();
     }
 }
 
+/// Send rate following the SES quota (`MaxSendRate`): lowered after SES
+/// throttles a send, then raised back gradually up to the quota.
+#[derive(Debug, PartialEq, Eq)]
+struct AdaptiveRate {
+    quota: u64,
+    current: u64,
+}
+
+impl AdaptiveRate {
+    const fn new(quota: u64) -> Self {
+        Self {
+            quota,
+            current: quota,
+        }
+    }
+
+    /// Applies a new quota; a rate lowered by throttling stays lowered.
+    fn set_quota(&mut self, quota: u64) {
+        let quota = quota.max(1);
+        self.current = if self.current >= self.quota {
+            quota
+        } else {
+            self.current.min(quota)
+        };
+        self.quota = quota;
+    }
+
+    /// Adjusts the rate after one second, `throttled` if SES returned 429s.
+    fn tick(&mut self, throttled: bool) -> u64 {
+        self.current = if throttled {
+            (self.current * THROTTLE_BACKOFF_NUMERATOR / THROTTLE_BACKOFF_DENOMINATOR).max(1)
+        } else {
+            (self.current + (self.quota / RATE_RECOVERY_STEPS).max(1)).min(self.quota)
+        };
+        self.current
+    }
+}
+
 /// Sends emails with rate limiting using Token Bucket + Semaphore.
+///
+/// The bucket runs at `MAX_SEND_PER_SECOND`, lowered to the SES account quota
+/// (`MaxSendRate`) once it is known, and slows down while SES throttles sends.
+/// The current rate is published through `send_rate`. Requests are taken from
+/// the priority lanes, transactional ones first and round-robin across topics
+/// within a lane, once they got a token from their topic and sender limits;
+/// the global bucket is acquired last. Queued requests already hold a slot of
+/// the 24-hour quota, reserved when they were queued. Requests of topics
+/// stopped or paused after they were queued are marked `Stopped` or `Paused`
+/// without being sent.
+#[allow(clippy::too_many_lines)]
 pub async fn receive_send_message(
-    mut rx: mpsc::Receiver<EmailRequest>,
+    mut rx: PriorityReceiver,
     tx: mpsc::Sender<EmailRequest>,
+    rate_limiter: Arc<RateLimiter>,
+    daily_quota: Arc<DailyQuota>,
+    cancelled_topics: Arc<CancelledTopics>,
+    send_rate: Arc<AtomicU64>,
 ) {
     let max_per_sec = u64::try_from(APP_CONFIG.max_send_per_second.max(1)).unwrap_or(1);
+    send_rate.store(max_per_sec, Ordering::Release);
 
     let bucket = Arc::new(TokenBucket::new(max_per_sec));
     let last_refill_ms = Arc::new(AtomicU64::new(current_time_ms()));
     let semaphore = Arc::new(Semaphore::new(
         usize::try_from(max_per_sec).unwrap_or(1) * 2,
     ));
+    let quota = Arc::new(AtomicU64::new(max_per_sec));
 
     let server_url: Arc<str> = APP_CONFIG.server_url.clone().into();
     let from_email: Arc<str> = APP_CONFIG.aws_ses_from_email.clone().into();
+    let tracking_secret: Arc<str> = APP_CONFIG.tracking_secret.clone().into();
 
-    spawn_token_refill_task(Arc::clone(&bucket), Arc::clone(&last_refill_ms));
+    spawn_quota_refresh_task(
+        Arc::clone(&quota),
+        Arc::clone(&daily_quota),
+        Arc::clone(&semaphore),
+        max_per_sec,
+    );
+    spawn_token_refill_task(
+        Arc::clone(&bucket),
+        Arc::clone(&last_refill_ms),
+        Arc::clone(&quota),
+        send_rate,
+    );
 
     info!("Email sender started: {max_per_sec} emails/sec");
 
-    while let Some(request) = rx.recv().await {
+    while let Some(mut request) = rx.recv(&rate_limiter, &cancelled_topics).await {
+        if let Some(status) = cancelled_topics.held_status(&request) {
+            debug!("Held back {} (topic {status:?})", request.email);
+            request.status = status as i32;
+            drop(tx.send(request).await);
+            continue;
+        }
         bucket.acquire().await;
 
         let request_id = request.id.unwrap_or_default();
-        // Clone content from Arc and append tracking pixel
-        // This defers the clone to send time (vs creation time for all emails)
-        let mut content = (*request.content).clone();
+        // Render per-recipient variables, rewrite links and append tracking pixel
+        // This defers the copy to send time (vs creation time for all emails).
+        let variables = request.variables.as_ref();
+        let subject = render(&request.subject, variables, false);
+        let content = render(&request.content, variables, true);
+        let text = request.text.as_ref().map(|t| render(t, variables, false));
+        // Text is derived before link rewriting so it shows the original URLs
+        let text = text.unwrap_or_else(|| html_to_text(&content));
+        let mut content = rewrite_links(&content, &server_url, &tracking_secret, request_id);
+        let unsubscribe_url = unsubscribe_url(&server_url, &tracking_secret, request_id);
         let _ = write!(
             content,
             "<img src=\"{server_url}/v1/events/open?request_id={request_id}\">"
         );
 
         let tx_clone = tx.clone();
-        let from_email = Arc::clone(&from_email);
-        let subject = Arc::clone(&request.subject);
+        let from: Arc<str> = request.options.from.as_ref().map_or_else(
+            || Arc::clone(&from_email),
+            |f| format_mailbox(&f.email, f.name.as_deref()).into(),
+        );
         let email = request.email.clone();
         let Ok(permit) = semaphore.clone().acquire_owned().await else {
             break;
         };
 
         // Move request ownership into spawned task for result handling
-        let mut request = request;
         tokio::spawn(async move {
             let _permit = permit;
 
-            match crate::services::sender::send_email(&from_email, &email, &subject, &content).await
-            {
+            let outgoing = OutgoingEmail {
+                from: &from,
+                to: &email,
+                subject: &subject,
+                html: &content,
+                text: &text,
+                attachments: &request.attachments,
+                options: &request.options,
+                unsubscribe_url: Some(&unsubscribe_url),
+                topic_id: request.topic_id.as_deref(),
+                request_id: request.id,
+            };
+
+            match send_email(&outgoing).await {
                 Ok(message_id) => {
                     debug!("Sent to {}: {message_id}", request.email);
                     request.status = EmailMessageStatus::Sent as i32;
@@ -153,12 +276,75 @@ pub async fn receive_send_message(
 fn current_time_ms() -> u64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
-        .map(|d| d.as_millis() as u64)
-        .unwrap_or(0)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+/// Refreshes the SES send quota every `SES_QUOTA_REFRESH_SECS` and resizes
+/// the semaphore with it. The rate never exceeds the configured
+/// `MAX_SEND_PER_SECOND` (`initial`), and the previous quota is kept if
+/// `GetAccount` fails.
+fn spawn_quota_refresh_task(
+    quota: Arc<AtomicU64>,
+    daily_quota: Arc<DailyQuota>,
+    semaphore: Arc<Semaphore>,
+    initial: u64,
+) {
+    tokio::spawn(async move {
+        let mut permits = initial * 2;
+        let mut interval = tokio::time::interval(Duration::from_secs(
+            APP_CONFIG.ses_quota_refresh_secs.max(1),
+        ));
+        loop {
+            interval.tick().await;
+
+            match get_send_quota().await {
+                Ok(send_quota) => {
+                    daily_quota
+                        .set_ses_quota(send_quota.max_24_hour_send, send_quota.sent_last_24_hours);
+                    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                    let max_send_rate =
+                        capped_send_rate(initial, send_quota.max_send_rate.floor() as u64);
+                    if quota.swap(max_send_rate, Ordering::AcqRel) != max_send_rate {
+                        info!(
+                            "SES quota: {max_send_rate} emails/sec, {}/{} sent in 24h",
+                            send_quota.sent_last_24_hours, send_quota.max_24_hour_send
+                        );
+                    }
+                    permits = resize_semaphore(&semaphore, permits, max_send_rate * 2);
+                }
+                Err(e) => warn!("Failed to fetch SES send quota: {e}"),
+            }
+        }
+    });
+}
+
+/// Send rate for an SES `MaxSendRate`: never above the configured rate.
+fn capped_send_rate(configured: u64, max_send_rate: u64) -> u64 {
+    max_send_rate.min(configured).max(1)
+}
+
+/// Grows or shrinks the semaphore from `permits` towards `target` and returns
+/// the new size. Permits held by in-flight sends cannot be forgotten, so a
+/// shrink may only complete on a later refresh.
+fn resize_semaphore(semaphore: &Semaphore, permits: u64, target: u64) -> u64 {
+    if target > permits {
+        semaphore.add_permits(usize::try_from(target - permits).unwrap_or(0));
+        target
+    } else {
+        let excess = usize::try_from(permits - target).unwrap_or(0);
+        permits - semaphore.forget_permits(excess) as u64
+    }
 }
 
-fn spawn_token_refill_task(bucket: Arc<TokenBucket>, last_refill_ms: Arc<AtomicU64>) {
+fn spawn_token_refill_task(
+    bucket: Arc<TokenBucket>,
+    last_refill_ms: Arc<AtomicU64>,
+    quota: Arc<AtomicU64>,
+    send_rate: Arc<AtomicU64>,
+) {
     tokio::spawn(async move {
+        let mut rate = AdaptiveRate::new(bucket.max_per_sec());
+        let mut last_throttled = throttled_count();
         let mut interval = tokio::time::interval(Duration::from_millis(TOKEN_REFILL_INTERVAL_MS));
         loop {
             interval.tick().await;
@@ -167,10 +353,20 @@ fn spawn_token_refill_task(bucket: Arc<TokenBucket>, last_refill_ms: Arc<AtomicU
             let last = last_refill_ms.load(Ordering::Acquire);
 
             if now_ms.saturating_sub(last) >= 1000 {
+                let throttled = throttled_count();
+                rate.set_quota(quota.load(Ordering::Acquire));
+                let max_per_sec = rate.tick(throttled > last_throttled);
+                if max_per_sec != bucket.max_per_sec() {
+                    debug!("Send rate: {max_per_sec} emails/sec");
+                    bucket.set_rate(max_per_sec);
+                    send_rate.store(max_per_sec, Ordering::Release);
+                }
+                last_throttled = throttled;
+
                 bucket.reset();
                 last_refill_ms.store(now_ms, Ordering::Release);
             } else {
-                let refill = bucket.max_per_sec.div_ceil(10);
+                let refill = bucket.max_per_sec().div_ceil(10);
                 bucket.refill(refill);
             }
         }
@@ -322,6 +518,7 @@ mod tests {
     use sqlx::Row;
 
     use super::*;
+    use crate::models::request::Priority;
 
     async fn setup_db() -> SqlitePool {
         let pool = SqlitePoolOptions::new()
@@ -390,7 +587,7 @@ mod tests {
     fn test_token_bucket_new() {
         let bucket = TokenBucket::new(10);
         assert_eq!(bucket.tokens.load(Ordering::Acquire), 10);
-        assert_eq!(bucket.max_per_sec, 10);
+        assert_eq!(bucket.max_per_sec(), 10);
     }
 
     #[test]
@@ -440,6 +637,63 @@ mod tests {
         assert_eq!(bucket.tokens.load(Ordering::Acquire), 10);
     }
 
+    #[test]
+    fn test_token_bucket_set_rate() {
+        let bucket = TokenBucket::new(10);
+        bucket.set_rate(4);
+        assert_eq!(bucket.tokens.load(Ordering::Acquire), 4);
+
+        bucket.set_rate(8);
+        assert_eq!(bucket.tokens.load(Ordering::Acquire), 4);
+        bucket.reset();
+        assert_eq!(bucket.tokens.load(Ordering::Acquire), 8);
+    }
+
+    #[test]
+    fn test_adaptive_rate() {
+        let mut rate = AdaptiveRate::new(24);
+
+        // The SES quota replaces the fallback rate
+        rate.set_quota(200);
+        assert_eq!(rate.tick(false), 200);
+
+        // Throttled: 3/4 of the rate, then back up by 10/sec (quota / 20)
+        assert_eq!(rate.tick(true), 150);
+        assert_eq!(rate.tick(true), 112);
+        assert_eq!(rate.tick(false), 122);
+
+        // A refreshed quota does not undo the backoff unless it is lower
+        rate.set_quota(400);
+        assert_eq!(rate.tick(false), 142);
+        rate.set_quota(100);
+        assert_eq!(rate.tick(false), 100);
+
+        let mut rate = AdaptiveRate::new(1);
+        assert_eq!(rate.tick(true), 1);
+    }
+
+    #[test]
+    fn test_capped_send_rate() {
+        // A configured rate below the SES quota is kept
+        assert_eq!(capped_send_rate(10, 200), 10);
+        assert_eq!(capped_send_rate(50, 14), 14);
+        assert_eq!(capped_send_rate(10, 0), 1);
+    }
+
+    #[test]
+    fn test_resize_semaphore() {
+        let semaphore = Semaphore::new(20);
+        assert_eq!(resize_semaphore(&semaphore, 20, 28), 28);
+        assert_eq!(semaphore.available_permits(), 28);
+
+        // Held permits are only forgotten once they are free again
+        let held = semaphore.try_acquire_many(25).unwrap();
+        assert_eq!(resize_semaphore(&semaphore, 28, 10), 25);
+        drop(held);
+        assert_eq!(resize_semaphore(&semaphore, 25, 10), 10);
+        assert_eq!(semaphore.available_permits(), 10);
+    }
+
     #[tokio::test]
     async fn test_token_bucket_acquire() {
         let bucket = Arc::new(TokenBucket::new(2));
@@ -478,10 +732,16 @@ mod tests {
                 email: "test1@test.com".to_string(),
                 subject: Arc::new(String::new()),
                 content: Arc::new(String::new()),
+                text: None,
+                attachments: Arc::default(),
+                options: Arc::default(),
                 scheduled_at: None,
                 status: EmailMessageStatus::Sent as i32,
                 message_id: Some("msg_1".to_string()),
                 error: None,
+                variables: None,
+                send_window: None,
+                priority: Priority::Bulk,
             },
             EmailRequest {
                 id: Some(2),
@@ -490,10 +750,16 @@ mod tests {
                 email: "test2@test.com".to_string(),
                 subject: Arc::new(String::new()),
                 content: Arc::new(String::new()),
+                text: None,
+                attachments: Arc::default(),
+                options: Arc::default(),
                 scheduled_at: None,
                 status: EmailMessageStatus::Failed as i32,
                 message_id: None,
                 error: Some("Rate limit".to_string()),
+                variables: None,
+                send_window: None,
+                priority: Priority::Bulk,
             },
             EmailRequest {
                 id: Some(3),
@@ -502,10 +768,16 @@ mod tests {
                 email: "test3@test.com".to_string(),
                 subject: Arc::new(String::new()),
                 content: Arc::new(String::new()),
+                text: None,
+                attachments: Arc::default(),
+                options: Arc::default(),
                 scheduled_at: None,
                 status: EmailMessageStatus::Sent as i32,
                 message_id: Some("msg_3".to_string()),
                 error: None,
+                variables: None,
+                send_window: None,
+                priority: Priority::Bulk,
             },
         ];
 
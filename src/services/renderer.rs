@@ -0,0 +1,386 @@
+//! Per-recipient template rendering and HTML-to-text conversion
+
+use std::{collections::HashMap, fmt::Write as _};
+
+/// Replaces `{{name}}` placeholders with values from `variables`.
+///
+/// - Whitespace inside the braces is ignored (`{{ name }}` == `{{name}}`)
+/// - Unknown variables render as an empty string, also when `variables` is `None`
+/// - Anything that is not a valid variable name (e.g. `{{ a b }}`) is left untouched
+/// - With `escape_html`, substituted values are HTML-escaped (use for HTML bodies)
+pub fn render(
+    template: &str,
+    variables: Option<&HashMap<String, String>>,
+    escape_html: bool,
+) -> String {
+    if !template.contains("{{") {
+        return template.to_owned();
+    }
+
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let after_open = &rest[start + 2..];
+
+        let Some(end) = after_open.find("}}") else {
+            out.push_str(&rest[start..]);
+            return out;
+        };
+
+        let key = after_open[..end].trim();
+        if is_variable_name(key) {
+            if let Some(value) = variables.and_then(|v| v.get(key)) {
+                if escape_html {
+                    push_html_escaped(&mut out, value);
+                } else {
+                    out.push_str(value);
+                }
+            }
+        } else {
+            out.push_str(&rest[start..start + 2 + end + 2]);
+        }
+
+        rest = &after_open[end + 2..];
+    }
+
+    out.push_str(rest);
+    out
+}
+
+fn is_variable_name(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+}
+
+fn push_html_escaped(out: &mut String, value: &str) {
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+}
+
+/// Derives a readable plain-text version of an HTML body.
+///
+/// Block elements become line breaks, list items are prefixed with `- `,
+/// `<script>`/`<style>`/`<head>` are dropped and links are kept as numbered
+/// footnotes (`label [1]` ... `[1] https://...`).
+pub fn html_to_text(html: &str) -> String {
+    let mut out = String::with_capacity(html.len() / 2);
+    let mut links: Vec<String> = Vec::new();
+    // (href, position in `out` where the link label starts)
+    let mut open_link: Option<(String, usize)> = None;
+    let mut skip_until: Option<&'static str> = None;
+    let mut rest = html;
+
+    while let Some(lt) = rest.find('<') {
+        if skip_until.is_none() {
+            push_text(&mut out, &rest[..lt]);
+        }
+        let after = &rest[lt + 1..];
+
+        if let Some(comment) = after.strip_prefix("!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+
+        let Some(gt) = after.find('>') else {
+            rest = &rest[lt..];
+            break;
+        };
+        let tag = &after[..gt];
+        rest = &after[gt + 1..];
+
+        let closing = tag.starts_with('/');
+        let tag = tag.trim_start_matches('/');
+        let name_end = tag
+            .find(|c: char| c.is_whitespace() || c == '/')
+            .unwrap_or(tag.len());
+        let name = tag[..name_end].to_ascii_lowercase();
+
+        if let Some(skip) = skip_until {
+            if closing && name == skip {
+                skip_until = None;
+            }
+            continue;
+        }
+
+        match (name.as_str(), closing) {
+            ("script", false) => skip_until = Some("script"),
+            ("style", false) => skip_until = Some("style"),
+            ("head", false) => skip_until = Some("head"),
+            ("br", _) => out.push('\n'),
+            ("li", false) => {
+                push_newline(&mut out);
+                out.push_str("- ");
+            }
+            ("hr", _) => {
+                push_newline(&mut out);
+                out.push_str("----------\n");
+            }
+            ("td" | "th", true) => out.push(' '),
+            (
+                "p" | "div" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "table" | "tr" | "ul"
+                | "ol" | "blockquote" | "section" | "article" | "header" | "footer" | "li",
+                _,
+            ) => push_newline(&mut out),
+            ("a", false) => {
+                open_link = attribute(&tag[name_end..], "href").map(|href| (href, out.len()));
+            }
+            ("a", true) => {
+                if let Some((href, start)) = open_link.take() {
+                    let label = out[start..].trim();
+                    let href_is_label =
+                        label == href || Some(label) == href.strip_prefix("mailto:");
+                    if is_footnote_link(&href) && !href_is_label {
+                        links.push(href);
+                        let _ = write!(out, " [{}]", links.len());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if skip_until.is_none() {
+        push_text(&mut out, rest);
+    }
+
+    let mut text = normalize_lines(&out);
+    if !links.is_empty() {
+        text.push_str("\n\n");
+        for (i, link) in links.iter().enumerate() {
+            let _ = writeln!(text, "[{}] {link}", i + 1);
+        }
+        text.pop();
+    }
+    text
+}
+
+/// Appends text content with HTML whitespace collapsing and entity decoding.
+fn push_text(out: &mut String, raw: &str) {
+    let decoded = decode_entities(raw);
+    for c in decoded.chars() {
+        if c.is_whitespace() && c != '\u{a0}' {
+            if !out.is_empty() && !out.ends_with([' ', '\n']) {
+                out.push(' ');
+            }
+        } else if c == '\u{a0}' {
+            out.push(' ');
+        } else {
+            out.push(c);
+        }
+    }
+}
+
+fn push_newline(out: &mut String) {
+    while out.ends_with(' ') {
+        out.pop();
+    }
+    if !out.is_empty() && !out.ends_with('\n') {
+        out.push('\n');
+    }
+}
+
+/// Trims each line and collapses runs of blank lines into one.
+fn normalize_lines(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut blank_run = 0;
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() {
+            blank_run += 1;
+            continue;
+        }
+        if !result.is_empty() {
+            result.push_str(if blank_run > 0 { "\n\n" } else { "\n" });
+        }
+        result.push_str(line);
+        blank_run = 0;
+    }
+    result
+}
+
+fn is_footnote_link(href: &str) -> bool {
+    !href.is_empty()
+        && !href.starts_with('#')
+        && !href.to_ascii_lowercase().starts_with("javascript:")
+}
+
+/// Extracts an attribute value from the inside of a tag (after its name).
+fn attribute(attrs: &str, name: &str) -> Option<String> {
+    let lower = attrs.to_ascii_lowercase();
+    let mut search_from = 0;
+
+    while let Some(pos) = lower[search_from..].find(name) {
+        let start = search_from + pos;
+        search_from = start + name.len();
+
+        let preceded_ok = start == 0 || lower.as_bytes()[start - 1].is_ascii_whitespace();
+        let value = attrs[search_from..].trim_start();
+        let Some(value) = value.strip_prefix('=').filter(|_| preceded_ok) else {
+            continue;
+        };
+        let value = value.trim_start();
+
+        let raw = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => {
+                let inner = &value[1..];
+                &inner[..inner.find(quote).unwrap_or(inner.len())]
+            }
+            _ => {
+                &value[..value
+                    .find(|c: char| c.is_whitespace() || c == '>')
+                    .unwrap_or(value.len())]
+            }
+        };
+        return Some(decode_entities(raw.trim()));
+    }
+    None
+}
+
+/// Decodes the common named entities and numeric character references.
+pub fn decode_entities(raw: &str) -> String {
+    if !raw.contains('&') {
+        return raw.to_owned();
+    }
+
+    let mut out = String::with_capacity(raw.len());
+    let mut rest = raw;
+
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        let after = &rest[amp + 1..];
+        let decoded = after.find(';').filter(|&end| end <= 10).and_then(|end| {
+            let entity = &after[..end];
+            let c = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some('\u{a0}'),
+                _ => entity
+                    .strip_prefix("#x")
+                    .or_else(|| entity.strip_prefix("#X"))
+                    .map_or_else(
+                        || entity.strip_prefix('#').and_then(|n| n.parse().ok()),
+                        |hex| u32::from_str_radix(hex, 16).ok(),
+                    )
+                    .and_then(char::from_u32),
+            };
+            c.map(|c| (c, end))
+        });
+
+        if let Some((c, end)) = decoded {
+            out.push(c);
+            rest = &after[end + 1..];
+        } else {
+            out.push('&');
+            rest = after;
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| ((*k).to_string(), (*v).to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_render_substitutes_variables() {
+        let v = vars(&[("name", "홍길동"), ("coupon_code", "WELCOME10")]);
+        let result = render("Hi {{name}}, use {{ coupon_code }}!", Some(&v), false);
+        assert_eq!(result, "Hi 홍길동, use WELCOME10!");
+    }
+
+    #[test]
+    fn test_render_unknown_variable_is_empty() {
+        let result = render("Hi {{name}}!", Some(&HashMap::new()), false);
+        assert_eq!(result, "Hi !");
+    }
+
+    #[test]
+    fn test_render_without_variables_matches_empty_variables() {
+        let template = "Hi {{name}}, see {{ a b }}";
+        let with_empty = render(template, Some(&HashMap::new()), false);
+        assert_eq!(render(template, None, false), with_empty);
+        assert_eq!(with_empty, "Hi , see {{ a b }}");
+    }
+
+    #[test]
+    fn test_render_escapes_html_values() {
+        let v = vars(&[("name", "<b>\"Tom\" & 'Jerry'</b>")]);
+        let result = render("<p>{{name}}</p>", Some(&v), true);
+        assert_eq!(
+            result,
+            "<p>&lt;b&gt;&quot;Tom&quot; &amp; &#39;Jerry&#39;&lt;/b&gt;</p>"
+        );
+    }
+
+    #[test]
+    fn test_render_leaves_non_placeholders_untouched() {
+        let v = vars(&[("a", "x")]);
+        assert_eq!(render("{{ a b }} {{a}}", Some(&v), false), "{{ a b }} x");
+        assert_eq!(render("open {{a", Some(&v), false), "open {{a");
+        assert_eq!(
+            render("no placeholders", Some(&v), false),
+            "no placeholders"
+        );
+    }
+
+    #[test]
+    fn test_html_to_text_blocks_and_lists() {
+        let html = "<h1>Title</h1><p>First   paragraph\n line</p><ul><li>One</li><li>Two</li></ul>";
+        assert_eq!(
+            html_to_text(html),
+            "Title\nFirst paragraph line\n- One\n- Two"
+        );
+    }
+
+    #[test]
+    fn test_html_to_text_links_as_footnotes() {
+        let html = r##"<p>Visit <a href="https://example.com/a?x=1&amp;y=2">our shop</a> or
+            <a href='https://example.com'>https://example.com</a> and <a href="#top">top</a></p>"##;
+        assert_eq!(
+            html_to_text(html),
+            "Visit our shop [1] or https://example.com and top\n\n[1] https://example.com/a?x=1&y=2"
+        );
+    }
+
+    #[test]
+    fn test_html_to_text_drops_head_style_script_and_comments() {
+        let html = "<html><head><title>x</title><style>p { color: red; }</style></head>\
+                    <body><!-- hidden --><script>alert(1)</script><p>Body</p></body></html>";
+        assert_eq!(html_to_text(html), "Body");
+    }
+
+    #[test]
+    fn test_html_to_text_decodes_entities() {
+        let html = "<p>Tom &amp; Jerry &lt;3 &#54620;&#xAE00; &copy;&nbsp;x</p>";
+        assert_eq!(html_to_text(html), "Tom & Jerry <3 한글 &copy; x");
+    }
+
+    #[test]
+    fn test_html_to_text_keeps_placeholders() {
+        let html = "<p>Hi {{name}},<br>bye</p>";
+        assert_eq!(html_to_text(html), "Hi {{name}},\nbye");
+    }
+}
@@ -0,0 +1,509 @@
+//! Scheduled send time parsing (RFC 3339 or local time in an IANA timezone),
+//! cron expressions for recurring schedules and daily send windows
+
+use std::fmt;
+
+use chrono::{DateTime, Days, LocalResult, NaiveDateTime, NaiveTime, SecondsFormat, TimeZone, Utc};
+use chrono_tz::Tz;
+use croner::Cron;
+use thiserror::Error;
+
+use crate::config::APP_CONFIG;
+
+/// `SQLite` `datetime()` format used for stored times (always UTC).
+pub const STORAGE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Local time formats accepted without an offset (interpreted in the request timezone).
+const LOCAL_FORMATS: &[&str] = &["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S"];
+
+/// Clock skew allowed between the client and this server.
+const PAST_TOLERANCE_SECS: i64 = 60;
+
+/// Longest accepted `send_after_seconds` (one year).
+pub const MAX_SEND_AFTER_SECONDS: u64 = 365 * 24 * 60 * 60;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ScheduleError {
+    #[error("invalid scheduled_at '{0}' (expected RFC 3339 or 'YYYY-MM-DD HH:MM:SS')")]
+    InvalidFormat(String),
+
+    #[error("unknown timezone '{0}' (expected an IANA name such as 'Asia/Seoul')")]
+    UnknownTimezone(String),
+
+    #[error("scheduled_at '{0}' does not exist in {1} (daylight saving time gap)")]
+    NonexistentLocalTime(String, Tz),
+
+    #[error("scheduled_at '{0}' is in the past")]
+    InPast(String),
+
+    #[error("scheduled_at and send_after_seconds cannot be combined")]
+    Conflicting,
+
+    #[error("send_after_seconds {0} exceeds {MAX_SEND_AFTER_SECONDS}")]
+    DelayTooLong(u64),
+
+    #[error("spread_over_seconds {0} exceeds {MAX_SEND_AFTER_SECONDS}")]
+    SpreadTooLong(u64),
+
+    #[error("invalid cron expression '{0}' (expected 'minute hour day month weekday')")]
+    InvalidCron(String),
+
+    #[error("invalid send window '{0}' (expected 'HH:MM' start and end that differ)")]
+    InvalidSendWindow(String),
+}
+
+/// Daily local time range in which emails may be sent, e.g. `09:00`–`21:00`.
+/// An `end` before `start` crosses midnight (`22:00`–`06:00`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SendWindow {
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+impl SendWindow {
+    /// Creates a window from `HH:MM` start and end times.
+    pub fn new(start: &str, end: &str) -> Result<Self, ScheduleError> {
+        let parse = |time: &str| NaiveTime::parse_from_str(time.trim(), "%H:%M").ok();
+        match (parse(start), parse(end)) {
+            (Some(start), Some(end)) if start != end => Ok(Self { start, end }),
+            _ => Err(ScheduleError::InvalidSendWindow(format!("{start}-{end}"))),
+        }
+    }
+
+    /// Parses a window stored with `to_string` (`HH:MM-HH:MM`).
+    pub fn parse(stored: &str) -> Result<Self, ScheduleError> {
+        stored
+            .split_once('-')
+            .ok_or_else(|| ScheduleError::InvalidSendWindow(stored.to_string()))
+            .and_then(|(start, end)| Self::new(start, end))
+    }
+
+    /// Returns the `[open, close)` window in `timezone` that contains `at`,
+    /// or the next one if `at` is outside the window.
+    pub fn next(self, timezone: Tz, at: DateTime<Utc>) -> (DateTime<Utc>, DateTime<Utc>) {
+        let today = at.with_timezone(&timezone).date_naive();
+        let crosses_midnight = self.end < self.start;
+
+        let windows = [today - Days::new(1), today, today + Days::new(1)].map(|date| {
+            let close_date = if crosses_midnight {
+                date + Days::new(1)
+            } else {
+                date
+            };
+            (
+                localize(timezone, date.and_time(self.start)),
+                localize(timezone, close_date.and_time(self.end)),
+            )
+        });
+        // The window opening tomorrow always closes after `at`
+        windows
+            .into_iter()
+            .find(|(_, close)| *close > at)
+            .unwrap_or(windows[2])
+    }
+}
+
+impl fmt::Display for SendWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}-{}",
+            self.start.format("%H:%M"),
+            self.end.format("%H:%M")
+        )
+    }
+}
+
+/// Converts a local time to UTC; times skipped by a daylight saving change
+/// move forward by an hour, repeated times use the earlier one.
+fn localize(timezone: Tz, local: NaiveDateTime) -> DateTime<Utc> {
+    timezone
+        .from_local_datetime(&local)
+        .earliest()
+        .or_else(|| {
+            timezone
+                .from_local_datetime(&(local + chrono::Duration::hours(1)))
+                .earliest()
+        })
+        .map_or_else(|| local.and_utc(), |time| time.with_timezone(&Utc))
+}
+
+/// Resolves an IANA timezone name (`None` = `DEFAULT_TIMEZONE`).
+pub fn parse_timezone(name: Option<&str>) -> Result<Tz, ScheduleError> {
+    name.map(str::trim)
+        .filter(|n| !n.is_empty())
+        .map_or(Ok(APP_CONFIG.default_timezone), |name| {
+            name.parse()
+                .map_err(|_| ScheduleError::UnknownTimezone(name.to_string()))
+        })
+}
+
+/// Parses a scheduled send time into UTC.
+///
+/// RFC 3339 timestamps carry their own offset (`2025-01-01T09:00:00+09:00`);
+/// local times without one (`2025-01-01 09:00:00`) are interpreted in
+/// `timezone`. Times that do not exist locally (DST gap) and times before
+/// `now` are rejected; ambiguous local times (DST overlap) use the earlier one.
+pub fn parse_scheduled_at(
+    input: &str,
+    timezone: Tz,
+    now: DateTime<Utc>,
+) -> Result<DateTime<Utc>, ScheduleError> {
+    let input = input.trim();
+
+    let utc = if let Ok(dt) = DateTime::parse_from_rfc3339(input) {
+        dt.with_timezone(&Utc)
+    } else {
+        let naive = LOCAL_FORMATS
+            .iter()
+            .find_map(|format| NaiveDateTime::parse_from_str(input, format).ok())
+            .ok_or_else(|| ScheduleError::InvalidFormat(input.to_string()))?;
+        match timezone.from_local_datetime(&naive) {
+            LocalResult::Single(dt) | LocalResult::Ambiguous(dt, _) => dt.with_timezone(&Utc),
+            LocalResult::None => {
+                return Err(ScheduleError::NonexistentLocalTime(
+                    input.to_string(),
+                    timezone,
+                ))
+            }
+        }
+    };
+
+    if utc < now - chrono::Duration::seconds(PAST_TOLERANCE_SECS) {
+        return Err(ScheduleError::InPast(input.to_string()));
+    }
+    Ok(utc)
+}
+
+/// Resolves a send time given as an absolute `scheduled_at` or as a delay
+/// from `now` (`send_after_seconds`). Returns `None` if neither is set.
+pub fn resolve(
+    scheduled_at: Option<&str>,
+    send_after_seconds: Option<u64>,
+    timezone: Tz,
+    now: DateTime<Utc>,
+) -> Result<Option<DateTime<Utc>>, ScheduleError> {
+    let scheduled_at = scheduled_at.map(str::trim).filter(|s| !s.is_empty());
+    match (scheduled_at, send_after_seconds) {
+        (Some(_), Some(_)) => Err(ScheduleError::Conflicting),
+        (Some(input), None) => parse_scheduled_at(input, timezone, now).map(Some),
+        (None, Some(seconds)) if seconds > MAX_SEND_AFTER_SECONDS => {
+            Err(ScheduleError::DelayTooLong(seconds))
+        }
+        (None, Some(seconds)) => {
+            #[allow(clippy::cast_possible_wrap)]
+            let delay = chrono::Duration::seconds(seconds as i64);
+            Ok(Some(now + delay))
+        }
+        (None, None) => Ok(None),
+    }
+}
+
+/// Delay of the `index`-th of `count` recipients spread evenly over
+/// `spread_over_seconds` (the first one is not delayed).
+pub fn spread_offset(spread_over_seconds: u64, index: usize, count: usize) -> chrono::Duration {
+    if count == 0 {
+        return chrono::Duration::zero();
+    }
+    let millis = u128::from(spread_over_seconds) * 1000 * index as u128 / count as u128;
+    chrono::Duration::milliseconds(i64::try_from(millis).unwrap_or(i64::MAX))
+}
+
+/// Parses a standard 5-field cron expression (`0 9 * * MON`).
+pub fn parse_cron(expression: &str) -> Result<Cron, ScheduleError> {
+    let expression = expression.trim();
+    Cron::new(expression)
+        .parse()
+        .map_err(|_| ScheduleError::InvalidCron(expression.to_string()))
+}
+
+/// Returns up to `count` occurrences of `cron` in `timezone` strictly after `after`.
+///
+/// Local times skipped by a daylight saving change run at the first valid
+/// time after the gap; repeated local times run once.
+pub fn next_runs(
+    cron: &Cron,
+    timezone: Tz,
+    after: DateTime<Utc>,
+    count: usize,
+) -> Vec<DateTime<Utc>> {
+    cron.iter_after(after.with_timezone(&timezone))
+        .take(count)
+        .map(|time| time.with_timezone(&Utc))
+        .collect()
+}
+
+/// Formats a time for storage and `datetime('now')` comparisons.
+pub fn to_storage(time: DateTime<Utc>) -> String {
+    time.format(STORAGE_FORMAT).to_string()
+}
+
+/// Parses a time stored with `to_storage`.
+pub fn from_storage(value: &str) -> Option<DateTime<Utc>> {
+    NaiveDateTime::parse_from_str(value, STORAGE_FORMAT)
+        .ok()
+        .map(|time| time.and_utc())
+}
+
+/// Formats a time for API responses (`2025-01-01T00:00:00Z`).
+pub fn to_rfc3339(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono_tz::{America::New_York, Asia::Seoul};
+
+    fn past() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2020, 1, 1, 0, 0, 0).unwrap()
+    }
+
+    fn parse(input: &str, timezone: Tz) -> Result<String, ScheduleError> {
+        parse_scheduled_at(input, timezone, past()).map(to_storage)
+    }
+
+    #[test]
+    fn test_parse_timezone() {
+        assert_eq!(parse_timezone(Some("America/New_York")), Ok(New_York));
+        assert_eq!(parse_timezone(None), Ok(APP_CONFIG.default_timezone));
+        assert_eq!(parse_timezone(Some(" ")), Ok(APP_CONFIG.default_timezone));
+        assert!(matches!(
+            parse_timezone(Some("KST")),
+            Err(ScheduleError::UnknownTimezone(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_rfc3339_with_offset() {
+        // The offset wins over the request timezone
+        assert_eq!(
+            parse("2025-12-27T15:30:45+09:00", New_York).unwrap(),
+            "2025-12-27 06:30:45"
+        );
+        assert_eq!(
+            parse("2025-12-27T06:30:45Z", Seoul).unwrap(),
+            "2025-12-27 06:30:45"
+        );
+    }
+
+    #[test]
+    fn test_parse_local_time_in_timezone() {
+        // KST 15:30:45 -> UTC 06:30:45
+        assert_eq!(
+            parse("2025-12-27 15:30:45", Seoul).unwrap(),
+            "2025-12-27 06:30:45"
+        );
+        // KST 2025-01-01 08:00:00 -> UTC 2024-12-31 23:00:00 (crosses date)
+        assert_eq!(
+            parse("2025-01-01T08:00:00", Seoul).unwrap(),
+            "2024-12-31 23:00:00"
+        );
+        // EDT (UTC-4) in summer, EST (UTC-5) in winter
+        assert_eq!(
+            parse("2025-07-01 09:00:00", New_York).unwrap(),
+            "2025-07-01 13:00:00"
+        );
+        assert_eq!(
+            parse("2025-01-15 09:00:00", New_York).unwrap(),
+            "2025-01-15 14:00:00"
+        );
+        // Leap day
+        assert_eq!(
+            parse("2024-02-29 12:00:00", Seoul).unwrap(),
+            "2024-02-29 03:00:00"
+        );
+    }
+
+    #[test]
+    fn test_parse_dst_transitions() {
+        // 02:30 does not exist when New York springs forward
+        assert!(matches!(
+            parse("2025-03-09 02:30:00", New_York),
+            Err(ScheduleError::NonexistentLocalTime(_, _))
+        ));
+        // 01:30 happens twice when it falls back: the earlier (EDT) one is used
+        assert_eq!(
+            parse("2025-11-02 01:30:00", New_York).unwrap(),
+            "2025-11-02 05:30:00"
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_invalid_format() {
+        for input in [
+            "",
+            "invalid",
+            "2025-13-01 12:00:00",
+            "2025-01-32 12:00:00",
+            "2025-01-15 25:00:00",
+            "2025-02-29 12:00:00",
+            "2025/01/15 12:00:00",
+            "2025-01-15",
+            "2025-01-15'; DROP TABLE--",
+            "2025-01-15 12:00:00xxxx",
+            "2025-01-15 12:00:00 +09:00",
+            "２０２５-01-15 12:00:00",
+        ] {
+            assert!(
+                matches!(parse(input, Seoul), Err(ScheduleError::InvalidFormat(_))),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_rejects_past() {
+        let now = Utc.with_ymd_and_hms(2025, 6, 1, 0, 0, 0).unwrap();
+        assert!(matches!(
+            parse_scheduled_at("2025-05-31T23:00:00Z", Seoul, now),
+            Err(ScheduleError::InPast(_))
+        ));
+        // Small clock skew is tolerated
+        assert!(parse_scheduled_at("2025-05-31T23:59:30Z", Seoul, now).is_ok());
+    }
+
+    #[test]
+    fn test_resolve() {
+        let now = Utc.with_ymd_and_hms(2025, 6, 1, 0, 0, 0).unwrap();
+        let resolve = |scheduled_at, seconds| resolve(scheduled_at, seconds, Seoul, now);
+
+        assert_eq!(resolve(None, None), Ok(None));
+        assert_eq!(resolve(Some(" "), None), Ok(None));
+        assert_eq!(
+            resolve(Some("2025-06-01 18:00:00"), None)
+                .unwrap()
+                .map(to_storage),
+            Some("2025-06-01 09:00:00".to_string())
+        );
+        assert_eq!(
+            resolve(None, Some(3600)).unwrap().map(to_storage),
+            Some("2025-06-01 01:00:00".to_string())
+        );
+        assert_eq!(resolve(None, Some(0)), Ok(Some(now)));
+
+        assert_eq!(
+            resolve(Some("2025-06-01 18:00:00"), Some(60)),
+            Err(ScheduleError::Conflicting)
+        );
+        assert_eq!(
+            resolve(None, Some(MAX_SEND_AFTER_SECONDS + 1)),
+            Err(ScheduleError::DelayTooLong(MAX_SEND_AFTER_SECONDS + 1))
+        );
+    }
+
+    #[test]
+    fn test_spread_offset() {
+        assert_eq!(spread_offset(3600, 0, 4), chrono::Duration::zero());
+        assert_eq!(spread_offset(3600, 1, 4), chrono::Duration::minutes(15));
+        assert_eq!(spread_offset(3600, 3, 4), chrono::Duration::minutes(45));
+        assert_eq!(
+            spread_offset(10, 1, 3),
+            chrono::Duration::milliseconds(3333)
+        );
+        assert_eq!(spread_offset(3600, 0, 0), chrono::Duration::zero());
+    }
+
+    #[test]
+    fn test_parse_cron() {
+        assert!(parse_cron("0 9 * * MON").is_ok());
+        assert!(parse_cron(" */15 * * * * ").is_ok());
+        for expression in ["", "0 9 * *", "61 * * * *", "0 9 * * XYZ", "every monday"] {
+            assert!(
+                matches!(parse_cron(expression), Err(ScheduleError::InvalidCron(_))),
+                "{expression}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_next_runs_in_timezone() {
+        // Mondays 09:00 in Seoul = 00:00 UTC
+        let cron = parse_cron("0 9 * * MON").unwrap();
+        let after = Utc.with_ymd_and_hms(2025, 6, 1, 0, 0, 0).unwrap(); // Sunday
+        let runs: Vec<String> = next_runs(&cron, Seoul, after, 2)
+            .into_iter()
+            .map(to_storage)
+            .collect();
+        assert_eq!(runs, ["2025-06-02 00:00:00", "2025-06-09 00:00:00"]);
+
+        // Strictly after: an occurrence at `after` itself is not returned
+        let runs = next_runs(
+            &cron,
+            Seoul,
+            Utc.with_ymd_and_hms(2025, 6, 2, 0, 0, 0).unwrap(),
+            1,
+        );
+        assert_eq!(to_storage(runs[0]), "2025-06-09 00:00:00");
+
+        // Daily 09:00 in New York follows daylight saving time
+        let cron = parse_cron("0 9 * * *").unwrap();
+        let after = Utc.with_ymd_and_hms(2025, 3, 8, 0, 0, 0).unwrap();
+        let runs: Vec<String> = next_runs(&cron, New_York, after, 2)
+            .into_iter()
+            .map(to_storage)
+            .collect();
+        assert_eq!(runs, ["2025-03-08 14:00:00", "2025-03-09 13:00:00"]);
+    }
+
+    #[test]
+    fn test_send_window_parse() {
+        let window = SendWindow::new("09:00", " 21:30").unwrap();
+        assert_eq!(window.to_string(), "09:00-21:30");
+        assert_eq!(SendWindow::parse("09:00-21:30"), Ok(window));
+
+        for (start, end) in [
+            ("09:00", "09:00"),
+            ("9am", "21:00"),
+            ("09:00", "24:00"),
+            ("", ""),
+        ] {
+            assert!(
+                matches!(
+                    SendWindow::new(start, end),
+                    Err(ScheduleError::InvalidSendWindow(_))
+                ),
+                "{start}-{end}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_send_window_next() {
+        let window = SendWindow::new("09:00", "21:00").unwrap();
+        let window_at = |time: &str| {
+            let (open, close) = window.next(Seoul, from_storage(time).unwrap());
+            (to_storage(open), to_storage(close))
+        };
+        // Seoul 09:00-21:00 = UTC 00:00-12:00
+        let today = (
+            "2025-06-02 00:00:00".to_string(),
+            "2025-06-02 12:00:00".to_string(),
+        );
+        let tomorrow = (
+            "2025-06-03 00:00:00".to_string(),
+            "2025-06-03 12:00:00".to_string(),
+        );
+
+        assert_eq!(window_at("2025-06-01 20:00:00"), today); // 05:00 local, before opening
+        assert_eq!(window_at("2025-06-02 03:00:00"), today); // 12:00 local, inside
+        assert_eq!(window_at("2025-06-02 12:00:00"), tomorrow); // 21:00 local, just closed
+        assert_eq!(window_at("2025-06-02 14:00:00"), tomorrow); // 23:00 local
+
+        // Crossing midnight: 22:00-06:00 in New York (EDT, UTC-4)
+        let night = SendWindow::new("22:00", "06:00").unwrap();
+        let (open, close) = night.next(New_York, from_storage("2025-06-02 06:00:00").unwrap());
+        assert_eq!(to_storage(open), "2025-06-02 02:00:00");
+        assert_eq!(to_storage(close), "2025-06-02 10:00:00");
+        let (open, _) = night.next(New_York, from_storage("2025-06-02 12:00:00").unwrap());
+        assert_eq!(to_storage(open), "2025-06-03 02:00:00");
+    }
+
+    #[test]
+    fn test_to_rfc3339() {
+        let time = Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap();
+        assert_eq!(to_rfc3339(time), "2025-01-01T00:00:00Z");
+        assert_eq!(from_storage(&to_storage(time)), Some(time));
+        assert_eq!(from_storage("2025-01-01T00:00:00Z"), None);
+    }
+}
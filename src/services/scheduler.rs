@@ -1,13 +1,28 @@
 //! Scheduled email pickup service
 
-use std::{sync::Arc, time::Duration};
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
+use chrono::Utc;
+use chrono_tz::Tz;
 use sqlx::SqlitePool;
 use thiserror::Error;
-use tokio::sync::mpsc;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
-use crate::models::request::{EmailMessageStatus, EmailRequest};
+use crate::{
+    constants::BATCH_INSERT_SIZE,
+    models::{
+        attachment::Attachment,
+        content::MessageOptions,
+        recurring::RecurringSchedule,
+        request::{EmailMessageStatus, EmailRequest, Priority},
+        suppression::Suppression,
+    },
+    services::{queue::SendQueue, quota::DailyQuota, schedule},
+};
 
 const BATCH_SIZE: i32 = 1000;
 
@@ -15,15 +30,45 @@ const BATCH_SIZE: i32 = 1000;
 const IDLE_DELAY_SECS: u64 = 10;
 const BATCH_DELAY_MS: u64 = 100;
 const ERROR_BACKOFF_SECS: u64 = 5;
+const QUOTA_REFRESH_SECS: u64 = 10;
 
-/// Polls for scheduled emails and forwards them to the sending queue.
-pub async fn schedule_pre_send_message(tx: &mpsc::Sender<EmailRequest>, db_pool: SqlitePool) {
+/// Polls for scheduled emails and forwards them to the sending queue, as far
+/// as the 24-hour send quota allows.
+pub async fn schedule_pre_send_message(
+    tx: &SendQueue,
+    db_pool: SqlitePool,
+    daily_quota: &DailyQuota,
+) {
     info!("Scheduler started: batch_size={BATCH_SIZE}");
 
     let mut consecutive_empty = 0u32;
+    let mut quota_refreshed_at: Option<Instant> = None;
+    let mut holding = false;
 
     loop {
-        match fetch_and_process_batch(tx, &db_pool).await {
+        if quota_refreshed_at.is_none_or(|at| at.elapsed().as_secs() >= QUOTA_REFRESH_SECS) {
+            if let Err(e) = daily_quota.refresh(&db_pool).await {
+                error!("Daily quota refresh error: {e}");
+            }
+            quota_refreshed_at = Some(Instant::now());
+        }
+        if holding != (daily_quota.remaining() == 0) {
+            holding = !holding;
+            if holding {
+                warn!("24-hour send quota reached, holding scheduled emails");
+            } else {
+                info!("24-hour send quota available again, resuming");
+            }
+        }
+
+        if let Err(e) = materialize_recurring_schedules(&db_pool).await {
+            error!("Recurring schedule error: {e}");
+        }
+        if let Err(e) = defer_closed_windows(&db_pool).await {
+            error!("Send window error: {e}");
+        }
+
+        match fetch_and_process_batch(tx, &db_pool, daily_quota).await {
             Ok(0) => {
                 consecutive_empty += 1;
                 let delay = if consecutive_empty > 5 {
@@ -56,6 +101,91 @@ enum SchedulerError {
     ChannelClosed,
 }
 
+/// Creates the requests of every due recurring schedule run.
+///
+/// Requests are scheduled at the run time, so the following batch picks them
+/// up. Runs missed while the service was down are created once, not per miss.
+async fn materialize_recurring_schedules(db_pool: &SqlitePool) -> Result<u64, SchedulerError> {
+    let now = Utc::now();
+    let mut created = 0;
+
+    for recurring in RecurringSchedule::due(db_pool).await? {
+        let (Ok(cron), Ok(timezone)) = (
+            schedule::parse_cron(&recurring.cron),
+            recurring.timezone.parse::<Tz>(),
+        ) else {
+            error!("Schedule {} has an invalid cron or timezone", recurring.id);
+            continue;
+        };
+        let next_run_at = schedule::next_runs(&cron, timezone, now, 1)
+            .first()
+            .map(|time| schedule::to_storage(*time));
+        let topic_id = run_topic_id(&recurring, timezone);
+
+        if let Some(count) = recurring
+            .materialize(db_pool, &topic_id, next_run_at.as_deref())
+            .await?
+        {
+            info!(
+                "Schedule {} created {count} requests (topic={topic_id})",
+                recurring.id
+            );
+            created += count;
+        }
+    }
+    Ok(created)
+}
+
+/// Topic of a run: `{topic_id}-{YYYYMMDDHHMM}` in the schedule's timezone.
+fn run_topic_id(recurring: &RecurringSchedule, timezone: Tz) -> String {
+    let run_at = recurring
+        .next_run_at
+        .as_deref()
+        .and_then(schedule::from_storage)
+        .unwrap_or_else(Utc::now);
+    format!(
+        "{}-{}",
+        recurring.topic_id,
+        run_at.with_timezone(&timezone).format("%Y%m%d%H%M")
+    )
+}
+
+/// Moves queued requests whose send window closed before they were claimed
+/// to the next opening of that window in their timezone.
+async fn defer_closed_windows(db_pool: &SqlitePool) -> Result<usize, SchedulerError> {
+    let rows = EmailRequest::find_closed_windows(db_pool, BATCH_SIZE).await?;
+    let count = rows.len();
+
+    let mut groups: HashMap<(String, String), Vec<i32>> = HashMap::new();
+    for (id, window, timezone) in rows {
+        groups.entry((window, timezone)).or_default().push(id);
+    }
+
+    let now = Utc::now();
+    for ((window, timezone), ids) in groups {
+        let (Ok(send_window), Ok(tz)) =
+            (schedule::SendWindow::parse(&window), timezone.parse::<Tz>())
+        else {
+            error!(
+                "Invalid send window {window} ({timezone}) on {} requests",
+                ids.len()
+            );
+            continue;
+        };
+        let (open, close) = send_window.next(tz, now);
+        let scheduled_at = schedule::to_storage(open.max(now));
+        let ends_at = schedule::to_storage(close);
+        for chunk in ids.chunks(BATCH_INSERT_SIZE) {
+            EmailRequest::defer_to_window(db_pool, chunk, &scheduled_at, &ends_at).await?;
+        }
+    }
+
+    if count > 0 {
+        debug!("Deferred {count} requests to their next send window");
+    }
+    Ok(count)
+}
+
 /// Row returned from UPDATE...RETURNING (without content fields).
 #[derive(sqlx::FromRow)]
 struct UpdatedRow {
@@ -77,34 +207,96 @@ struct ScheduledEmailRow {
     email: String,
     subject: String,
     content: String,
+    text: Option<String>,
+    options: Option<String>,
+    variables: Option<String>,
+    priority: i32,
 }
 
+/// Claims due emails: transactional first, then one email per topic per turn.
+///
+/// Only the first `?3` due emails of each topic are ranked, so a poll never
+/// sorts a whole queued campaign. The topics of each lane are walked with
+/// `MIN(topic_id)` index seeks (`idx_requests_status_priority_topic_scheduled`),
+/// and each topic's head is read from the same index in `scheduled_at` order.
+///
+/// Binds: `?1` claimed status, `?2` created status, `?3` batch limit,
+/// `?4`/`?5` transactional/bulk priority.
+const CLAIM_SQL: &str = "
+    UPDATE email_requests
+    SET status = ?1, updated_at = datetime('now')
+    WHERE id IN (
+        WITH RECURSIVE lanes(priority, topic_id) AS (
+            SELECT lane.priority, (SELECT MIN(topic_id) FROM email_requests
+                                   WHERE status = ?2 AND priority = lane.priority)
+            FROM (SELECT ?4 AS priority UNION ALL SELECT ?5) AS lane
+            UNION ALL
+            SELECT lanes.priority, (SELECT MIN(topic_id) FROM email_requests
+                                    WHERE status = ?2 AND priority = lanes.priority
+                                      AND topic_id > lanes.topic_id)
+            FROM lanes
+            WHERE lanes.topic_id IS NOT NULL
+        ),
+        heads AS (
+            SELECT r.id, r.priority, r.scheduled_at,
+                   ROW_NUMBER() OVER (PARTITION BY r.priority, r.topic_id
+                                      ORDER BY r.scheduled_at, r.id) AS turn
+            FROM lanes
+            JOIN email_requests r ON r.id IN (
+                SELECT id FROM email_requests
+                WHERE status = ?2 AND priority = lanes.priority AND topic_id = lanes.topic_id
+                  AND scheduled_at <= datetime('now')
+                  AND (send_window_ends_at IS NULL OR send_window_ends_at > datetime('now'))
+                ORDER BY scheduled_at, id
+                LIMIT ?3
+            )
+        )
+        SELECT id FROM heads
+        ORDER BY priority ASC, turn ASC, scheduled_at ASC
+        LIMIT ?3
+    )
+    RETURNING id, topic_id, content_id, email";
+
 /// Atomically claims and processes a batch of scheduled emails.
 ///
+/// Due emails are claimed transactional first, then taking turns between
+/// topics so one large campaign does not hold back the others. No more are
+/// claimed than the 24-hour quota has left.
+///
 /// Uses two-phase approach to avoid per-row subqueries in RETURNING:
 /// 1. UPDATE...RETURNING to atomically claim emails and get basic info
 /// 2. Single JOIN query to fetch content for all claimed emails
 async fn fetch_and_process_batch(
-    tx: &mpsc::Sender<EmailRequest>,
+    tx: &SendQueue,
     db_pool: &SqlitePool,
+    daily_quota: &DailyQuota,
 ) -> Result<usize, SchedulerError> {
+    let reserved = daily_quota.try_reserve(BATCH_SIZE.unsigned_abs().into());
+    if reserved == 0 {
+        return Ok(0);
+    }
+    let limit = i32::try_from(reserved).unwrap_or(BATCH_SIZE);
+
     // Phase 1: Atomically update and return basic info (no subqueries)
-    let updated: Vec<UpdatedRow> = sqlx::query_as(
-        "UPDATE email_requests
-         SET status = ?, updated_at = datetime('now')
-         WHERE id IN (
-             SELECT id FROM email_requests
-             WHERE status = ? AND scheduled_at <= datetime('now')
-             ORDER BY scheduled_at ASC
-             LIMIT ?
-         )
-         RETURNING id, topic_id, content_id, email",
-    )
-    .bind(EmailMessageStatus::Processed as i32)
-    .bind(EmailMessageStatus::Created as i32)
-    .bind(BATCH_SIZE)
-    .fetch_all(db_pool)
-    .await?;
+    let claimed: Result<Vec<UpdatedRow>, sqlx::Error> = sqlx::query_as(CLAIM_SQL)
+        .bind(EmailMessageStatus::Processed as i32)
+        .bind(EmailMessageStatus::Created as i32)
+        .bind(limit)
+        .bind(Priority::Transactional as i32)
+        .bind(Priority::Bulk as i32)
+        .fetch_all(db_pool)
+        .await;
+    let updated = match claimed {
+        Ok(updated) => updated,
+        Err(e) => {
+            daily_quota.release(reserved);
+            return Err(e.into());
+        }
+    };
+    // Claimed rows are now counted as `Processed`; the rest of the slots are returned
+    let claimed = updated.len() as u64;
+    daily_quota.commit(claimed);
+    daily_quota.release(reserved - claimed);
 
     if updated.is_empty() {
         return Ok(0);
@@ -116,7 +308,8 @@ async fn fetch_and_process_batch(
     let ids: Vec<i64> = updated.iter().map(|r| r.id).collect();
     let placeholders = vec!["?"; ids.len()].join(",");
     let sql = format!(
-        "SELECT r.id, r.topic_id, r.content_id, r.email, c.subject, c.content
+        "SELECT r.id, r.topic_id, r.content_id, r.email, c.subject, c.content, c.text, c.options, r.variables,
+                r.priority
          FROM email_requests r
          JOIN email_contents c ON r.content_id = c.id
          WHERE r.id IN ({placeholders})"
@@ -128,6 +321,30 @@ async fn fetch_and_process_batch(
     }
     let rows = query.fetch_all(db_pool).await?;
 
+    // Load attachments once per content and share them across its requests
+    let mut content_ids: Vec<i32> = rows.iter().map(|r| r.content_id).collect();
+    content_ids.sort_unstable();
+    content_ids.dedup();
+    let attachments: HashMap<i32, Arc<Vec<Attachment>>> =
+        Attachment::find_by_content_ids(db_pool, &content_ids)
+            .await?
+            .into_iter()
+            .map(|(content_id, files)| (content_id, Arc::new(files)))
+            .collect();
+
+    // Recipients suppressed after the request was created are skipped, not sent
+    let emails: Vec<&str> = rows.iter().map(|r| r.email.as_str()).collect();
+    let suppressed = Suppression::find_suppressed(db_pool, &emails).await?;
+    let (rows, skipped): (Vec<_>, Vec<_>) = rows
+        .into_iter()
+        .partition(|r| !suppressed.contains(&Suppression::normalize(&r.email)));
+    if !skipped.is_empty() {
+        #[allow(clippy::cast_possible_truncation)]
+        let ids: Vec<i32> = skipped.iter().map(|r| r.id as i32).collect();
+        EmailRequest::mark_skipped(db_pool, &ids).await?;
+        info!("Skipped {} suppressed recipients", ids.len());
+    }
+
     for row in rows {
         #[allow(clippy::cast_possible_truncation)]
         let request = EmailRequest {
@@ -137,10 +354,19 @@ async fn fetch_and_process_batch(
             email: row.email,
             subject: Arc::new(row.subject),
             content: Arc::new(row.content),
+            text: row.text.map(Arc::new),
+            attachments: attachments
+                .get(&row.content_id)
+                .map(Arc::clone)
+                .unwrap_or_default(),
+            options: Arc::new(MessageOptions::from_json(row.options.as_deref())),
             scheduled_at: None,
             status: EmailMessageStatus::Processed as i32,
             error: None,
             message_id: None,
+            variables: EmailRequest::parse_variables(row.variables.as_deref()),
+            send_window: None,
+            priority: Priority::from_i32(row.priority),
         };
 
         if tx.send(request).await.is_err() {
@@ -154,6 +380,7 @@ async fn fetch_and_process_batch(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::{seed_content, seed_scheduled_request};
 
     #[test]
     fn test_scheduler_error_display() {
@@ -161,6 +388,210 @@ mod tests {
         assert_eq!(err.to_string(), "Send channel closed");
     }
 
+    #[tokio::test]
+    async fn test_fetch_claims_no_more_than_daily_quota() {
+        use EmailMessageStatus::Created;
+        let db = crate::config::init_test_db().await;
+        for (id, topic_id, email, status, scheduled_in) in [
+            (1, "t", "a@example.com", Created, "-1 minute"),
+            (2, "t", "b@example.com", Created, "-1 minute"),
+            (3, "t", "c@example.com", Created, "-1 minute"),
+        ] {
+            seed_scheduled_request(&db, id, topic_id, email, status, scheduled_in).await;
+        }
+        let (tx, _rx) = tokio::sync::mpsc::channel(10);
+        let tx = SendQueue::from(tx);
+
+        let quota = DailyQuota::new(2, 0);
+        assert_eq!(fetch_and_process_batch(&tx, &db, &quota).await.unwrap(), 2);
+
+        // The claimed requests count once the window is recounted
+        quota.refresh(&db).await.unwrap();
+        assert_eq!(quota.remaining(), 0);
+        assert_eq!(fetch_and_process_batch(&tx, &db, &quota).await.unwrap(), 0);
+
+        sqlx::query("UPDATE email_requests SET updated_at = datetime('now', '-25 hours')")
+            .execute(&db)
+            .await
+            .unwrap();
+        quota.refresh(&db).await.unwrap();
+        assert_eq!(fetch_and_process_batch(&tx, &db, &quota).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_takes_turns_between_topics() {
+        use EmailMessageStatus::Created;
+        let db = crate::config::init_test_db().await;
+        for (id, topic_id, email, status, scheduled_in) in [
+            (1, "big", "a1@example.com", Created, "-5 minutes"),
+            (2, "big", "a2@example.com", Created, "-4 minutes"),
+            (3, "big", "a3@example.com", Created, "-3 minutes"),
+            (4, "small", "b1@example.com", Created, "-1 minute"),
+            (5, "small", "b2@example.com", Created, "+1 hour"),
+            (6, "big", "otp@example.com", Created, "-1 minute"),
+        ] {
+            seed_scheduled_request(&db, id, topic_id, email, status, scheduled_in).await;
+        }
+        sqlx::query("UPDATE email_requests SET priority = 0 WHERE id = 6")
+            .execute(&db)
+            .await
+            .unwrap();
+        let (tx, mut rx) = tokio::sync::mpsc::channel(10);
+        let tx = SendQueue::from(tx);
+
+        let quota = DailyQuota::new(4, 0);
+        assert_eq!(fetch_and_process_batch(&tx, &db, &quota).await.unwrap(), 4);
+
+        let mut claimed: Vec<i32> = std::iter::from_fn(|| rx.try_recv().ok())
+            .filter_map(|r| r.id)
+            .collect();
+        claimed.sort_unstable();
+        // Transactional first, then the head of each bulk topic, then the next turn
+        assert_eq!(claimed, vec![1, 2, 4, 6]);
+    }
+
+    #[tokio::test]
+    async fn test_claim_query_uses_topic_index() {
+        let db = crate::config::init_test_db().await;
+        let plan: Vec<(i64, i64, i64, String)> =
+            sqlx::query_as(&format!("EXPLAIN QUERY PLAN {CLAIM_SQL}"))
+                .bind(EmailMessageStatus::Processed as i32)
+                .bind(EmailMessageStatus::Created as i32)
+                .bind(BATCH_SIZE)
+                .bind(Priority::Transactional as i32)
+                .bind(Priority::Bulk as i32)
+                .fetch_all(&db)
+                .await
+                .unwrap();
+        let details: Vec<&str> = plan.iter().map(|(.., d)| d.as_str()).collect();
+
+        assert!(
+            details
+                .iter()
+                .any(|d| d.contains("idx_requests_status_priority_topic_scheduled")),
+            "{details:?}"
+        );
+        // No full scan of the queue
+        assert!(!details.contains(&"SCAN email_requests"), "{details:?}");
+    }
+
+    #[tokio::test]
+    async fn test_fetch_skips_suppressed_recipients() {
+        use EmailMessageStatus::Created;
+        let db = crate::config::init_test_db().await;
+        for (id, topic_id, email, status, scheduled_in) in [
+            (1, "t", "Blocked@example.com", Created, "-1 minute"),
+            (2, "t", "ok@example.com", Created, "-1 minute"),
+        ] {
+            seed_scheduled_request(&db, id, topic_id, email, status, scheduled_in).await;
+        }
+        Suppression::add(
+            &db,
+            "blocked@example.com",
+            crate::models::suppression::SuppressionReason::Bounce,
+            None,
+        )
+        .await
+        .unwrap();
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(10);
+        let tx = SendQueue::from(tx);
+        assert_eq!(
+            fetch_and_process_batch(&tx, &db, &DailyQuota::new(0, 0))
+                .await
+                .unwrap(),
+            2
+        );
+
+        let sent = rx.try_recv().unwrap();
+        assert_eq!(sent.email, "ok@example.com");
+        assert!(rx.try_recv().is_err());
+
+        let (status,): (i32,) = sqlx::query_as("SELECT status FROM email_requests WHERE id = 1")
+            .fetch_one(&db)
+            .await
+            .unwrap();
+        assert_eq!(status, EmailMessageStatus::Skipped as i32);
+    }
+
+    #[tokio::test]
+    async fn test_materialize_recurring_schedules() {
+        let db = crate::config::init_test_db().await;
+        seed_content(&db).await;
+        sqlx::query(
+            r#"INSERT INTO recurring_schedules (id, name, cron, timezone, topic_id, content_id, recipients, next_run_at)
+             VALUES (1, 'weekly', '0 9 * * MON', 'Asia/Seoul', 'news', 1,
+                     '[{"email":"a@example.com"},{"email":"b@example.com"}]', '2000-01-03 00:00:00');"#,
+        )
+        .execute(&db)
+        .await
+        .unwrap();
+
+        assert_eq!(materialize_recurring_schedules(&db).await.unwrap(), 2);
+        assert_eq!(materialize_recurring_schedules(&db).await.unwrap(), 0);
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(10);
+        let tx = SendQueue::from(tx);
+        assert_eq!(
+            fetch_and_process_batch(&tx, &db, &DailyQuota::new(0, 0))
+                .await
+                .unwrap(),
+            2
+        );
+        let sent = rx.try_recv().unwrap();
+        assert_eq!(sent.topic_id.as_deref(), Some("news-200001030900"));
+
+        let recurring = RecurringSchedule::get(&db, 1).await.unwrap().unwrap();
+        let next = recurring
+            .next_run_at
+            .as_deref()
+            .and_then(schedule::from_storage);
+        assert!(next.is_some_and(|t| t > Utc::now()));
+    }
+
+    #[tokio::test]
+    async fn test_closed_send_window_is_deferred() {
+        let db = crate::config::init_test_db().await;
+        seed_content(&db).await;
+        sqlx::query(
+            "INSERT INTO email_requests (topic_id, content_id, email, scheduled_at, status, send_window, timezone, send_window_ends_at)
+             VALUES ('t', 1, 'a@example.com', '2000-01-01 00:00:00', ?, '09:00-18:00', 'Asia/Seoul', '2000-01-01 09:00:00')",
+        )
+        .bind(EmailMessageStatus::Created as i32)
+        .execute(&db)
+        .await
+        .unwrap();
+
+        // The window closed before the request was claimed
+        let (tx, mut rx) = tokio::sync::mpsc::channel(10);
+        let tx = SendQueue::from(tx);
+        assert_eq!(
+            fetch_and_process_batch(&tx, &db, &DailyQuota::new(0, 0))
+                .await
+                .unwrap(),
+            0
+        );
+        assert_eq!(defer_closed_windows(&db).await.unwrap(), 1);
+
+        let (scheduled_at, ends_at): (String, String) =
+            sqlx::query_as("SELECT scheduled_at, send_window_ends_at FROM email_requests")
+                .fetch_one(&db)
+                .await
+                .unwrap();
+        let open = schedule::from_storage(&scheduled_at).unwrap();
+        let close = schedule::from_storage(&ends_at).unwrap();
+        let seoul = chrono_tz::Asia::Seoul;
+        assert!(close > Utc::now());
+        assert_eq!(
+            close.with_timezone(&seoul).format("%H:%M").to_string(),
+            "18:00"
+        );
+        assert!(open < close);
+        assert!(close - open <= chrono::Duration::hours(9));
+        assert!(rx.try_recv().is_err());
+        assert_eq!(defer_closed_windows(&db).await.unwrap(), 0);
+    }
+
     #[test]
     fn test_constants() {
         assert_eq!(BATCH_SIZE, 1000);
@@ -1,19 +1,35 @@
-//! AWS SES email sending service with retry logic
+//! AWS SES email sending service with retry logic and account send quota
 
-use std::time::Duration;
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
 
 use aws_config::{meta::region::RegionProviderChain, BehaviorVersion};
 use aws_sdk_sesv2::{
     config::Region,
     error::SdkError,
-    types::{Body, Content, Destination, EmailContent, Message},
+    primitives::Blob,
+    types::{
+        Body, Content, Destination, EmailContent, Message, MessageHeader, MessageTag, RawMessage,
+    },
     Client,
 };
 use thiserror::Error;
 use tokio::sync::OnceCell;
 use tracing::warn;
 
-use crate::config::APP_CONFIG;
+use crate::{
+    config::APP_CONFIG,
+    models::{attachment::Attachment, content::MessageOptions},
+    services::mime,
+};
+
+/// Tag names attached to every send (returned in SES event `mail.tags`).
+pub const TOPIC_ID_TAG: &str = "topic_id";
+pub const REQUEST_ID_TAG: &str = "request_id";
+/// SES limit for tag values.
+const MAX_TAG_VALUE_LENGTH: usize = 256;
 
 // Retry configuration
 const MAX_RETRIES: u32 = 3;
@@ -21,6 +37,9 @@ const INITIAL_BACKOFF_MS: u64 = 100;
 
 static SES_CLIENT: OnceCell<Client> = OnceCell::const_new();
 
+/// Sends rejected by SES with a throttling (429) error since startup.
+static THROTTLED: AtomicU64 = AtomicU64::new(0);
+
 async fn get_ses_client() -> &'static Client {
     SES_CLIENT
         .get_or_init(|| async {
@@ -30,10 +49,12 @@ async fn get_ses_client() -> &'static Client {
                 .or_default_provider()
                 .or_else(Region::new(region.clone()));
 
-            let config = aws_config::defaults(BehaviorVersion::latest())
-                .region(region_provider)
-                .load()
-                .await;
+            let mut loader =
+                aws_config::defaults(BehaviorVersion::latest()).region(region_provider);
+            if let Some(url) = &APP_CONFIG.aws_ses_endpoint_url {
+                loader = loader.endpoint_url(url);
+            }
+            let config = loader.load().await;
 
             Client::new(&config)
         })
@@ -53,59 +74,189 @@ pub enum SendEmailError {
     MaxRetriesExceeded(String),
 }
 
-/// Checks if an SES error is retryable (throttling, transient network issues).
-fn is_retryable_error<E: std::fmt::Debug>(err: &SdkError<E>) -> bool {
+/// Checks if SES rejected a call for exceeding the account send rate.
+fn is_throttling_error<E: std::fmt::Debug>(err: &SdkError<E>) -> bool {
     matches!(
         err,
         SdkError::ServiceError(e) if e.raw().status().as_u16() == 429
-    ) || matches!(
-        err,
-        SdkError::TimeoutError(_) | SdkError::DispatchFailure(_)
     )
 }
 
+/// Checks if an SES error is retryable (throttling, transient network issues).
+fn is_retryable_error<E: std::fmt::Debug>(err: &SdkError<E>) -> bool {
+    is_throttling_error(err)
+        || matches!(
+            err,
+            SdkError::TimeoutError(_) | SdkError::DispatchFailure(_)
+        )
+}
+
+/// Number of throttling (429) errors returned by SES since startup.
+pub fn throttled_count() -> u64 {
+    THROTTLED.load(Ordering::Relaxed)
+}
+
+/// SES account sending limits (`GetAccount`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SendQuota {
+    pub max_send_rate: f64,
+    pub max_24_hour_send: f64,
+    pub sent_last_24_hours: f64,
+}
+
+/// Fetches the send quota of the SES account.
+pub async fn get_send_quota() -> Result<SendQuota, SendEmailError> {
+    fetch_send_quota(get_ses_client().await).await
+}
+
+async fn fetch_send_quota(client: &Client) -> Result<SendQuota, SendEmailError> {
+    let account = client
+        .get_account()
+        .send()
+        .await
+        .map_err(|e| SendEmailError::Sdk(format!("{e:?}")))?;
+    let quota = account
+        .send_quota()
+        .ok_or_else(|| SendEmailError::Sdk("GetAccount returned no send quota".to_string()))?;
+    Ok(SendQuota {
+        max_send_rate: quota.max_send_rate(),
+        max_24_hour_send: quota.max24_hour_send(),
+        sent_last_24_hours: quota.sent_last24_hours(),
+    })
+}
+
+/// Fully rendered email for a single recipient.
+pub struct OutgoingEmail<'a> {
+    pub from: &'a str,
+    pub to: &'a str,
+    pub subject: &'a str,
+    pub html: &'a str,
+    pub text: &'a str,
+    pub attachments: &'a [Attachment],
+    pub options: &'a MessageOptions,
+    /// Per-recipient unsubscribe URL (adds RFC 8058 `List-Unsubscribe` headers).
+    pub unsubscribe_url: Option<&'a str>,
+    /// Attached as SES message tags to correlate events without a `message_id` lookup.
+    pub topic_id: Option<&'a str>,
+    pub request_id: Option<i32>,
+}
+
+impl OutgoingEmail<'_> {
+    /// Custom headers of the message followed by the `List-Unsubscribe` pair.
+    pub fn headers(&self) -> Vec<(&str, String)> {
+        let mut headers: Vec<(&str, String)> = self
+            .options
+            .headers
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.clone()))
+            .collect();
+        if let Some(url) = self.unsubscribe_url {
+            headers.push(("List-Unsubscribe", format!("<{url}>")));
+            headers.push((
+                "List-Unsubscribe-Post",
+                "List-Unsubscribe=One-Click".to_string(),
+            ));
+        }
+        headers
+    }
+
+    /// SES message tags (`topic_id`, `request_id`).
+    ///
+    /// Tag values only allow ASCII letters, digits, `_` and `-`, so other
+    /// characters of the topic ID are replaced with `_`.
+    pub fn tags(&self) -> Vec<(&'static str, String)> {
+        let topic_id = self
+            .topic_id
+            .filter(|t| !t.is_empty())
+            .map(|t| (TOPIC_ID_TAG, tag_value(t)));
+        let request_id = self.request_id.map(|id| (REQUEST_ID_TAG, id.to_string()));
+        topic_id.into_iter().chain(request_id).collect()
+    }
+
+    /// Configuration set of the message, falling back to `AWS_SES_CONFIGURATION_SET`.
+    pub fn configuration_set(&self) -> Option<&str> {
+        self.options
+            .configuration_set
+            .as_deref()
+            .or(APP_CONFIG.aws_ses_configuration_set.as_deref())
+    }
+}
+
+fn tag_value(value: &str) -> String {
+    value
+        .chars()
+        .take(MAX_TAG_VALUE_LENGTH)
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '_' | '-') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
 /// Sends an email via AWS SES with exponential backoff retry.
 ///
+/// `html` and `text` are sent as a multipart/alternative body. Emails with
+/// attachments are built as a full MIME message and sent as raw content.
 /// Returns the SES message ID on success.
-pub async fn send_email(
-    sender: &str,
-    recipient: &str,
-    subject: &str,
-    body: &str,
-) -> Result<String, SendEmailError> {
-    let client = get_ses_client().await;
-
-    let subject_content = Content::builder()
-        .data(subject)
-        .charset("UTF-8")
-        .build()
-        .map_err(|e| SendEmailError::Build(format!("subject: {e:?}")))?;
+pub async fn send_email(email: &OutgoingEmail<'_>) -> Result<String, SendEmailError> {
+    send_email_with(get_ses_client().await, email).await
+}
 
-    let body_content = Content::builder()
-        .data(body)
-        .charset("UTF-8")
-        .build()
-        .map_err(|e| SendEmailError::Build(format!("body: {e:?}")))?;
+async fn send_email_with(
+    client: &Client,
+    email: &OutgoingEmail<'_>,
+) -> Result<String, SendEmailError> {
+    let email_content = if email.attachments.is_empty() {
+        EmailContent::builder()
+            .simple(build_simple_message(email)?)
+            .build()
+    } else {
+        let raw = RawMessage::builder()
+            .data(Blob::new(mime::build_message(email)))
+            .build()
+            .map_err(|e| SendEmailError::Build(format!("raw: {e:?}")))?;
+        EmailContent::builder().raw(raw).build()
+    };
 
-    let message = Message::builder()
-        .subject(subject_content)
-        .body(Body::builder().html(body_content).build())
+    let destination = Destination::builder()
+        .to_addresses(email.to)
+        .set_cc_addresses(non_empty(&email.options.cc))
+        .set_bcc_addresses(non_empty(&email.options.bcc))
         .build();
 
-    let email_content = EmailContent::builder().simple(message).build();
-    let destination = Destination::builder().to_addresses(recipient).build();
+    let tags = email
+        .tags()
+        .into_iter()
+        .map(|(name, value)| {
+            MessageTag::builder()
+                .name(name)
+                .value(value)
+                .build()
+                .map_err(|e| SendEmailError::Build(format!("tag {name}: {e:?}")))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
 
     let mut attempts = 0;
 
     loop {
-        match client
+        let result = client
             .send_email()
-            .from_email_address(sender)
+            .from_email_address(email.from)
             .destination(destination.clone())
+            .set_reply_to_addresses(non_empty(&email.options.reply_to))
             .content(email_content.clone())
+            .set_configuration_set_name(email.configuration_set().map(str::to_string))
+            .set_email_tags((!tags.is_empty()).then(|| tags.clone()))
             .send()
-            .await
-        {
+            .await;
+        if result.as_ref().is_err_and(is_throttling_error) {
+            THROTTLED.fetch_add(1, Ordering::Relaxed);
+        }
+
+        match result {
             Ok(resp) => {
                 return Ok(resp.message_id().unwrap_or_default().to_string());
             }
@@ -114,7 +265,7 @@ pub async fn send_email(
                 let backoff = Duration::from_millis(INITIAL_BACKOFF_MS * 2_u64.pow(attempts));
                 warn!(
                     "SES retry {}/{} for {}: {:?}, waiting {:?}",
-                    attempts, MAX_RETRIES, recipient, e, backoff
+                    attempts, MAX_RETRIES, email.to, e, backoff
                 );
                 tokio::time::sleep(backoff).await;
             }
@@ -125,10 +276,126 @@ pub async fn send_email(
     }
 }
 
+fn non_empty(addresses: &[String]) -> Option<Vec<String>> {
+    (!addresses.is_empty()).then(|| addresses.to_vec())
+}
+
+/// Builds a simple (SES-assembled) message with HTML and text bodies.
+fn build_simple_message(email: &OutgoingEmail<'_>) -> Result<Message, SendEmailError> {
+    let subject_content = Content::builder()
+        .data(email.subject)
+        .charset("UTF-8")
+        .build()
+        .map_err(|e| SendEmailError::Build(format!("subject: {e:?}")))?;
+
+    let html_content = Content::builder()
+        .data(email.html)
+        .charset("UTF-8")
+        .build()
+        .map_err(|e| SendEmailError::Build(format!("html: {e:?}")))?;
+
+    let text_content = Content::builder()
+        .data(email.text)
+        .charset("UTF-8")
+        .build()
+        .map_err(|e| SendEmailError::Build(format!("text: {e:?}")))?;
+
+    let headers = email
+        .headers()
+        .into_iter()
+        .map(|(name, value)| {
+            MessageHeader::builder()
+                .name(name)
+                .value(value)
+                .build()
+                .map_err(|e| SendEmailError::Build(format!("header {name}: {e:?}")))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Message::builder()
+        .subject(subject_content)
+        .body(
+            Body::builder()
+                .html(html_content)
+                .text(text_content)
+                .build(),
+        )
+        .set_headers((!headers.is_empty()).then_some(headers))
+        .build())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_outgoing_email_headers() {
+        let options = MessageOptions {
+            headers: [("X-Campaign".to_string(), "spring".to_string())].into(),
+            ..Default::default()
+        };
+        let mut email = OutgoingEmail {
+            from: "a@example.com",
+            to: "b@example.com",
+            subject: "s",
+            html: "h",
+            text: "t",
+            attachments: &[],
+            options: &options,
+            unsubscribe_url: None,
+            topic_id: None,
+            request_id: None,
+        };
+        assert_eq!(email.headers(), vec![("X-Campaign", "spring".to_string())]);
+
+        email.unsubscribe_url = Some("https://mail.example.com/v1/unsubscribe?request_id=1");
+        assert_eq!(
+            email.headers()[1..],
+            [
+                (
+                    "List-Unsubscribe",
+                    "<https://mail.example.com/v1/unsubscribe?request_id=1>".to_string()
+                ),
+                (
+                    "List-Unsubscribe-Post",
+                    "List-Unsubscribe=One-Click".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_outgoing_email_tags_and_configuration_set() {
+        let options = MessageOptions {
+            configuration_set: Some("transactional".to_string()),
+            ..Default::default()
+        };
+        let mut email = OutgoingEmail {
+            from: "a@example.com",
+            to: "b@example.com",
+            subject: "s",
+            html: "h",
+            text: "t",
+            attachments: &[],
+            options: &options,
+            unsubscribe_url: None,
+            topic_id: Some("newsletter 2024/01"),
+            request_id: Some(42),
+        };
+        assert_eq!(
+            email.tags(),
+            vec![
+                (TOPIC_ID_TAG, "newsletter_2024_01".to_string()),
+                (REQUEST_ID_TAG, "42".to_string()),
+            ]
+        );
+        assert_eq!(email.configuration_set(), Some("transactional"));
+
+        email.topic_id = Some("");
+        email.request_id = None;
+        assert!(email.tags().is_empty());
+    }
+
     #[test]
     fn test_send_email_error_display() {
         let err = SendEmailError::Build("test".to_string());
@@ -146,4 +413,96 @@ mod tests {
         assert_eq!(MAX_RETRIES, 3);
         assert_eq!(INITIAL_BACKOFF_MS, 100);
     }
+
+    /// Local SES stand-in: `GetAccount` returns a quota and the first
+    /// `SendEmail` call is throttled.
+    async fn ses_stand_in() -> Client {
+        use std::sync::{atomic::AtomicBool, Arc};
+
+        use axum::{
+            http::StatusCode,
+            routing::{get, post},
+            Json, Router,
+        };
+
+        let throttled = Arc::new(AtomicBool::new(false));
+        let app = Router::new()
+            .route(
+                "/v2/email/account",
+                get(|| async {
+                    Json(serde_json::json!({
+                        "SendQuota": {
+                            "Max24HourSend": 50000.0,
+                            "MaxSendRate": 14.0,
+                            "SentLast24Hours": 1200.0
+                        }
+                    }))
+                }),
+            )
+            .route(
+                "/v2/email/outbound-emails",
+                post(move || async move {
+                    if throttled.swap(true, Ordering::Relaxed) {
+                        (
+                            StatusCode::OK,
+                            Json(serde_json::json!({"MessageId": "m-1"})),
+                        )
+                    } else {
+                        (
+                            StatusCode::TOO_MANY_REQUESTS,
+                            Json(serde_json::json!({"message": "Maximum sending rate exceeded."})),
+                        )
+                    }
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let config = aws_sdk_sesv2::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new("us-east-1"))
+            .credentials_provider(aws_sdk_sesv2::config::Credentials::new(
+                "test", "test", None, None, "test",
+            ))
+            .retry_config(aws_sdk_sesv2::config::retry::RetryConfig::disabled())
+            .endpoint_url(url)
+            .build();
+        Client::from_conf(config)
+    }
+
+    #[tokio::test]
+    async fn test_fetch_send_quota() {
+        let client = ses_stand_in().await;
+        assert_eq!(
+            fetch_send_quota(&client).await.unwrap(),
+            SendQuota {
+                max_send_rate: 14.0,
+                max_24_hour_send: 50000.0,
+                sent_last_24_hours: 1200.0,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_throttled_send_is_counted_and_retried() {
+        let client = ses_stand_in().await;
+        let options = MessageOptions::default();
+        let email = OutgoingEmail {
+            from: "a@example.com",
+            to: "b@example.com",
+            subject: "s",
+            html: "h",
+            text: "t",
+            attachments: &[],
+            options: &options,
+            unsubscribe_url: None,
+            topic_id: None,
+            request_id: None,
+        };
+
+        let before = throttled_count();
+        assert_eq!(send_email_with(&client, &email).await.unwrap(), "m-1");
+        assert!(throttled_count() > before);
+    }
 }
@@ -0,0 +1,301 @@
+//! Click tracking and unsubscribe links (HMAC-signed per request)
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use url::{form_urlencoded, Url};
+
+use crate::services::renderer::decode_entities;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Links with this attribute (e.g. `<a href="..." data-no-track>`) are not rewritten.
+pub const NO_TRACK_ATTRIBUTE: &str = "data-no-track";
+
+/// Signs a redirect target for a request.
+pub fn sign(secret: &str, request_id: i32, url: &str) -> String {
+    URL_SAFE_NO_PAD.encode(
+        mac(secret, b"click", request_id, url)
+            .finalize()
+            .into_bytes(),
+    )
+}
+
+/// Verifies a signature created by [`sign`] (constant-time comparison).
+pub fn verify(secret: &str, request_id: i32, url: &str, signature: &str) -> bool {
+    verify_mac(mac(secret, b"click", request_id, url), signature)
+}
+
+/// Generates the unsubscribe token of a recipient (one per request).
+pub fn unsubscribe_token(secret: &str, request_id: i32) -> String {
+    URL_SAFE_NO_PAD.encode(
+        mac(secret, b"unsubscribe", request_id, "")
+            .finalize()
+            .into_bytes(),
+    )
+}
+
+/// Verifies a token created by [`unsubscribe_token`].
+pub fn verify_unsubscribe_token(secret: &str, request_id: i32, token: &str) -> bool {
+    verify_mac(mac(secret, b"unsubscribe", request_id, ""), token)
+}
+
+/// Builds the `List-Unsubscribe` URL of a recipient.
+pub fn unsubscribe_url(server_url: &str, secret: &str, request_id: i32) -> String {
+    format!(
+        "{server_url}/v1/unsubscribe?request_id={request_id}&token={}",
+        unsubscribe_token(secret, request_id)
+    )
+}
+
+/// Keyed by purpose so a click signature can never be used as an unsubscribe token.
+fn mac(secret: &str, purpose: &[u8], request_id: i32, data: &str) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes())
+        .unwrap_or_else(|_| unreachable!("HMAC accepts keys of any length"));
+    mac.update(purpose);
+    mac.update(b"\n");
+    mac.update(request_id.to_string().as_bytes());
+    mac.update(b"\n");
+    mac.update(data.as_bytes());
+    mac
+}
+
+fn verify_mac(mac: HmacSha256, signature: &str) -> bool {
+    URL_SAFE_NO_PAD
+        .decode(signature)
+        .is_ok_and(|sig| mac.verify_slice(&sig).is_ok())
+}
+
+/// Parses a redirect target, accepting only absolute `http`/`https` URLs.
+pub fn parse_target(url: &str) -> Option<Url> {
+    Url::parse(url)
+        .ok()
+        .filter(|u| matches!(u.scheme(), "http" | "https"))
+}
+
+/// Builds the tracking URL that records a click and redirects to `url`.
+pub fn click_url(server_url: &str, secret: &str, request_id: i32, url: &str) -> String {
+    let encoded: String = form_urlencoded::byte_serialize(url.as_bytes()).collect();
+    format!(
+        "{server_url}/v1/events/click?request_id={request_id}&url={encoded}&sig={}",
+        sign(secret, request_id, url)
+    )
+}
+
+/// Rewrites every trackable `<a href>` in an HTML body to its click tracking URL.
+///
+/// Skipped: links marked with [`NO_TRACK_ATTRIBUTE`], non-`http(s)` links
+/// (`mailto:`, `#anchor`, ...) and links that already point to this server.
+pub fn rewrite_links(html: &str, server_url: &str, secret: &str, request_id: i32) -> String {
+    let mut out = String::with_capacity(html.len() + html.len() / 4);
+    let mut rest = html;
+
+    while let Some(start) = find_anchor_start(rest) {
+        out.push_str(&rest[..start]);
+        let tag_and_rest = &rest[start..];
+        let Some(end) = find_tag_end(tag_and_rest) else {
+            out.push_str(tag_and_rest);
+            return out;
+        };
+
+        let tag = &tag_and_rest[..=end];
+        match tracked_href(tag, server_url) {
+            Some((span_start, span_end, url)) => {
+                let tracked = click_url(server_url, secret, request_id, &url);
+                out.push_str(&tag[..span_start]);
+                out.push('"');
+                out.push_str(&tracked.replace('&', "&amp;"));
+                out.push('"');
+                out.push_str(&tag[span_end..]);
+            }
+            None => out.push_str(tag),
+        }
+        rest = &tag_and_rest[end + 1..];
+    }
+
+    out.push_str(rest);
+    out
+}
+
+/// Finds the next `<a` tag opening (case-insensitive, followed by whitespace).
+fn find_anchor_start(html: &str) -> Option<usize> {
+    let bytes = html.as_bytes();
+    html.match_indices('<').map(|(i, _)| i).find(|&i| {
+        bytes
+            .get(i + 1)
+            .is_some_and(|b| b.eq_ignore_ascii_case(&b'a'))
+            && bytes.get(i + 2).is_some_and(u8::is_ascii_whitespace)
+    })
+}
+
+/// Index of the `>` closing a tag, ignoring `>` inside quoted attribute values.
+fn find_tag_end(tag: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, b) in tag.bytes().enumerate() {
+        match (quote, b) {
+            (None, b'"' | b'\'') => quote = Some(b),
+            (Some(q), _) if q == b => quote = None,
+            (None, b'>') => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Returns the byte span of the `href` value (including quotes) and the decoded
+/// URL when the link should be tracked.
+fn tracked_href(tag: &str, server_url: &str) -> Option<(usize, usize, String)> {
+    let bytes = tag.as_bytes();
+    // Skip "<a"
+    let mut i = 2;
+    let mut href = None;
+
+    while i < bytes.len() {
+        while i < bytes.len() && (bytes[i].is_ascii_whitespace() || bytes[i] == b'/') {
+            i += 1;
+        }
+        if i >= bytes.len() || bytes[i] == b'>' {
+            break;
+        }
+
+        let name_start = i;
+        while i < bytes.len()
+            && !matches!(bytes[i], b'=' | b'>' | b'/')
+            && !bytes[i].is_ascii_whitespace()
+        {
+            i += 1;
+        }
+        let name = tag[name_start..i].to_ascii_lowercase();
+
+        while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        if bytes.get(i) != Some(&b'=') {
+            if name == NO_TRACK_ATTRIBUTE {
+                return None;
+            }
+            continue;
+        }
+        i += 1;
+        while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+
+        let value_start = i;
+        let (raw, value_end) = if let Some(&q @ (b'"' | b'\'')) = bytes.get(i) {
+            let close = tag[i + 1..]
+                .find(q as char)
+                .map_or(bytes.len(), |p| i + 1 + p);
+            (&tag[i + 1..close], (close + 1).min(bytes.len()))
+        } else {
+            while i < bytes.len() && bytes[i] != b'>' && !bytes[i].is_ascii_whitespace() {
+                i += 1;
+            }
+            (&tag[value_start..i], i)
+        };
+        i = value_end;
+
+        match name.as_str() {
+            NO_TRACK_ATTRIBUTE => return None,
+            "href" if href.is_none() => href = Some((value_start, value_end, raw)),
+            _ => {}
+        }
+    }
+
+    let (start, end, raw) = href?;
+    let url = decode_entities(raw.trim());
+    let is_own_link = !server_url.is_empty() && url.starts_with(server_url);
+    (parse_target(&url).is_some() && !is_own_link).then_some((start, end, url))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERVER: &str = "https://mail.example.com";
+    const SECRET: &str = "secret";
+
+    #[test]
+    fn test_sign_and_verify() {
+        let sig = sign(SECRET, 7, "https://shop.example.com/a?b=1");
+        assert!(verify(SECRET, 7, "https://shop.example.com/a?b=1", &sig));
+        assert!(!verify(SECRET, 8, "https://shop.example.com/a?b=1", &sig));
+        assert!(!verify(SECRET, 7, "https://evil.example.com", &sig));
+        assert!(!verify("other", 7, "https://shop.example.com/a?b=1", &sig));
+        assert!(!verify(
+            SECRET,
+            7,
+            "https://shop.example.com/a?b=1",
+            "not-base64!"
+        ));
+    }
+
+    #[test]
+    fn test_unsubscribe_token() {
+        let token = unsubscribe_token(SECRET, 7);
+        assert!(verify_unsubscribe_token(SECRET, 7, &token));
+        assert!(!verify_unsubscribe_token(SECRET, 8, &token));
+        assert!(!verify_unsubscribe_token("other", 7, &token));
+        // Click signatures are not valid unsubscribe tokens
+        assert!(!verify_unsubscribe_token(SECRET, 7, &sign(SECRET, 7, "")));
+
+        assert_eq!(
+            unsubscribe_url(SERVER, SECRET, 7),
+            format!("{SERVER}/v1/unsubscribe?request_id=7&token={token}")
+        );
+    }
+
+    #[test]
+    fn test_parse_target_only_http() {
+        assert!(parse_target("https://example.com/x").is_some());
+        assert!(parse_target("http://example.com").is_some());
+        assert!(parse_target("javascript:alert(1)").is_none());
+        assert!(parse_target("mailto:a@example.com").is_none());
+        assert!(parse_target("/relative").is_none());
+    }
+
+    #[test]
+    fn test_rewrite_links() {
+        let html = r#"<p><a href="https://shop.example.com/a?x=1&amp;y=2" class="btn">Buy</a></p>"#;
+        let result = rewrite_links(html, SERVER, SECRET, 42);
+
+        let target = "https://shop.example.com/a?x=1&y=2";
+        let expected_url = click_url(SERVER, SECRET, 42, target).replace('&', "&amp;");
+        assert_eq!(
+            result,
+            format!(r#"<p><a href="{expected_url}" class="btn">Buy</a></p>"#)
+        );
+        assert!(result.contains("url=https%3A%2F%2Fshop.example.com%2Fa%3Fx%3D1%26y%3D2"));
+    }
+
+    #[test]
+    fn test_rewrite_links_skips_untrackable() {
+        let html = concat!(
+            r#"<a href="mailto:help@example.com">Mail</a>"#,
+            r##"<a href="#top">Top</a>"##,
+            r#"<a data-no-track href="https://example.com/private">Private</a>"#,
+            r#"<a href='https://example.com/x' data-no-track="true">Private</a>"#,
+            r#"<a href="https://mail.example.com/v1/unsubscribe">Own</a>"#,
+            r#"<abbr title="x">abbr</abbr><img src="https://example.com/i.png">"#,
+        );
+        assert_eq!(rewrite_links(html, SERVER, SECRET, 1), html);
+    }
+
+    #[test]
+    fn test_rewrite_links_unquoted_and_uppercase() {
+        let html = "<A HREF=https://example.com/x>x</A>";
+        let result = rewrite_links(html, SERVER, SECRET, 3);
+        assert!(result.starts_with(
+            "<A HREF=\"https://mail.example.com/v1/events/click?request_id=3&amp;url="
+        ));
+        assert!(result.ends_with("\">x</A>"));
+    }
+
+    #[test]
+    fn test_rewrite_links_quoted_gt_in_attribute() {
+        let html = r#"<a title="a > b" href="https://example.com">x</a>"#;
+        let result = rewrite_links(html, SERVER, SECRET, 1);
+        assert!(result
+            .starts_with(r#"<a title="a > b" href="https://mail.example.com/v1/events/click?"#));
+    }
+}
@@ -1,24 +1,49 @@
 //! 애플리케이션 상태 모듈.
 
+use std::sync::{atomic::AtomicU64, Arc};
+
 use sqlx::SqlitePool;
-use tokio::sync::mpsc;
 
-use crate::models::request::EmailRequest;
+use crate::{
+    config::APP_CONFIG,
+    services::{
+        limiter::RateLimiter,
+        queue::{CancelledTopics, SendQueue},
+        quota::DailyQuota,
+    },
+};
 
 /// Shared application state accessible via Axum's State extractor.
 #[derive(Clone)]
 pub struct AppState {
     /// `SQLite` connection pool
     pub db_pool: SqlitePool,
-    /// Channel sender for email requests
-    pub tx: mpsc::Sender<EmailRequest>,
+    /// Priority lanes to the email sender
+    pub tx: SendQueue,
+    /// Per-topic and per-sender rate limits, shared with the email sender
+    pub rate_limiter: Arc<RateLimiter>,
+    /// Rolling 24-hour send quota, shared with the scheduler and the email sender
+    pub daily_quota: Arc<DailyQuota>,
+    /// Stopped topics whose queued emails the email sender drops
+    pub cancelled_topics: Arc<CancelledTopics>,
+    /// Current global send rate (emails/sec), kept up to date by the email sender
+    pub send_rate: Arc<AtomicU64>,
 }
 
 impl AppState {
     /// Creates a new `AppState` instance.
     #[must_use]
-    pub const fn new(db_pool: SqlitePool, tx: mpsc::Sender<EmailRequest>) -> Self {
-        Self { db_pool, tx }
+    pub fn new(db_pool: SqlitePool, tx: impl Into<SendQueue>) -> Self {
+        Self {
+            db_pool,
+            tx: tx.into(),
+            rate_limiter: Arc::default(),
+            daily_quota: Arc::default(),
+            cancelled_topics: Arc::default(),
+            send_rate: Arc::new(AtomicU64::new(
+                u64::try_from(APP_CONFIG.max_send_per_second.max(1)).unwrap_or(1),
+            )),
+        }
     }
 }
 